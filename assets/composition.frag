@@ -1,5 +1,8 @@
 #version 460
 layout(location = 0) out vec4 outColor;
+layout(set = 0, binding = 0) uniform Fill {
+    vec4 color;
+} fill;
 void main() {
-    outColor = vec4(0.392, 0.584, 0.929, 1.0);
+    outColor = fill.color;
 }
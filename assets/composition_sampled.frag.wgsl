@@ -0,0 +1,23 @@
+// Composition from the forward pass's colour, sampled through the bindless heap. The texture
+// and sampler indices come from push constants and are the same for every fragment, as is the
+// rectangle of the target the texture is stretched over.
+
+enable wgpu_binding_array;
+
+struct Composite {
+    texture: u32,
+    sampler: u32,
+    origin: vec2<f32>,
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0) var textures: binding_array<texture_2d<f32>>;
+@group(1) @binding(0) var samplers: binding_array<sampler>;
+
+var<immediate> pc: Composite;
+
+@fragment
+fn main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = (position.xy - pc.origin) / pc.size;
+    return textureSample(textures[pc.texture], samplers[pc.sampler], uv);
+}
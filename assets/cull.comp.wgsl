@@ -0,0 +1,147 @@
+// GPU culling. One invocation per instance: instances whose bounding sphere survives the
+// frustum test, and optionally a Hi-Z occlusion test, get their draw command appended to the
+// visible list and bump the count read by vkCmdDrawIndexedIndirectCount.
+//
+// Everything is reached through the bindless heap by index. Bounds are one world-space
+// sphere per instance, xyz centre and w radius; commands are vk::DrawIndexedIndirectCommand.
+
+enable wgpu_binding_array;
+
+// Words of a storage buffer. naga rejects several variables on one binding and atomics can't
+// be mixed into a plain array, so every buffer is read and written through this one view.
+struct Words {
+    items: array<atomic<u32>>,
+}
+
+// Layout of vk::DrawIndexedIndirectCommand.
+const DRAW_WORDS: u32 = 5u;
+
+struct Cull {
+    view_proj: mat4x4<f32>,
+    instance_count: u32,
+    flags: u32,
+    bounds: u32,
+    draws: u32,
+    visible: u32,
+    count: u32,
+    // Laid out as depth_pyramid.comp.wgsl writes it.
+    pyramid: u32,
+    pyramid_size: u32,
+}
+
+const OCCLUSION: u32 = 1u;
+// The pyramid's view-projection matrix ahead of level 0.
+const PYRAMID_HEADER_WORDS: u32 = 16u;
+
+@group(0) @binding(1) var<storage, read_write> buffers: binding_array<Words>;
+
+var<immediate> pc: Cull;
+
+// Planes from the rows of the view-projection matrix (Gribb-Hartmann), with Vulkan's 0..1
+// depth range.
+fn in_frustum(center: vec3<f32>, radius: f32) -> bool {
+    let m = transpose(pc.view_proj);
+    var planes = array<vec4<f32>, 6>(
+        m[3] + m[0],
+        m[3] - m[0],
+        m[3] + m[1],
+        m[3] - m[1],
+        m[2],
+        m[3] - m[2],
+    );
+    for (var i = 0u; i < 6u; i++) {
+        let plane = planes[i] / length(planes[i].xyz);
+        if dot(plane.xyz, center) + plane.w < -radius {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn pyramid_word(index: u32) -> f32 {
+    return bitcast<f32>(atomicLoad(&buffers[pc.pyramid].items[index]));
+}
+
+fn pyramid_depth(level: u32, texel: vec2<u32>) -> f32 {
+    var offset = PYRAMID_HEADER_WORDS;
+    for (var l = 0u; l < level; l++) {
+        let side = pc.pyramid_size >> l;
+        offset += side * side;
+    }
+    return pyramid_word(offset + texel.y * (pc.pyramid_size >> level) + texel.x);
+}
+
+// Compares the sphere's nearest depth against the farthest depth the pyramid holds over its
+// screen rectangle, at the level where that rectangle spans at most two texels a side. The
+// sphere is projected with the camera the pyramid was built with, usually last frame's.
+fn unoccluded(center: vec3<f32>, radius: f32) -> bool {
+    var view_proj: mat4x4<f32>;
+    for (var i = 0u; i < 16u; i++) {
+        view_proj[i / 4u][i % 4u] = pyramid_word(i);
+    }
+
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var nearest = 1.0;
+    for (var corner = 0u; corner < 8u; corner++) {
+        let offset = vec3<f32>(
+            f32(corner & 1u),
+            f32((corner >> 1u) & 1u),
+            f32((corner >> 2u) & 1u),
+        ) * 2.0 - 1.0;
+        let clip = view_proj * vec4<f32>(center + offset * radius, 1.0);
+        // Crosses the camera plane, so there is no rectangle to test. A pyramid nothing was
+        // rendered into yet has a zero matrix and ends up here too.
+        if clip.w <= 0.0 {
+            return true;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * 0.5 + 0.5;
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest = min(nearest, ndc.z);
+    }
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    let extent = (uv_max - uv_min) * f32(pc.pyramid_size);
+    let top = countTrailingZeros(pc.pyramid_size);
+    let level = min(u32(ceil(log2(max(max(extent.x, extent.y), 1.0)))), top);
+    let side = pc.pyramid_size >> level;
+    let last = vec2<u32>(side - 1u);
+    let lo = min(vec2<u32>(uv_min * f32(side)), last);
+    let hi = min(vec2<u32>(uv_max * f32(side)), last);
+
+    let farthest = max(
+        max(pyramid_depth(level, lo), pyramid_depth(level, vec2<u32>(hi.x, lo.y))),
+        max(pyramid_depth(level, vec2<u32>(lo.x, hi.y)), pyramid_depth(level, hi)),
+    );
+    return nearest <= farthest;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= pc.instance_count {
+        return;
+    }
+
+    let sphere = vec4<f32>(
+        bitcast<f32>(atomicLoad(&buffers[pc.bounds].items[i * 4u])),
+        bitcast<f32>(atomicLoad(&buffers[pc.bounds].items[i * 4u + 1u])),
+        bitcast<f32>(atomicLoad(&buffers[pc.bounds].items[i * 4u + 2u])),
+        bitcast<f32>(atomicLoad(&buffers[pc.bounds].items[i * 4u + 3u])),
+    );
+    if !in_frustum(sphere.xyz, sphere.w) {
+        return;
+    }
+    if (pc.flags & OCCLUSION) != 0u && !unoccluded(sphere.xyz, sphere.w) {
+        return;
+    }
+
+    let slot = atomicAdd(&buffers[pc.count].items[0], 1u);
+    for (var word = 0u; word < DRAW_WORDS; word++) {
+        let value = atomicLoad(&buffers[pc.draws].items[i * DRAW_WORDS + word]);
+        atomicStore(&buffers[pc.visible].items[slot * DRAW_WORDS + word], value);
+    }
+}
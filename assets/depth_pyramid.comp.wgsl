@@ -0,0 +1,88 @@
+// Builds one level of the Hi-Z pyramid the culling pass tests against. Level 0 holds the
+// farthest depth of the forward depth buffer over each texel's footprint, every further level
+// the farthest of the 2x2 texels below it.
+//
+// The pyramid is one storage buffer: the view-projection matrix the depth was rendered with,
+// then square levels from `size` texels a side down to 1, back to back and row-major. Being
+// square and fixed-size, it doesn't depend on the swapchain's extent.
+
+enable wgpu_binding_array;
+
+// Words of a storage buffer. naga rejects several variables on one binding, so every buffer is
+// read and written through this one view.
+struct Words {
+    items: array<u32>,
+}
+
+struct Pyramid {
+    view_proj: mat4x4<f32>,
+    depth: u32,
+    pyramid: u32,
+    level: u32,
+    size: u32,
+}
+
+// The view-projection matrix ahead of level 0.
+const HEADER_WORDS: u32 = 16u;
+
+@group(0) @binding(0) var textures: binding_array<texture_2d<f32>>;
+@group(0) @binding(1) var<storage, read_write> buffers: binding_array<Words>;
+
+var<immediate> pc: Pyramid;
+
+fn level_offset(level: u32) -> u32 {
+    var offset = HEADER_WORDS;
+    for (var l = 0u; l < level; l++) {
+        let side = pc.size >> l;
+        offset += side * side;
+    }
+    return offset;
+}
+
+// Every depth texel the footprint touches, partially covered ones included, so the result is
+// never nearer than what was rendered.
+fn farthest_depth(texel: vec2<u32>, side: u32) -> f32 {
+    let depth_size = textureDimensions(textures[pc.depth]);
+    let lo = texel * depth_size / side;
+    let hi = max(((texel + 1u) * depth_size + side - 1u) / side, lo + 1u);
+    var farthest = 0.0;
+    for (var y = lo.y; y < hi.y; y++) {
+        for (var x = lo.x; x < hi.x; x++) {
+            farthest = max(farthest, textureLoad(textures[pc.depth], vec2<u32>(x, y), 0).r);
+        }
+    }
+    return farthest;
+}
+
+fn farthest_below(texel: vec2<u32>, side: u32) -> f32 {
+    let below = level_offset(pc.level - 1u);
+    let below_side = side * 2u;
+    var farthest = 0.0;
+    for (var i = 0u; i < 4u; i++) {
+        let source = texel * 2u + vec2<u32>(i & 1u, i >> 1u);
+        let word = buffers[pc.pyramid].items[below + source.y * below_side + source.x];
+        farthest = max(farthest, bitcast<f32>(word));
+    }
+    return farthest;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let side = pc.size >> pc.level;
+    if id.x >= side || id.y >= side {
+        return;
+    }
+
+    var farthest: f32;
+    if pc.level == 0u {
+        farthest = farthest_depth(id.xy, side);
+        if id.x == 0u && id.y == 0u {
+            for (var i = 0u; i < HEADER_WORDS; i++) {
+                buffers[pc.pyramid].items[i] = bitcast<u32>(pc.view_proj[i / 4u][i % 4u]);
+            }
+        }
+    } else {
+        farthest = farthest_below(id.xy, side);
+    }
+    buffers[pc.pyramid].items[level_offset(pc.level) + id.y * side + id.x] = bitcast<u32>(farthest);
+}
@@ -1,10 +1,7 @@
 #version 460
-vec2 positions[3] = vec2[](
-    vec2(-1.0, -1.0),
-    vec2( 3.0, -1.0),
-    vec2(-1.0,  3.0)
-);
+#extension GL_GOOGLE_include_directive : require
+#include "fullscreen.glsl"
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
+    gl_Position = vec4(FULLSCREEN_POSITIONS[gl_VertexIndex], 0.0, 1.0);
 }
@@ -0,0 +1,45 @@
+// Vertex pulling for ForwardPass's indirect draws. There are no vertex attributes: positions
+// come from the vertex arena and per-instance bounds from the culling pass's bounds buffer,
+// both reached through the bindless heap by index.
+//
+// Vertices are vec4 positions (w unused) in a unit cube; vertex_index already includes the
+// draw's vertexOffset and instance_index its firstInstance, so both index the buffers directly.
+
+enable wgpu_binding_array;
+
+// Words of a storage buffer. naga rejects several variables on one binding, so every buffer is
+// read through this one view.
+struct Words {
+    items: array<u32>,
+}
+
+struct Draw {
+    view_proj: mat4x4<f32>,
+    vertices: u32,
+    instances: u32,
+}
+
+@group(0) @binding(1) var<storage, read> buffers: binding_array<Words>;
+
+var<immediate> pc: Draw;
+
+fn load_vec4(buffer: u32, index: u32) -> vec4<f32> {
+    return vec4<f32>(
+        bitcast<f32>(buffers[buffer].items[index * 4u]),
+        bitcast<f32>(buffers[buffer].items[index * 4u + 1u]),
+        bitcast<f32>(buffers[buffer].items[index * 4u + 2u]),
+        bitcast<f32>(buffers[buffer].items[index * 4u + 3u]),
+    );
+}
+
+@vertex
+fn main(
+    @builtin(vertex_index) vertex: u32,
+    @builtin(instance_index) instance: u32,
+) -> @builtin(position) vec4<f32> {
+    let position = load_vec4(pc.vertices, vertex).xyz;
+    // xyz centre and w radius; the cube's corners lie on the sphere.
+    let sphere = load_vec4(pc.instances, instance);
+    let world = sphere.xyz + position * sphere.w * inverseSqrt(3.0);
+    return pc.view_proj * vec4<f32>(world, 1.0);
+}
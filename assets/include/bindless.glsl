@@ -0,0 +1,19 @@
+// The bindless heap. Indices come from TextureIndex, BufferIndex and SamplerIndex, usually
+// through push constants; wrap them in nonuniformEXT when they vary within a draw.
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(set = 0, binding = 0) uniform texture2D textures[];
+layout(set = 1, binding = 0) uniform sampler samplers[];
+
+// Storage buffers share one binding, so each element type gets its own view of it:
+//   BINDLESS_BUFFER(Instances, Instance, instances);
+//   Instance instance = instances[nonuniformEXT(index)].items[gl_InstanceIndex];
+#define BINDLESS_BUFFER(Block, Type, name) \
+    layout(set = 0, binding = 1) readonly buffer Block { Type items[]; } name[]
+
+vec4 bindless_sample(uint texture_index, uint sampler_index, vec2 uv) {
+    return texture(
+        sampler2D(textures[nonuniformEXT(texture_index)], samplers[nonuniformEXT(sampler_index)]),
+        uv
+    );
+}
@@ -0,0 +1,6 @@
+// Positions of a single triangle covering the whole viewport, indexed by gl_VertexIndex.
+const vec2 FULLSCREEN_POSITIONS[3] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2( 3.0, -1.0),
+    vec2(-1.0,  3.0)
+);
@@ -34,6 +34,15 @@ impl App {
             proxy,
         }
     }
+
+    fn stop(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(mut e) = self.engine.take()
+            && let Err(e) = e.shutdown()
+        {
+            self.app_state = AppState::FatalError(e);
+        }
+        event_loop.exit();
+    }
 }
 
 impl ApplicationHandler<AppEvent> for App {
@@ -67,16 +76,7 @@ impl ApplicationHandler<AppEvent> for App {
         match event {
             WindowEvent::CloseRequested => {
                 log::debug!("The close button was pressed; stopping");
-                if let Some(mut e) = self.engine.take() {
-                    match e.shutdown() {
-                        Ok(e) => e,
-                        Err(e) => {
-                            self.app_state = AppState::FatalError(e);
-                            return;
-                        }
-                    }
-                }
-                event_loop.exit();
+                self.stop(event_loop);
             }
             WindowEvent::RedrawRequested => {
                 self.window.as_ref().unwrap().request_redraw();
@@ -89,13 +89,15 @@ impl ApplicationHandler<AppEvent> for App {
         match event {
             AppEvent::EngineFailed => {
                 log::error!("Engine thread failed; shutting down");
-
-                if let Some(mut engine) = self.engine.take() {
-                    let _ = engine.shutdown();
-                }
-
-                event_loop.exit();
+                self.stop(event_loop);
             }
         }
     }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.engine.as_ref().is_some_and(Engine::exit_requested) {
+            log::debug!("The engine asked to exit; stopping");
+            self.stop(event_loop);
+        }
+    }
 }
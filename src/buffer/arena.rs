@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use ash::vk;
+use slotmap::SlotMap;
+
+use crate::buffer::keys::{ArenaBlockKey, ArenaKey, BufferKey};
+
+/// A sub-allocation in a device arena. Resolve it with `BufferManager::arena_block` each time
+/// it is used, since defragmenting moves it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ArenaHandle {
+    pub arena: ArenaKey,
+    block: ArenaBlockKey,
+}
+
+/// Where a sub-allocation currently sits in its arena's buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ArenaBlock {
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ArenaStats {
+    pub capacity: vk::DeviceSize,
+    pub used: vk::DeviceSize,
+    pub blocks: usize,
+    pub free_ranges: usize,
+    pub largest_free: vk::DeviceSize,
+}
+
+impl ArenaStats {
+    /// Share of the free space outside the largest free range: 0 when it is all in one piece,
+    /// approaching 1 as it splinters.
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+        if free == 0 {
+            return 0.0;
+        }
+        1.0 - self.largest_free as f32 / free as f32
+    }
+}
+
+/// First-fit free-list over one device-local buffer. Only bookkeeping lives here; the buffer
+/// itself is owned by the manager.
+pub(super) struct Arena {
+    pub buffer: BufferKey,
+    capacity: vk::DeviceSize,
+    blocks: SlotMap<ArenaBlockKey, ArenaBlock>,
+    /// Free ranges by offset. Adjacent ranges are always merged.
+    free: BTreeMap<vk::DeviceSize, vk::DeviceSize>,
+}
+
+impl Arena {
+    pub fn new(buffer: BufferKey, capacity: vk::DeviceSize) -> Self {
+        Self {
+            buffer,
+            capacity,
+            blocks: SlotMap::default(),
+            free: BTreeMap::from([(0, capacity)]),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        arena: ArenaKey,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<ArenaHandle> {
+        let (range_offset, range_size, offset) =
+            self.free.iter().find_map(|(&range_offset, &range_size)| {
+                let offset = range_offset.next_multiple_of(alignment);
+                (offset + size <= range_offset + range_size).then_some((
+                    range_offset,
+                    range_size,
+                    offset,
+                ))
+            })?;
+
+        self.free.remove(&range_offset);
+        if offset > range_offset {
+            self.free.insert(range_offset, offset - range_offset);
+        }
+        let end = offset + size;
+        if end < range_offset + range_size {
+            self.free.insert(end, range_offset + range_size - end);
+        }
+
+        let block = self.blocks.insert(ArenaBlock {
+            offset,
+            size,
+            alignment,
+        });
+        Some(ArenaHandle { arena, block })
+    }
+
+    pub fn free(&mut self, handle: ArenaHandle) {
+        let Some(block) = self.blocks.remove(handle.block) else {
+            return;
+        };
+        let mut offset = block.offset;
+        let mut size = block.size;
+
+        if let Some((&prev_offset, &prev_size)) = self.free.range(..offset).next_back()
+            && prev_offset + prev_size == offset
+        {
+            self.free.remove(&prev_offset);
+            offset = prev_offset;
+            size += prev_size;
+        }
+        if let Some(next_size) = self.free.remove(&(offset + size)) {
+            size += next_size;
+        }
+        self.free.insert(offset, size);
+    }
+
+    pub fn block(&self, handle: ArenaHandle) -> Option<ArenaBlock> {
+        self.blocks.get(handle.block).copied()
+    }
+
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            capacity: self.capacity,
+            used: self.blocks.values().map(|block| block.size).sum(),
+            blocks: self.blocks.len(),
+            free_ranges: self.free.len(),
+            largest_free: self.free.values().copied().max().unwrap_or(0),
+        }
+    }
+
+    /// Packs every block towards the start, in offset order, and returns the copies that move
+    /// the contents from the old layout into a new buffer with the packed one. Padding left
+    /// in front of aligned blocks stays free.
+    pub fn compact(&mut self) -> Vec<vk::BufferCopy> {
+        let mut blocks = self.blocks.values_mut().collect::<Vec<_>>();
+        blocks.sort_by_key(|block| block.offset);
+
+        let mut free = BTreeMap::new();
+        let mut head: vk::DeviceSize = 0;
+        let copies = blocks
+            .into_iter()
+            .map(|block| {
+                let offset = head.next_multiple_of(block.alignment);
+                if offset > head {
+                    free.insert(head, offset - head);
+                }
+                let copy = vk::BufferCopy {
+                    src_offset: block.offset,
+                    dst_offset: offset,
+                    size: block.size,
+                };
+                block.offset = offset;
+                head = offset + block.size;
+                copy
+            })
+            .collect();
+
+        if head < self.capacity {
+            free.insert(head, self.capacity - head);
+        }
+        self.free = free;
+        copies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arena(capacity: vk::DeviceSize) -> Arena {
+        Arena::new(BufferKey::default(), capacity)
+    }
+
+    fn alloc(arena: &mut Arena, size: vk::DeviceSize, alignment: vk::DeviceSize) -> ArenaHandle {
+        arena
+            .allocate(ArenaKey::default(), size, alignment)
+            .expect("arena should have room")
+    }
+
+    fn free_ranges(arena: &Arena) -> Vec<(vk::DeviceSize, vk::DeviceSize)> {
+        arena
+            .free
+            .iter()
+            .map(|(&offset, &size)| (offset, size))
+            .collect()
+    }
+
+    #[test]
+    fn allocates_first_fit() {
+        let mut arena = arena(256);
+        let a = alloc(&mut arena, 64, 1);
+        let b = alloc(&mut arena, 64, 1);
+        assert_eq!(arena.block(a).unwrap().offset, 0);
+        assert_eq!(arena.block(b).unwrap().offset, 64);
+
+        // The hole `a` leaves is the first range that fits.
+        arena.free(a);
+        let c = alloc(&mut arena, 32, 1);
+        assert_eq!(arena.block(c).unwrap().offset, 0);
+        assert_eq!(free_ranges(&arena), [(32, 32), (128, 128)]);
+    }
+
+    #[test]
+    fn free_coalesces_with_both_neighbours() {
+        let mut arena = arena(256);
+        let a = alloc(&mut arena, 64, 1);
+        let b = alloc(&mut arena, 64, 1);
+        let c = alloc(&mut arena, 64, 1);
+
+        arena.free(a);
+        arena.free(c);
+        assert_eq!(free_ranges(&arena), [(0, 64), (128, 128)]);
+
+        arena.free(b);
+        assert_eq!(free_ranges(&arena), [(0, 256)]);
+        assert_eq!(arena.stats().used, 0);
+        assert_eq!(arena.stats().blocks, 0);
+    }
+
+    #[test]
+    fn freeing_twice_is_harmless() {
+        let mut arena = arena(128);
+        let a = alloc(&mut arena, 64, 1);
+        arena.free(a);
+        arena.free(a);
+        assert!(arena.block(a).is_none());
+        assert_eq!(free_ranges(&arena), [(0, 128)]);
+    }
+
+    #[test]
+    fn aligns_offsets_and_keeps_padding_free() {
+        let mut arena = arena(256);
+        alloc(&mut arena, 4, 1);
+        let aligned = alloc(&mut arena, 16, 64);
+        assert_eq!(arena.block(aligned).unwrap().offset, 64);
+        assert_eq!(free_ranges(&arena), [(4, 60), (80, 176)]);
+
+        // Small unaligned requests still fit the padding.
+        let small = alloc(&mut arena, 8, 4);
+        assert_eq!(arena.block(small).unwrap().offset, 4);
+    }
+
+    #[test]
+    fn fails_when_exhausted_or_fragmented() {
+        let mut arena = arena(256);
+        assert!(arena.allocate(ArenaKey::default(), 512, 1).is_none());
+
+        let blocks = (0..4).map(|_| alloc(&mut arena, 64, 1)).collect::<Vec<_>>();
+        assert!(arena.allocate(ArenaKey::default(), 1, 1).is_none());
+
+        // Half the arena is free, but in two pieces of 64.
+        arena.free(blocks[0]);
+        arena.free(blocks[2]);
+        assert!(arena.allocate(ArenaKey::default(), 128, 1).is_none());
+        let stats = arena.stats();
+        assert_eq!(stats.used, 128);
+        assert_eq!(stats.free_ranges, 2);
+        assert_eq!(stats.largest_free, 64);
+        assert_eq!(stats.fragmentation(), 0.5);
+    }
+
+    #[test]
+    fn compact_packs_blocks_in_offset_order() {
+        let mut arena = arena(256);
+        let a = alloc(&mut arena, 32, 1);
+        let b = alloc(&mut arena, 32, 1);
+        let c = alloc(&mut arena, 16, 1);
+        let d = alloc(&mut arena, 16, 64);
+        arena.free(a);
+
+        let copies = arena
+            .compact()
+            .into_iter()
+            .map(|copy| (copy.src_offset, copy.dst_offset, copy.size))
+            .collect::<Vec<_>>();
+        assert_eq!(copies, [(32, 0, 32), (64, 32, 16), (128, 64, 16)]);
+
+        assert_eq!(arena.block(b).unwrap().offset, 0);
+        assert_eq!(arena.block(c).unwrap().offset, 32);
+        // `d` keeps its alignment, and the padding in front of it stays free.
+        assert_eq!(arena.block(d).unwrap().offset, 64);
+        assert_eq!(free_ranges(&arena), [(48, 16), (80, 176)]);
+        assert_eq!(arena.stats().used, 64);
+    }
+}
@@ -3,3 +3,9 @@ use slotmap::new_key_type;
 new_key_type! { pub struct BufferKey; }
 
 new_key_type! { pub struct LogicalBufferKey; }
+
+new_key_type! { pub struct LinearKey; }
+
+new_key_type! { pub struct ArenaKey; }
+
+new_key_type! { pub struct ArenaBlockKey; }
@@ -0,0 +1,231 @@
+use std::cell::{RefCell, RefMut};
+
+use ash::vk;
+
+use crate::buffer::{resource::Buffer, spec::BufferSpec};
+
+/// What a linear allocator does when a frame asks for more than its buffer holds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Switch to a buffer twice the size for the rest of the frame and keep it from then on.
+    Grow,
+    /// Return an error. For data whose size is known up front, where overflowing is a bug.
+    Fail,
+}
+
+/// A sub-allocation valid until its frame's fence next signals. It holds its frame's blocks
+/// borrowed while alive, so finish writing one allocation before asking for the next.
+pub struct LinearAllocation<'a> {
+    frame: RefMut<'a, LinearFrame>,
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+impl<'a> LinearAllocation<'a> {
+    pub(super) fn new(
+        frame: RefMut<'a, LinearFrame>,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Self {
+        Self {
+            buffer: frame.block.vk_buffer,
+            offset,
+            size,
+            frame,
+        }
+    }
+
+    pub fn mapped(&mut self) -> &mut [u8] {
+        let mapped = self
+            .frame
+            .block
+            .mapped
+            .expect("linear buffers are host visible and mapped");
+        // SAFETY: `bump` kept the range inside the block, and the frame stays mutably borrowed
+        // for as long as the slice, so no other allocation or reset can touch it meanwhile.
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                mapped.as_ptr().add(self.offset as usize),
+                self.size as usize,
+            )
+        }
+    }
+}
+
+pub(super) struct LinearAllocator {
+    pub spec: BufferSpec,
+    pub policy: OverflowPolicy,
+    /// Applied on top of what the caller asks for, typically the device's minimum uniform or
+    /// storage buffer offset alignment.
+    pub min_alignment: vk::DeviceSize,
+    /// Passes allocate through a shared `BufferManager` while recording, so each frame's
+    /// blocks sit behind a `RefCell`.
+    pub frames: Vec<RefCell<LinearFrame>>,
+}
+
+/// The blocks one frame in flight allocates from. They are owned here rather than by the
+/// manager's buffer map, so replacing one needs no mutable access to the manager. Generic over
+/// the block so the bookkeeping can be tested without a device.
+pub(super) struct LinearFrame<B = Buffer> {
+    pub block: B,
+    pub capacity: vk::DeviceSize,
+    pub head: vk::DeviceSize,
+    /// Blocks outgrown this frame. Commands recorded this frame still read them, so they are
+    /// only freed on the frame's next reset.
+    pub retired: Vec<B>,
+}
+
+impl<B> LinearFrame<B> {
+    pub fn new(block: B, capacity: vk::DeviceSize) -> Self {
+        Self {
+            block,
+            capacity,
+            head: 0,
+            retired: Vec::new(),
+        }
+    }
+
+    /// Offset of `size` bytes at `alignment` in the current block, if they fit.
+    pub fn bump(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let offset = self.head.next_multiple_of(alignment);
+        let end = offset.checked_add(size)?;
+        if end > self.capacity {
+            return None;
+        }
+        self.head = end;
+        Some(offset)
+    }
+
+    /// Like `bump`, but applies `policy` when the allocation doesn't fit. `Grow` asks `grow`
+    /// for a block of the given capacity and moves to it.
+    pub fn bump_or_grow(
+        &mut self,
+        policy: OverflowPolicy,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        grow: impl FnOnce(vk::DeviceSize) -> anyhow::Result<B>,
+    ) -> anyhow::Result<vk::DeviceSize> {
+        if let Some(offset) = self.bump(size, alignment) {
+            return Ok(offset);
+        }
+        match policy {
+            OverflowPolicy::Fail => anyhow::bail!(
+                "linear allocation of {size} bytes at offset {} overflows the {}-byte block",
+                self.head,
+                self.capacity
+            ),
+            OverflowPolicy::Grow => {
+                let capacity = (self.capacity * 2).max(size);
+                let block = grow(capacity)?;
+                self.replace_block(block, capacity);
+                Ok(self
+                    .bump(size, alignment)
+                    .expect("fresh linear block fits the allocation"))
+            }
+        }
+    }
+
+    /// Moves to a fresh block; the old one is kept until the next reset.
+    pub fn replace_block(&mut self, block: B, capacity: vk::DeviceSize) {
+        self.retired.push(std::mem::replace(&mut self.block, block));
+        self.capacity = capacity;
+        self.head = 0;
+    }
+
+    /// Rewinds to the start of the current block and hands back the outgrown ones to free.
+    pub fn reset(&mut self) -> Vec<B> {
+        self.head = 0;
+        std::mem::take(&mut self.retired)
+    }
+
+    pub fn into_buffers(self) -> impl Iterator<Item = B> {
+        std::iter::once(self.block).chain(self.retired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grow_to(capacity: vk::DeviceSize) -> anyhow::Result<vk::DeviceSize> {
+        Ok(capacity)
+    }
+
+    #[test]
+    fn bumps_back_to_back() {
+        let mut frame = LinearFrame::new(0, 256);
+        assert_eq!(frame.bump(64, 1), Some(0));
+        assert_eq!(frame.bump(64, 1), Some(64));
+        assert_eq!(frame.head, 128);
+    }
+
+    #[test]
+    fn aligns_each_allocation() {
+        let mut frame = LinearFrame::new(0, 256);
+        assert_eq!(frame.bump(3, 1), Some(0));
+        assert_eq!(frame.bump(8, 16), Some(16));
+        assert_eq!(frame.bump(1, 64), Some(64));
+        assert_eq!(frame.head, 65);
+    }
+
+    #[test]
+    fn fits_exactly_but_not_beyond() {
+        let mut frame = LinearFrame::new(0, 128);
+        assert_eq!(frame.bump(100, 1), Some(0));
+        // Alignment padding counts against the block too.
+        assert_eq!(frame.bump(28, 8), None);
+        assert_eq!(frame.bump(28, 1), Some(100));
+        assert_eq!(frame.bump(1, 1), None);
+        assert_eq!(frame.bump(vk::DeviceSize::MAX, 1), None);
+        assert_eq!(frame.head, 128);
+    }
+
+    #[test]
+    fn fail_policy_errors_and_keeps_the_block() {
+        let mut frame = LinearFrame::new(0, 64);
+        frame.bump(48, 1).unwrap();
+        assert!(
+            frame
+                .bump_or_grow(OverflowPolicy::Fail, 32, 1, |_| panic!("must not grow"))
+                .is_err()
+        );
+        assert_eq!(frame.capacity, 64);
+        assert_eq!(frame.head, 48);
+        assert!(frame.retired.is_empty());
+    }
+
+    #[test]
+    fn grow_policy_doubles_and_retires_the_old_block() {
+        let mut frame = LinearFrame::new(64, 64);
+        frame.bump(48, 1).unwrap();
+        let offset = frame
+            .bump_or_grow(OverflowPolicy::Grow, 32, 16, grow_to)
+            .unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!((frame.block, frame.capacity, frame.head), (128, 128, 32));
+        assert_eq!(frame.retired, [64]);
+
+        // An allocation bigger than twice the block gets a block of its own size.
+        frame
+            .bump_or_grow(OverflowPolicy::Grow, 1000, 1, grow_to)
+            .unwrap();
+        assert_eq!((frame.capacity, frame.head), (1000, 1000));
+        assert_eq!(frame.retired, [64, 128]);
+    }
+
+    #[test]
+    fn reset_rewinds_and_releases_outgrown_blocks() {
+        let mut frame = LinearFrame::new(64, 64);
+        frame
+            .bump_or_grow(OverflowPolicy::Grow, 100, 1, grow_to)
+            .unwrap();
+        assert_eq!(frame.reset(), [64]);
+        assert_eq!((frame.block, frame.capacity, frame.head), (128, 128, 0));
+        assert!(frame.retired.is_empty());
+    }
+}
@@ -1,14 +1,21 @@
+use std::{cell::RefCell, collections::HashSet, ptr::NonNull, sync::Arc};
+
 use anyhow::Context;
 use ash::vk;
+use bytemuck::Pod;
 use slotmap::SlotMap;
 use vk_mem::Alloc;
 
 use crate::{
     buffer::{
-        keys::{BufferKey, LogicalBufferKey},
+        arena::{Arena, ArenaBlock, ArenaHandle, ArenaStats},
+        keys::{ArenaKey, BufferKey, LinearKey, LogicalBufferKey},
+        linear::{LinearAllocation, LinearAllocator, LinearFrame, OverflowPolicy},
         resource::Buffer,
-        spec::{BufferLifetime, BufferSpec},
+        spec::{AllocationStrategy, BufferLifetime, BufferSpec, BufferUsage},
     },
+    leaks::Leak,
+    render::{BindlessHeap, BufferIndex},
     vulkan::DeviceContext,
 };
 
@@ -18,13 +25,39 @@ pub enum CompositeBufferKey {
     PerFrame(LogicalBufferKey),
 }
 
+/// A buffer replaced while `frame_number` was being recorded. Frames recorded earlier may
+/// still reference it.
+struct Retired {
+    frame_number: u64,
+    buffer: Buffer,
+}
+
+/// An arena range freed while `frame_number` was being recorded.
+struct RetiredBlock {
+    frame_number: u64,
+    handle: ArenaHandle,
+}
+
 #[derive(Default)]
 pub struct BufferManager {
     buffers: SlotMap<BufferKey, Buffer>,
     logical_buffers: SlotMap<LogicalBufferKey, Vec<BufferKey>>,
+    linear: SlotMap<LinearKey, LinearAllocator>,
+    arenas: SlotMap<ArenaKey, Arena>,
+    retired: Vec<Retired>,
+    retired_blocks: Vec<RetiredBlock>,
+    /// The frame being recorded, as last passed to `collect_retired`.
+    frame_number: u64,
+
+    bindless: Option<Arc<BindlessHeap>>,
 }
 
 impl BufferManager {
+    /// Storage buffers created from here on are registered in `heap`.
+    pub fn set_bindless(&mut self, heap: Arc<BindlessHeap>) {
+        self.bindless = Some(heap);
+    }
+
     #[inline]
     pub fn buffer_global(&self, key: BufferKey) -> &Buffer {
         self.buffers
@@ -50,6 +83,92 @@ impl BufferManager {
         }
     }
 
+    /// Copies `data` into a host-visible buffer at byte `offset`. Device-local buffers can't
+    /// be mapped and are filled through a host-visible staging buffer and a copy instead.
+    pub fn write<T: Pod>(
+        &self,
+        allocator: &vk_mem::Allocator,
+        key: CompositeBufferKey,
+        frame_index: usize,
+        offset: vk::DeviceSize,
+        data: &[T],
+    ) -> anyhow::Result<()> {
+        let buffer = self.resolve_buffer(key, frame_index);
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let mapped = mapped_range(buffer, offset, bytes.len())?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), mapped.as_ptr(), bytes.len());
+        }
+        // A no-op on host-coherent memory.
+        allocator
+            .flush_allocation(&buffer.allocation, offset, bytes.len() as vk::DeviceSize)
+            .with_context(|| format!("failed to flush {}", buffer.spec))
+    }
+
+    /// Copies bytes from `offset` of a host-visible buffer into `out`, e.g. results copied
+    /// into it in a frame whose fence has signalled. `Readback` buffers are cached and fast to
+    /// read; `HostTransient` ones may be write-combined, which makes reads slow but correct.
+    pub fn read<T: Pod>(
+        &self,
+        allocator: &vk_mem::Allocator,
+        key: CompositeBufferKey,
+        frame_index: usize,
+        offset: vk::DeviceSize,
+        out: &mut [T],
+    ) -> anyhow::Result<()> {
+        let buffer = self.resolve_buffer(key, frame_index);
+        if !buffer.spec.buffer_type.is_host_visible() {
+            anyhow::bail!(
+                "{} is device-local; copy the data into a Readback buffer to read it on the host",
+                buffer.spec
+            );
+        }
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(out);
+        let mapped = mapped_range(buffer, offset, bytes.len())?;
+
+        allocator
+            .invalidate_allocation(&buffer.allocation, offset, bytes.len() as vk::DeviceSize)
+            .with_context(|| format!("failed to invalidate {}", buffer.spec))?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(mapped.as_ptr(), bytes.as_mut_ptr(), bytes.len());
+        }
+        Ok(())
+    }
+
+    /// Records a copy of `regions` from `src` to `dst`, e.g. out of a `HostTransient` staging
+    /// buffer into device-local memory or out of one into a `Readback` buffer. Later accesses
+    /// to `dst` wait for it.
+    pub fn copy(
+        &self,
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        src: CompositeBufferKey,
+        dst: CompositeBufferKey,
+        frame_index: usize,
+        regions: &[vk::BufferCopy],
+    ) -> anyhow::Result<()> {
+        let src = self.resolve_buffer(src, frame_index);
+        let dst = self.resolve_buffer(dst, frame_index);
+        for region in regions {
+            if region.src_offset + region.size > src.size
+                || region.dst_offset + region.size > dst.size
+            {
+                anyhow::bail!(
+                    "copy of {} bytes from {} at {} to {} at {} is out of bounds",
+                    region.size,
+                    src.spec,
+                    region.src_offset,
+                    dst.spec,
+                    region.dst_offset
+                );
+            }
+        }
+        record_copy(device, cmd, src.vk_buffer, dst.vk_buffer, regions);
+        Ok(())
+    }
+
+    /// Creates the buffer `spec` describes, one per frame in flight for per-frame buffers.
     pub fn create_buffer(
         &mut self,
         allocator: &vk_mem::Allocator,
@@ -57,22 +176,13 @@ impl BufferManager {
         spec: BufferSpec,
         frame_count: u32,
     ) -> anyhow::Result<CompositeBufferKey> {
+        spec.validate(&device_context.features)
+            .context("invalid buffer spec")?;
+
         match spec.lifetime {
             BufferLifetime::Global => {
-                let (vk_buffer, allocation) = with_buffer_create_info(&spec, |bci, aci| unsafe {
-                    allocator.create_buffer(bci, aci)
-                })
-                .context("failed to create buffer")?;
-
-                if let Some(name) = spec.debug_name.as_deref() {
-                    device_context.name_object(vk_buffer, name)?;
-                }
-
-                let key = self.buffers.insert(Buffer {
-                    vk_buffer,
-                    allocation,
-                    spec,
-                });
+                let name = spec.debug_name.clone();
+                let key = self.create_one(allocator, device_context, spec, name)?;
                 Ok(CompositeBufferKey::Global(key))
             }
 
@@ -80,24 +190,16 @@ impl BufferManager {
                 let mut buffer_keys: Vec<BufferKey> = Vec::with_capacity(frame_count as usize);
 
                 for i in 0..frame_count {
-                    let spec_clone = spec.clone();
-
-                    let (vk_buffer, allocation) =
-                        with_buffer_create_info(&spec_clone, |bci, aci| unsafe {
-                            allocator.create_buffer(bci, aci)
-                        })
-                        .context("failed to create buffer")?;
-
-                    if let Some(name) = spec.debug_name.as_deref() {
-                        device_context
-                            .name_object(vk_buffer, format!("{}(Frame {:?})", name, i))?;
-                    }
-
-                    buffer_keys.push(self.buffers.insert(Buffer {
-                        vk_buffer,
-                        allocation,
-                        spec: spec_clone,
-                    }));
+                    let name = spec
+                        .debug_name
+                        .as_deref()
+                        .map(|name| format!("{}(Frame {:?})", name, i));
+                    buffer_keys.push(self.create_one(
+                        allocator,
+                        device_context,
+                        spec.clone(),
+                        name,
+                    )?);
                 }
                 let logical_key = self.logical_buffers.insert(buffer_keys);
                 Ok(CompositeBufferKey::PerFrame(logical_key))
@@ -105,28 +207,577 @@ impl BufferManager {
         }
     }
 
-    pub fn cleanup_per_frames(&mut self, allocator: &vk_mem::Allocator) -> anyhow::Result<()> {
-        for (_, buffers) in self.logical_buffers.drain() {
-            for key in buffers {
-                if let Some(mut buffer) = self.buffers.remove(key) {
-                    unsafe {
-                        allocator.destroy_buffer(buffer.vk_buffer, &mut buffer.allocation);
-                    }
-                }
+    fn create_one(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        spec: BufferSpec,
+        name: Option<String>,
+    ) -> anyhow::Result<BufferKey> {
+        let buffer = self.allocate(allocator, device_context, spec, name)?;
+        Ok(self.buffers.insert(buffer))
+    }
+
+    fn allocate(
+        &self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        spec: BufferSpec,
+        name: Option<String>,
+    ) -> anyhow::Result<Buffer> {
+        // Descriptor buffers describe storage buffers by address.
+        let spec = if spec.usage.contains(BufferUsage::STORAGE)
+            && self
+                .bindless
+                .as_ref()
+                .is_some_and(|heap| heap.needs_buffer_addresses())
+        {
+            spec.device_address(true)
+        } else {
+            spec
+        };
+        let usage = spec.usage_flags();
+
+        let size = spec.initial_size as vk::DeviceSize;
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let (vk_buffer, allocation) = unsafe {
+            allocator
+                .create_buffer(&buffer_info, &spec.allocation_create_info())
+                .with_context(|| format!("failed to create buffer for {spec}"))?
+        };
+
+        if let Some(name) = name {
+            device_context.name_object(vk_buffer, name)?;
+        }
+
+        let mapped = if spec.buffer_type.is_host_visible() {
+            let mapped = NonNull::new(allocator.get_allocation_info(&allocation).mapped_data);
+            if mapped.is_none() {
+                anyhow::bail!("{spec} is host visible but was not mapped");
             }
+            mapped.map(NonNull::cast)
+        } else {
+            None
+        };
+
+        let address = usage
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+            .then(|| unsafe {
+                device_context.device.get_buffer_device_address(
+                    &vk::BufferDeviceAddressInfo::default().buffer(vk_buffer),
+                )
+            });
+
+        let mut buffer = Buffer {
+            vk_buffer,
+            allocation,
+            size,
+            mapped,
+            address,
+            spec,
+            bindless: None,
+        };
+        buffer.bindless = self.register_bindless(&buffer)?;
+        Ok(buffer)
+    }
+
+    /// Makes a global `Resizable` buffer hold at least `required` bytes. When it has to grow,
+    /// the buffer behind `key` is replaced by one at least twice the size and a copy of its
+    /// first `live_size` bytes is recorded into `cmd`. The device address and bindless index
+    /// change with it; the old buffer is freed by `collect_retired` once the frames that may
+    /// use it have finished. Returns whether the buffer grew.
+    pub fn ensure_capacity(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        cmd: vk::CommandBuffer,
+        key: CompositeBufferKey,
+        required: vk::DeviceSize,
+        live_size: vk::DeviceSize,
+    ) -> anyhow::Result<bool> {
+        let CompositeBufferKey::Global(key) = key else {
+            anyhow::bail!("per-frame buffers can't be resized");
+        };
+        let old = self.buffers.get(key).expect("invalid BufferKey");
+        if required <= old.size {
+            return Ok(false);
+        }
+        if old.spec.allocation_strategy != AllocationStrategy::Resizable {
+            anyhow::bail!(
+                "{} needs {required} bytes but holds {} and is not resizable",
+                old.spec,
+                old.size
+            );
+        }
+        if live_size > old.size {
+            anyhow::bail!(
+                "{}: live range of {live_size} bytes is past its {} byte end",
+                old.spec,
+                old.size
+            );
+        }
+
+        let mut size = (old.size * 2).max(required);
+        let stride = old.spec.item_stride as vk::DeviceSize;
+        if stride != 0 {
+            size = size.next_multiple_of(stride);
+        }
+        log::debug!("Growing {} from {} to {size} bytes", old.spec, old.size);
+
+        let spec = old.spec.clone().size(size as usize);
+        let name = spec.debug_name.clone();
+        let new = self.allocate(allocator, device_context, spec, name)?;
+
+        if live_size > 0 {
+            let region = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size: live_size,
+            };
+            record_copy(
+                &device_context.device,
+                cmd,
+                old.vk_buffer,
+                new.vk_buffer,
+                &[region],
+            );
+        }
+
+        let old = std::mem::replace(&mut self.buffers[key], new);
+        self.retire(old);
+        Ok(true)
+    }
+
+    /// Creates a device-local arena buffer that meshes and instance data are sub-allocated
+    /// from. Shaders reach allocations through the arena's bindless index plus their offset;
+    /// index data can be bound directly when the spec's usage is `Index`.
+    pub fn create_arena(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        spec: BufferSpec,
+    ) -> anyhow::Result<ArenaKey> {
+        spec.validate(&device_context.features)
+            .context("invalid buffer spec")?;
+        if spec.allocation_strategy != AllocationStrategy::Arena {
+            anyhow::bail!("{spec} is not an arena buffer");
+        }
+        if spec.lifetime != BufferLifetime::Global {
+            anyhow::bail!("{spec}: arenas outlive frames, so they must be global");
+        }
+
+        let capacity = spec.initial_size as vk::DeviceSize;
+        let name = spec.debug_name.clone();
+        let buffer = self.create_one(allocator, device_context, spec, name)?;
+        Ok(self.arenas.insert(Arena::new(buffer, capacity)))
+    }
+
+    /// Sub-allocates `size` bytes at `alignment`, a power of two.
+    pub fn arena_alloc(
+        &mut self,
+        key: ArenaKey,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> anyhow::Result<ArenaHandle> {
+        debug_assert!(alignment.is_power_of_two());
+        let arena = self.arenas.get_mut(key).expect("invalid ArenaKey");
+        match arena.allocate(key, size, alignment) {
+            Some(handle) => Ok(handle),
+            None => {
+                let stats = arena.stats();
+                anyhow::bail!(
+                    "{} has no room for {size} bytes ({} of {} used, {} free ranges, largest \
+                     {}, fragmentation {:.2})",
+                    self.buffers[arena.buffer].spec,
+                    stats.used,
+                    stats.capacity,
+                    stats.free_ranges,
+                    stats.largest_free,
+                    stats.fragmentation()
+                )
+            }
+        }
+    }
+
+    /// Frees the range once the frames that may still read it have finished, like `destroy`.
+    /// The handle stays resolvable until then.
+    pub fn arena_free(&mut self, handle: ArenaHandle) {
+        self.retired_blocks.push(RetiredBlock {
+            frame_number: self.frame_number,
+            handle,
+        });
+    }
+
+    /// The arena's buffer under a key passes can hold like any other; it stays valid across
+    /// defragmentation.
+    pub fn arena_buffer_key(&self, key: ArenaKey) -> CompositeBufferKey {
+        CompositeBufferKey::Global(self.arenas.get(key).expect("invalid ArenaKey").buffer)
+    }
+
+    #[inline]
+    pub fn arena_block(&self, handle: ArenaHandle) -> ArenaBlock {
+        self.arenas
+            .get(handle.arena)
+            .and_then(|arena| arena.block(handle))
+            .expect("invalid ArenaHandle")
+    }
+
+    pub fn arena_stats(&self, key: ArenaKey) -> ArenaStats {
+        self.arenas.get(key).expect("invalid ArenaKey").stats()
+    }
+
+    /// Packs the arena's allocations into a new buffer, recording the copies into `cmd`. The
+    /// arena keeps its `BufferKey`, but offsets, device address and bindless index change, so
+    /// handles must be resolved again. The old buffer is retired like a grown one.
+    pub fn defragment_arena(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        cmd: vk::CommandBuffer,
+        key: ArenaKey,
+    ) -> anyhow::Result<()> {
+        let buffer_key = self.arenas.get(key).expect("invalid ArenaKey").buffer;
+        let spec = self.buffers[buffer_key].spec.clone();
+        let name = spec.debug_name.clone();
+        // Allocated before compacting, so a failure leaves every block where it was.
+        let new = self.allocate(allocator, device_context, spec, name)?;
+
+        let arena = &mut self.arenas[key];
+        let before = arena.stats();
+        let copies = arena.compact();
+        if !copies.is_empty() {
+            record_copy(
+                &device_context.device,
+                cmd,
+                self.buffers[buffer_key].vk_buffer,
+                new.vk_buffer,
+                &copies,
+            );
+        }
+        log::debug!(
+            "Defragmented {}: {} blocks, fragmentation {:.2} -> {:.2}",
+            new.spec,
+            before.blocks,
+            before.fragmentation(),
+            arena.stats().fragmentation()
+        );
+
+        let old = std::mem::replace(&mut self.buffers[buffer_key], new);
+        self.retire(old);
+        Ok(())
+    }
+
+    /// Destroys the buffer behind `key`, every frame's copy of it for per-frame buffers, once
+    /// the frames that may still use it have finished. The key is invalid from here on.
+    pub fn destroy(&mut self, key: CompositeBufferKey) {
+        let keys = match key {
+            CompositeBufferKey::Global(key) => vec![key],
+            CompositeBufferKey::PerFrame(key) => {
+                self.logical_buffers.remove(key).unwrap_or_default()
+            }
+        };
+        self.retire_keys(keys);
+    }
+
+    /// Like `destroy`, for every block of a linear allocator.
+    pub fn destroy_linear(&mut self, key: LinearKey) {
+        let Some(linear) = self.linear.remove(key) else {
+            return;
+        };
+        for frame in linear.frames {
+            for buffer in frame.into_inner().into_buffers() {
+                self.retire(buffer);
+            }
+        }
+    }
+
+    /// Like `destroy`, for an arena and every allocation in it.
+    pub fn destroy_arena(&mut self, key: ArenaKey) {
+        if let Some(arena) = self.arenas.remove(key) {
+            self.retire_keys(vec![arena.buffer]);
+        }
+    }
+
+    fn retire_keys(&mut self, keys: Vec<BufferKey>) {
+        for key in keys {
+            if let Some(buffer) = self.buffers.remove(key) {
+                self.retire(buffer);
+            }
+        }
+    }
+
+    /// Queues `buffer` for `collect_retired`. Frames up to the one being recorded may use it.
+    fn retire(&mut self, buffer: Buffer) {
+        self.retired.push(Retired {
+            frame_number: self.frame_number,
+            buffer,
+        });
+    }
+
+    /// Destroys replaced buffers and frees arena ranges once every frame that could reference
+    /// them has retired. Call at the start of each frame: anything released from here on is
+    /// retired against `frame_number`.
+    pub fn collect_retired(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        frame_number: u64,
+        frame_count: u64,
+    ) {
+        let (done, pending) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|retired| retired.frame_number + frame_count <= frame_number);
+        self.retired = pending;
+        self.frame_number = frame_number;
+        for retired in done {
+            self.destroy_now(allocator, retired.buffer);
+        }
+
+        let (done, pending) = std::mem::take(&mut self.retired_blocks)
+            .into_iter()
+            .partition(|retired| retired.frame_number + frame_count <= frame_number);
+        self.retired_blocks = pending;
+        for retired in done {
+            // Arenas destroyed in the meantime took their blocks with them.
+            if let Some(arena) = self.arenas.get_mut(retired.handle.arena) {
+                arena.free(retired.handle);
+            }
+        }
+    }
+
+    /// Creates a linear allocator with one `spec`-sized block per frame in flight. Allocations
+    /// from it live until `reset_linear` is called for their frame.
+    pub fn create_linear(
+        &mut self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        spec: BufferSpec,
+        policy: OverflowPolicy,
+        min_alignment: vk::DeviceSize,
+        frame_count: u32,
+    ) -> anyhow::Result<LinearKey> {
+        spec.validate(&device_context.features)
+            .context("invalid buffer spec")?;
+        if spec.allocation_strategy != AllocationStrategy::Linear {
+            anyhow::bail!("{spec} is not a linear buffer");
+        }
+        if !min_alignment.is_power_of_two() {
+            anyhow::bail!("linear buffer alignment {min_alignment} is not a power of two");
+        }
+
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for i in 0..frame_count {
+            let name = spec
+                .debug_name
+                .as_deref()
+                .map(|name| format!("{}(Frame {:?})", name, i));
+            let block = self.allocate(allocator, device_context, spec.clone(), name)?;
+            let capacity = block.size;
+            frames.push(RefCell::new(LinearFrame::new(block, capacity)));
+        }
+
+        Ok(self.linear.insert(LinearAllocator {
+            spec,
+            policy,
+            min_alignment,
+            frames,
+        }))
+    }
+
+    /// Bumps `size` bytes out of `frame_index`'s block. `alignment` must be a power of two.
+    /// Takes `&self` so passes can allocate while recording; the returned allocation keeps the
+    /// frame's blocks borrowed, so only one allocation per frame can be live at a time.
+    pub fn linear_alloc(
+        &self,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        key: LinearKey,
+        frame_index: usize,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> anyhow::Result<LinearAllocation<'_>> {
+        debug_assert!(alignment.is_power_of_two());
+        let linear = self.linear.get(key).expect("invalid LinearKey");
+        let alignment = alignment.max(linear.min_alignment);
+        let mut frame = linear.frames[frame_index].try_borrow_mut().map_err(|_| {
+            anyhow::anyhow!(
+                "{} (frame {frame_index}) still has a live allocation",
+                linear.spec
+            )
+        })?;
+
+        let offset = frame
+            .bump_or_grow(linear.policy, size, alignment, |capacity| {
+                let spec = linear.spec.clone().size(capacity as usize);
+                let name = spec
+                    .debug_name
+                    .as_deref()
+                    .map(|name| format!("{}(Frame {:?}, {} bytes)", name, frame_index, capacity));
+                log::debug!(
+                    "Growing linear buffer {} to {capacity} bytes for frame {frame_index}",
+                    linear.spec
+                );
+                self.allocate(allocator, device_context, spec, name)
+            })
+            .with_context(|| format!("{} (frame {frame_index})", linear.spec))?;
+
+        Ok(LinearAllocation::new(frame, offset, size))
+    }
+
+    /// Rewinds every linear allocator's block for `frame_index` and frees the blocks it outgrew.
+    /// Only call once the frame's fence has signalled.
+    pub fn reset_linear(&mut self, allocator: &vk_mem::Allocator, frame_index: usize) {
+        let mut retired = Vec::new();
+        for (_, linear) in &mut self.linear {
+            retired.append(&mut linear.frames[frame_index].get_mut().reset());
+        }
+        for buffer in retired {
+            self.destroy_now(allocator, buffer);
+        }
+    }
+
+    /// Buffers, arenas and linear allocators nobody destroyed, per-frame ones included.
+    pub fn leaks(&self) -> Vec<Leak> {
+        let arenas = self
+            .arenas
+            .values()
+            .map(|arena| arena.buffer)
+            .collect::<HashSet<_>>();
+        let buffers = self.buffers.iter().map(|(key, buffer)| Leak {
+            kind: if arenas.contains(&key) {
+                "arena buffer"
+            } else {
+                "buffer"
+            },
+            description: buffer.spec.to_string(),
+            bytes: Some(buffer.size),
+        });
+
+        let linear = self.linear.values().flat_map(|linear| {
+            linear.frames.iter().map(|frame| {
+                let frame = frame.borrow();
+                Leak {
+                    kind: "linear allocator block",
+                    description: frame.block.spec.to_string(),
+                    bytes: Some(
+                        std::iter::once(&frame.block)
+                            .chain(&frame.retired)
+                            .map(|buffer| buffer.size)
+                            .sum(),
+                    ),
+                }
+            })
+        });
+
+        buffers.chain(linear).collect()
+    }
+
+    /// Frees every buffer still alive, retired or not. The device must be idle.
+    pub fn destroy_all(&mut self, allocator: &vk_mem::Allocator) -> anyhow::Result<()> {
+        self.logical_buffers.clear();
+        self.arenas.clear();
+        self.retired_blocks.clear();
+
+        let buffers = self
+            .buffers
+            .drain()
+            .map(|(_, buffer)| buffer)
+            .chain(
+                self.linear
+                    .drain()
+                    .flat_map(|(_, linear)| linear.frames)
+                    .flat_map(|frame| frame.into_inner().into_buffers()),
+            )
+            .chain(
+                std::mem::take(&mut self.retired)
+                    .into_iter()
+                    .map(|retired| retired.buffer),
+            )
+            .collect::<Vec<_>>();
+        for buffer in buffers {
+            self.destroy_now(allocator, buffer);
         }
         Ok(())
     }
+
+    fn destroy_now(&self, allocator: &vk_mem::Allocator, mut buffer: Buffer) {
+        if let (Some(heap), Some(index)) = (&self.bindless, buffer.bindless) {
+            heap.release_buffer(index);
+        }
+        unsafe {
+            allocator.destroy_buffer(buffer.vk_buffer, &mut buffer.allocation);
+        }
+    }
+
+    fn register_bindless(&self, buffer: &Buffer) -> anyhow::Result<Option<BufferIndex>> {
+        let Some(heap) = &self.bindless else {
+            return Ok(None);
+        };
+        if !buffer.spec.usage.contains(BufferUsage::STORAGE) {
+            return Ok(None);
+        }
+        heap.register_buffer(buffer.vk_buffer, buffer.address, buffer.size)
+            .map(Some)
+            .context("failed to register buffer in the bindless heap")
+    }
 }
 
-fn with_buffer_create_info<R>(
-    spec: &BufferSpec,
-    f: impl FnOnce(&vk::BufferCreateInfo, &vk_mem::AllocationCreateInfo) -> R,
-) -> R {
-    let bci = vk::BufferCreateInfo::default();
-    let aci = vk_mem::AllocationCreateInfo {
-        usage: vk_mem::MemoryUsage::Auto,
-        ..Default::default()
+/// The mapped address of `len` bytes at `offset`, checked against the buffer's size.
+fn mapped_range(
+    buffer: &Buffer,
+    offset: vk::DeviceSize,
+    len: usize,
+) -> anyhow::Result<NonNull<u8>> {
+    let Some(mapped) = buffer.mapped else {
+        anyhow::bail!(
+            "{} is device local and can't be mapped; fill it by copying from a HostTransient \
+             staging buffer instead",
+            buffer.spec
+        );
     };
-    f(&bci, &aci)
+    let end = offset.checked_add(len as vk::DeviceSize);
+    if end.is_none_or(|end| end > buffer.size) {
+        anyhow::bail!(
+            "{len} bytes at offset {offset} are out of bounds of {} ({} bytes)",
+            buffer.spec,
+            buffer.size
+        );
+    }
+    // SAFETY: in bounds of the mapping, checked above.
+    Ok(unsafe { mapped.add(offset as usize) })
+}
+
+/// Copies `regions` from `src` to `dst`, ordered after earlier writes to `src` and before any
+/// later access to `dst`.
+fn record_copy(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    src: vk::Buffer,
+    dst: vk::Buffer,
+    regions: &[vk::BufferCopy],
+) {
+    let before = vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+        .dst_access_mask(vk::AccessFlags2::TRANSFER_READ);
+    let after = vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::COPY)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE);
+    unsafe {
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&before)),
+        );
+        device.cmd_copy_buffer(cmd, src, dst, regions);
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&after)),
+        );
+    }
 }
@@ -1,6 +1,12 @@
+mod arena;
 mod keys;
+mod linear;
 mod manager;
 mod resource;
 mod spec;
 
-pub use manager::BufferManager;
+pub use arena::ArenaHandle;
+pub use keys::{ArenaKey, LinearKey};
+pub use linear::OverflowPolicy;
+pub use manager::{BufferManager, CompositeBufferKey};
+pub use spec::{AllocationStrategy, BufferLifetime, BufferSpec, BufferType, BufferUsage};
@@ -1,9 +1,17 @@
+use std::ptr::NonNull;
+
 use ash::vk;
 
-use crate::buffer::spec::BufferSpec;
+use crate::{buffer::spec::BufferSpec, render::BufferIndex};
 
 pub struct Buffer {
     pub vk_buffer: vk::Buffer,
     pub allocation: vk_mem::Allocation,
+    pub size: vk::DeviceSize,
+    /// Persistent mapping of host-visible buffers.
+    pub mapped: Option<NonNull<u8>>,
+    pub address: Option<vk::DeviceAddress>,
     pub spec: BufferSpec,
+    /// Slot in the bindless heap, for storage buffers.
+    pub bindless: Option<BufferIndex>,
 }
@@ -1,37 +1,389 @@
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+use std::fmt;
+
+use ash::vk;
+
+use crate::vulkan::DeviceFeatures;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum AllocationStrategy {
+    /// One buffer of `initial_size`, used as a whole.
+    Fixed,
     Linear,
+    /// One buffer that `BufferManager::ensure_capacity` replaces with a larger one, keeping
+    /// its `BufferKey`.
     Resizable,
     Arena,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum BufferLifetime {
     PerFrame,
     Global,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// Where a buffer lives and who writes it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum BufferType {
+    /// Device-local memory sub-allocated by an arena, filled through transfers.
     DeviceArena,
+    /// Device-local memory filled through transfers or by shaders.
     Device,
+    /// Host-visible memory, persistently mapped and rewritten by the CPU. It may be
+    /// write-combined, so reading it back is slow.
     HostTransient,
+    /// Host-visible memory the GPU copies results into and the CPU reads back, persistently
+    /// mapped and cached.
+    Readback,
+    /// Device-local draw or dispatch arguments, written by uploads or compute shaders. Needs
+    /// `INDIRECT` usage, plus `STORAGE` when a shader writes it.
     IndirectCommand,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub enum BufferUsage {
-    Storage,
-    Uniform,
-    Transfer,
+impl BufferType {
+    pub fn is_host_visible(self) -> bool {
+        matches!(self, BufferType::HostTransient | BufferType::Readback)
+    }
+}
+
+bitflags::bitflags! {
+    /// What shaders and commands use a buffer for. Flags combine, e.g. `STORAGE | INDIRECT`
+    /// for draws a compute shader writes.
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    pub struct BufferUsage: u32 {
+        const STORAGE = 1 << 0;
+        const UNIFORM = 1 << 1;
+        /// Source and destination of copies.
+        const TRANSFER = 1 << 2;
+        const INDEX = 1 << 3;
+        const VERTEX = 1 << 4;
+        const INDIRECT = 1 << 5;
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct BufferSpec {
     pub allocation_strategy: AllocationStrategy,
     pub lifetime: BufferLifetime,
+    pub buffer_type: BufferType,
     pub usage: BufferUsage,
     pub initial_size: usize,
+    /// Size of one element, for buffers holding arrays. Zero when the buffer is untyped.
     pub item_stride: usize,
+    /// Adds `SHADER_DEVICE_ADDRESS` usage so shaders can reach the buffer by pointer.
+    pub device_address: bool,
     pub debug_name: Option<String>,
 }
+
+impl Default for BufferSpec {
+    fn default() -> Self {
+        Self {
+            allocation_strategy: AllocationStrategy::Fixed,
+            lifetime: BufferLifetime::Global,
+            buffer_type: BufferType::Device,
+            usage: BufferUsage::STORAGE,
+            initial_size: 0,
+            item_stride: 0,
+            device_address: false,
+            debug_name: None,
+        }
+    }
+}
+
+impl BufferSpec {
+    pub fn allocation_strategy(mut self, strategy: AllocationStrategy) -> Self {
+        self.allocation_strategy = strategy;
+        self
+    }
+
+    pub fn lifetime(mut self, lifetime: BufferLifetime) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    pub fn buffer_type(mut self, buffer_type: BufferType) -> Self {
+        self.buffer_type = buffer_type;
+        self
+    }
+
+    pub fn usage(mut self, usage: BufferUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn size(mut self, size: usize) -> Self {
+        self.initial_size = size;
+        self
+    }
+
+    /// Sizes the buffer for `count` items of `stride` bytes.
+    pub fn items(mut self, stride: usize, count: usize) -> Self {
+        self.item_stride = stride;
+        self.initial_size = stride * count;
+        self
+    }
+
+    pub fn device_address(mut self, device_address: bool) -> Self {
+        self.device_address = device_address;
+        self
+    }
+
+    pub fn debug_name(mut self, name: impl Into<String>) -> Self {
+        self.debug_name = Some(name.into());
+        self
+    }
+
+    pub fn usage_flags(&self) -> vk::BufferUsageFlags {
+        let usage = [
+            (BufferUsage::STORAGE, vk::BufferUsageFlags::STORAGE_BUFFER),
+            (BufferUsage::UNIFORM, vk::BufferUsageFlags::UNIFORM_BUFFER),
+            (
+                BufferUsage::TRANSFER,
+                vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            ),
+            (BufferUsage::INDEX, vk::BufferUsageFlags::INDEX_BUFFER),
+            (BufferUsage::VERTEX, vk::BufferUsageFlags::VERTEX_BUFFER),
+            (BufferUsage::INDIRECT, vk::BufferUsageFlags::INDIRECT_BUFFER),
+        ]
+        .into_iter()
+        .filter(|(usage, _)| self.usage.contains(*usage))
+        .fold(vk::BufferUsageFlags::empty(), |flags, (_, vk_flags)| {
+            flags | vk_flags
+        });
+
+        // Device memory can only be filled by copying into it; host memory is a copy source.
+        let placement = match self.buffer_type {
+            BufferType::Device | BufferType::DeviceArena | BufferType::IndirectCommand => {
+                vk::BufferUsageFlags::TRANSFER_DST
+            }
+            BufferType::HostTransient => vk::BufferUsageFlags::TRANSFER_SRC,
+            BufferType::Readback => vk::BufferUsageFlags::TRANSFER_DST,
+        };
+
+        // Growing and defragmenting copy the old contents across on the GPU.
+        let resize = match self.allocation_strategy {
+            AllocationStrategy::Resizable | AllocationStrategy::Arena => {
+                vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST
+            }
+            AllocationStrategy::Fixed | AllocationStrategy::Linear => vk::BufferUsageFlags::empty(),
+        };
+
+        let address = if self.device_address {
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+        } else {
+            vk::BufferUsageFlags::empty()
+        };
+
+        usage | placement | resize | address
+    }
+
+    pub fn allocation_create_info(&self) -> vk_mem::AllocationCreateInfo {
+        match self.buffer_type {
+            BufferType::HostTransient => vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::AutoPreferHost,
+                flags: vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+                required_flags: vk::MemoryPropertyFlags::HOST_COHERENT,
+                ..Default::default()
+            },
+            // Possibly non-coherent; `BufferManager::read` invalidates before copying out.
+            BufferType::Readback => vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::AutoPreferHost,
+                flags: vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+                ..Default::default()
+            },
+            BufferType::Device | BufferType::DeviceArena | BufferType::IndirectCommand => {
+                vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::AutoPreferDevice,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Rejects specs the manager could not honour or that contradict themselves.
+    pub fn validate(&self, features: &DeviceFeatures) -> anyhow::Result<()> {
+        if self.initial_size == 0 {
+            anyhow::bail!("{self} has no size");
+        }
+
+        if self.usage.is_empty() {
+            anyhow::bail!("{self} has no usage");
+        }
+
+        if self.item_stride != 0 && !self.initial_size.is_multiple_of(self.item_stride) {
+            anyhow::bail!(
+                "{self} is not a whole number of {}-byte items",
+                self.item_stride
+            );
+        }
+
+        if self.device_address && !features.buffer_device_address {
+            anyhow::bail!("{self} asks for a device address, which the device does not support");
+        }
+
+        match (self.allocation_strategy, self.buffer_type) {
+            (AllocationStrategy::Linear, BufferType::HostTransient)
+                if self.lifetime != BufferLifetime::PerFrame =>
+            {
+                anyhow::bail!("{self} is linear, which is only reset per frame");
+            }
+            (AllocationStrategy::Linear, BufferType::HostTransient) => {}
+            (AllocationStrategy::Linear, _) => {
+                anyhow::bail!("{self} is linear, which needs host-visible memory to write into");
+            }
+            (AllocationStrategy::Arena, BufferType::DeviceArena) => {}
+            (AllocationStrategy::Arena, _) | (_, BufferType::DeviceArena) => {
+                anyhow::bail!("{self}: only DeviceArena buffers use the Arena strategy");
+            }
+            _ => {}
+        }
+
+        if self.buffer_type == BufferType::IndirectCommand {
+            if !self.usage.contains(BufferUsage::INDIRECT) {
+                anyhow::bail!("{self}: indirect command buffers need INDIRECT usage");
+            }
+            if self.usage.contains(BufferUsage::UNIFORM) {
+                anyhow::bail!("{self}: indirect command buffers can't be uniform buffers");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for BufferSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BufferSpec(type={:?}, usage={:?}, strategy={:?}, lifetime={:?}, size={}, stride={}, deviceAddress={}, debugName={})",
+            self.buffer_type,
+            self.usage,
+            self.allocation_strategy,
+            self.lifetime,
+            self.initial_size,
+            self.item_stride,
+            self.device_address,
+            match &self.debug_name {
+                Some(name) => name,
+                None => "<none>",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> BufferSpec {
+        BufferSpec::default().size(256)
+    }
+
+    #[test]
+    fn usage_flags_combine_every_usage() {
+        let flags = spec()
+            .usage(BufferUsage::STORAGE | BufferUsage::INDEX)
+            .usage_flags();
+        assert_eq!(
+            flags,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+        );
+
+        let flags = spec()
+            .buffer_type(BufferType::IndirectCommand)
+            .usage(BufferUsage::STORAGE | BufferUsage::INDIRECT)
+            .usage_flags();
+        assert_eq!(
+            flags,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+        );
+    }
+
+    #[test]
+    fn usage_flags_follow_placement_and_strategy() {
+        let staging = spec()
+            .buffer_type(BufferType::HostTransient)
+            .usage(BufferUsage::UNIFORM)
+            .usage_flags();
+        assert_eq!(
+            staging,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC
+        );
+
+        let resizable = spec()
+            .allocation_strategy(AllocationStrategy::Resizable)
+            .usage_flags();
+        assert!(
+            resizable
+                .contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+        );
+
+        let addressed = spec().device_address(true).usage_flags();
+        assert!(addressed.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS));
+        assert!(
+            !spec()
+                .usage_flags()
+                .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+        );
+    }
+
+    #[test]
+    fn validate_accepts_sensible_specs() {
+        let features = DeviceFeatures {
+            buffer_device_address: true,
+            ..Default::default()
+        };
+        let specs = [
+            spec(),
+            spec().items(16, 16).device_address(true),
+            spec()
+                .allocation_strategy(AllocationStrategy::Linear)
+                .lifetime(BufferLifetime::PerFrame)
+                .buffer_type(BufferType::HostTransient)
+                .usage(BufferUsage::UNIFORM),
+            spec()
+                .allocation_strategy(AllocationStrategy::Arena)
+                .buffer_type(BufferType::DeviceArena)
+                .usage(BufferUsage::STORAGE | BufferUsage::INDEX),
+            spec()
+                .buffer_type(BufferType::IndirectCommand)
+                .usage(BufferUsage::STORAGE | BufferUsage::INDIRECT),
+        ];
+        for spec in specs {
+            assert!(spec.validate(&features).is_ok(), "{spec} was rejected");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_contradictions() {
+        let features = DeviceFeatures::default();
+        let specs = [
+            spec().size(0),
+            spec().usage(BufferUsage::empty()),
+            spec().items(12, 16).size(100),
+            spec().device_address(true),
+            // Linear buffers must be per-frame and host-visible.
+            spec()
+                .allocation_strategy(AllocationStrategy::Linear)
+                .buffer_type(BufferType::HostTransient),
+            spec()
+                .allocation_strategy(AllocationStrategy::Linear)
+                .lifetime(BufferLifetime::PerFrame),
+            // Arenas and the Arena strategy only go together.
+            spec().allocation_strategy(AllocationStrategy::Arena),
+            spec().buffer_type(BufferType::DeviceArena),
+            spec().buffer_type(BufferType::IndirectCommand),
+            spec()
+                .buffer_type(BufferType::IndirectCommand)
+                .usage(BufferUsage::INDIRECT | BufferUsage::UNIFORM),
+        ];
+        for spec in specs {
+            assert!(spec.validate(&features).is_err(), "{spec} was accepted");
+        }
+    }
+}
@@ -1,24 +1,75 @@
 use std::env;
-use std::path::PathBuf;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[path = "render/shader/compile.rs"]
+mod compile;
+
+use compile::{Compiler, ShaderFile};
+
+const SHADER_DIR: &str = "assets";
+const INCLUDE_DIR: &str = "assets/include";
 
 fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
-    let shaders = [
-        ("assets/shader.vert", "shader.vert.spv"),
-        ("assets/shader.frag", "shader.frag.spv"),
-    ];
-
-    for (src, dst) in shaders {
-        let out = out_dir.join(dst);
-        let status = Command::new("glslc")
-            .args([src, "-o"])
-            .arg(&out)
-            .status()
-            .expect("failed to run glslc");
-
-        assert!(status.success());
-        println!("cargo:rerun-if-changed={src}");
+    println!("cargo:rerun-if-changed={SHADER_DIR}");
+    println!("cargo:rerun-if-env-changed=GLSLC");
+    println!("cargo:rerun-if-env-changed=VULKAN_SDK");
+
+    let shaders = find_shaders(Path::new(SHADER_DIR));
+    let compiler = Compiler::detect();
+    if !compiler.has_glslc() {
+        println!("cargo:warning=glslc not found; compiling GLSL with naga");
+    }
+
+    let mut failures = Vec::new();
+
+    for shader in &shaders {
+        match compiler.compile(shader, Path::new(INCLUDE_DIR)) {
+            Ok(compiled) => {
+                let out = out_dir.join(format!("{}.spv", shader.name));
+                if let Err(e) = fs::write(&out, &compiled.spirv) {
+                    panic!("failed to write {}: {e}", out.display());
+                }
+                for dep in &compiled.dependencies {
+                    println!("cargo:rerun-if-changed={}", dep.display());
+                }
+            }
+            Err(e) => {
+                for line in e.lines() {
+                    println!("cargo:warning={line}");
+                }
+                failures.push(shader.path.display().to_string());
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("failed to compile shaders: {}", failures.join(", "));
+    }
+}
+
+/// Shader stages directly under `dir`. Files in the include directory are only compiled as
+/// part of the stages that include them.
+fn find_shaders(dir: &Path) -> Vec<ShaderFile> {
+    let mut shaders = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {e}", dir.display()))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| ShaderFile::classify(&path))
+        .collect::<Vec<_>>();
+    shaders.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // `water.frag` and `water.frag.wgsl` would both produce `water.frag.spv`.
+    for (i, shader) in shaders.iter().enumerate() {
+        if let Some(other) = shaders[i + 1..].iter().find(|s| s.name == shader.name) {
+            panic!(
+                "{} and {} both compile to shader '{}'",
+                shader.path.display(),
+                other.path.display(),
+                shader.name
+            );
+        }
     }
+    shaders
 }
@@ -53,7 +53,10 @@ impl Engine {
             thread::Builder::new()
                 .name("render".to_string())
                 .spawn(move || {
-                    if let Err(e) = render_thread(render_caps, control, swapchain_create_caps) {
+                    if let Err(e) =
+                        render_thread(render_caps, control.clone(), swapchain_create_caps)
+                    {
+                        control.set_failed();
                         let _ = error_tx.send(("render".to_string(), e));
                     }
                 })?
@@ -65,9 +68,14 @@ impl Engine {
             thread::Builder::new()
                 .name("upload".to_string())
                 .spawn(move || {
-                    if let Err(e) =
-                        upload_thread(upload_caps, upload_rx, render_tx, complete_tx, control)
-                    {
+                    if let Err(e) = upload_thread(
+                        upload_caps,
+                        upload_rx,
+                        render_tx,
+                        complete_tx,
+                        control.clone(),
+                    ) {
+                        control.set_failed();
                         let _ = error_tx.send(("upload".to_string(), e));
                     }
                 })?
@@ -79,7 +87,8 @@ impl Engine {
             thread::Builder::new()
                 .name("gameplay".to_string())
                 .spawn(move || {
-                    if let Err(e) = gameplay_thread(upload_tx, complete_rx, control) {
+                    if let Err(e) = gameplay_thread(upload_tx, complete_rx, control.clone()) {
+                        control.set_failed();
                         let _ = error_tx.send(("gameplay".to_string(), e));
                     }
                 })?
@@ -120,6 +129,14 @@ impl Engine {
             handle.join().ok();
         }
 
+        if self.control.failed() {
+            anyhow::bail!("an engine thread failed; see the log for its error");
+        }
         Ok(())
     }
+
+    /// Whether a thread asked for the engine to be shut down.
+    pub fn exit_requested(&self) -> bool {
+        self.control.exit_requested()
+    }
 }
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::Context;
 use ash::vk;
 use slotmap::SlotMap;
@@ -10,6 +12,8 @@ use crate::image::resource::OwnedImageInfo;
 use crate::image::resource::OwnedImageViewInfo;
 use crate::image::spec::ImageLifetime;
 use crate::image::spec::ImageViewTarget;
+use crate::leaks::Leak;
+use crate::render::{BindlessHeap, TextureIndex};
 use crate::vulkan::DeviceContext;
 
 use super::{
@@ -44,6 +48,18 @@ impl FrameIndex {
     }
 }
 
+enum RetiredResource {
+    Image(Image),
+    View(ImageView),
+}
+
+/// A resource destroyed while `frame_number` was being recorded. Frames recorded earlier may
+/// still reference it.
+struct Retired {
+    frame_number: u64,
+    resource: RetiredResource,
+}
+
 #[derive(Default)]
 pub struct ImageManager {
     images: SlotMap<ImageKey, Image>,
@@ -51,9 +67,20 @@ pub struct ImageManager {
 
     logical_images: SlotMap<LogicalImageKey, Vec<ImageKey>>,
     logical_image_views: SlotMap<LogicalImageViewKey, Vec<ImageViewKey>>,
+
+    retired: Vec<Retired>,
+    /// The frame being recorded, as last passed to `collect_retired`.
+    frame_number: u64,
+
+    bindless: Option<Arc<BindlessHeap>>,
 }
 
 impl ImageManager {
+    /// Views created from here on are registered in `heap` if their image can be sampled.
+    pub fn set_bindless(&mut self, heap: Arc<BindlessHeap>) {
+        self.bindless = Some(heap);
+    }
+
     #[inline]
     pub fn image_global(&self, key: ImageKey) -> &Image {
         self.images
@@ -124,10 +151,7 @@ impl ImageManager {
 
                 let key = self.images.insert(Image {
                     vk_image,
-                    owned: Some(OwnedImageInfo {
-                        allocation,
-                        _spec: spec,
-                    }),
+                    owned: Some(OwnedImageInfo { allocation, spec }),
                 });
 
                 Ok(CompositeImageKey::Global(key))
@@ -154,7 +178,7 @@ impl ImageManager {
                         vk_image,
                         owned: Some(OwnedImageInfo {
                             allocation,
-                            _spec: spec_clone,
+                            spec: spec_clone,
                         }),
                     }));
                 }
@@ -187,6 +211,7 @@ impl ImageManager {
                 self.image_views.insert(ImageView {
                     vk_image_view: view,
                     owned: None,
+                    bindless: None,
                 })
             })
             .collect();
@@ -216,13 +241,13 @@ impl ImageManager {
                         .create_image_view(&info, None)
                         .context("failed to create ImageView")?
                 };
+                let bindless = self.register_bindless(image, vk_image_view)?;
+                let debug_name = image_debug_name(image).map(str::to_owned);
 
                 let key = self.image_views.insert(ImageView {
                     vk_image_view,
-                    owned: Some(OwnedImageViewInfo {
-                        _spec: spec,
-                        _debug_name: None,
-                    }),
+                    owned: Some(OwnedImageViewInfo { spec, debug_name }),
+                    bindless,
                 });
                 Ok(CompositeImageViewKey::Global(key))
             }
@@ -239,13 +264,14 @@ impl ImageManager {
                             .create_image_view(&info, None)
                             .context("failed to create ImageView")?
                     };
+                    let bindless = self.register_bindless(image, vk_image_view)?;
+                    let debug_name =
+                        image_debug_name(image).map(|name| format!("{}(Frame {:?})", name, frame));
 
                     let key = self.image_views.insert(ImageView {
                         vk_image_view,
-                        owned: Some(OwnedImageViewInfo {
-                            _spec: spec,
-                            _debug_name: None,
-                        }),
+                        owned: Some(OwnedImageViewInfo { spec, debug_name }),
+                        bindless,
                     });
 
                     keys.push(key);
@@ -257,34 +283,178 @@ impl ImageManager {
         }
     }
 
-    pub fn cleanup_per_frames(
+    /// Destroys the image behind `key`, every frame's copy of it for per-frame images, once the
+    /// frames that may still use it have finished. Its views have to be destroyed as well.
+    /// External images are only forgotten.
+    pub fn destroy_image(&mut self, key: CompositeImageKey) {
+        let keys = match key {
+            CompositeImageKey::Global(key) => vec![key],
+            CompositeImageKey::PerFrame(key) => self.logical_images.remove(key).unwrap_or_default(),
+        };
+        for key in keys {
+            if let Some(image) = self.images.remove(key) {
+                self.retire(RetiredResource::Image(image));
+            }
+        }
+    }
+
+    /// Like `destroy_image`, for views. The bindless slot is freed along with the view.
+    pub fn destroy_image_view(&mut self, key: CompositeImageViewKey) {
+        let keys = match key {
+            CompositeImageViewKey::Global(key) => vec![key],
+            CompositeImageViewKey::PerFrame(key) => {
+                self.logical_image_views.remove(key).unwrap_or_default()
+            }
+        };
+        for key in keys {
+            if let Some(view) = self.image_views.remove(key) {
+                self.retire(RetiredResource::View(view));
+            }
+        }
+    }
+
+    fn retire(&mut self, resource: RetiredResource) {
+        self.retired.push(Retired {
+            frame_number: self.frame_number,
+            resource,
+        });
+    }
+
+    /// Destroys retired images and views once every frame that could reference them has
+    /// retired. Call at the start of each frame: resources destroyed from here on are retired
+    /// against `frame_number`.
+    pub fn collect_retired(
+        &mut self,
+        device: &ash::Device,
+        allocator: &vk_mem::Allocator,
+        frame_number: u64,
+        frame_count: u64,
+    ) {
+        let (done, pending) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|retired| retired.frame_number + frame_count <= frame_number);
+        self.retired = pending;
+        self.frame_number = frame_number;
+        for retired in done {
+            self.destroy_now(device, allocator, retired.resource);
+        }
+    }
+
+    /// Owned images and views nobody destroyed. External images, such as the swapchain's,
+    /// belong to whoever registered them and are not counted.
+    pub fn leaks(&self, allocator: &vk_mem::Allocator) -> Vec<Leak> {
+        let images = self.images.values().filter_map(|image| {
+            let owned = image.owned.as_ref()?;
+            Some(Leak {
+                kind: "image",
+                description: owned.spec.to_string(),
+                bytes: Some(allocator.get_allocation_info(&owned.allocation).size),
+            })
+        });
+
+        // Views own no memory; the image they view reports it, and counting it again per view
+        // would inflate the total.
+        let views = self.image_views.values().filter_map(|view| {
+            let owned = view.owned.as_ref()?;
+            Some(Leak {
+                kind: "image view",
+                description: format!(
+                    "{:?} view of {} (format={:?}, mips={}+{}, layers={}+{})",
+                    owned.spec.view_type,
+                    owned.debug_name.as_deref().unwrap_or("<unnamed>"),
+                    owned.spec.format,
+                    owned.spec.base_mip_level,
+                    owned.spec.level_count,
+                    owned.spec.base_array_layer,
+                    owned.spec.layer_count
+                ),
+                bytes: None,
+            })
+        });
+
+        views.chain(images).collect()
+    }
+
+    /// Frees every image and view still alive, retired or not. The device must be idle.
+    pub fn destroy(
         &mut self,
         device: &ash::Device,
         allocator: &vk_mem::Allocator,
     ) -> anyhow::Result<()> {
-        for (_, views) in self.logical_image_views.drain() {
-            for key in views {
-                if let Some(view) = self.image_views.remove(key)
-                    && view.owned.is_some()
-                {
+        self.logical_image_views.clear();
+        self.logical_images.clear();
+
+        let (retired_views, retired_images): (Vec<_>, Vec<_>) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .map(|retired| retired.resource)
+            .partition(|resource| matches!(resource, RetiredResource::View(_)));
+
+        // Views first, since they may be of images going with them.
+        let views = self
+            .image_views
+            .drain()
+            .map(|(_, view)| RetiredResource::View(view))
+            .collect::<Vec<_>>();
+        let images = self
+            .images
+            .drain()
+            .map(|(_, image)| RetiredResource::Image(image))
+            .collect::<Vec<_>>();
+
+        for resource in views
+            .into_iter()
+            .chain(retired_views)
+            .chain(images)
+            .chain(retired_images)
+        {
+            self.destroy_now(device, allocator, resource);
+        }
+        Ok(())
+    }
+
+    fn destroy_now(
+        &self,
+        device: &ash::Device,
+        allocator: &vk_mem::Allocator,
+        resource: RetiredResource,
+    ) {
+        match resource {
+            RetiredResource::View(view) => {
+                if let (Some(heap), Some(index)) = (&self.bindless, view.bindless) {
+                    heap.release_image_view(index);
+                }
+                if view.owned.is_some() {
                     unsafe { device.destroy_image_view(view.vk_image_view, None) }
                 }
             }
-        }
-
-        for (_, images) in self.logical_images.drain() {
-            for key in images {
-                if let Some(image) = self.images.remove(key)
-                    && let Some(mut owned) = image.owned
-                {
+            RetiredResource::Image(image) => {
+                if let Some(mut owned) = image.owned {
                     unsafe {
                         allocator.destroy_image(image.vk_image, &mut owned.allocation);
                     }
                 }
             }
         }
+    }
 
-        Ok(())
+    fn register_bindless(
+        &self,
+        image: &Image,
+        view: vk::ImageView,
+    ) -> anyhow::Result<Option<TextureIndex>> {
+        let Some(heap) = &self.bindless else {
+            return Ok(None);
+        };
+        let sampled = image
+            .owned
+            .as_ref()
+            .is_some_and(|owned| owned.spec.usage.contains(vk::ImageUsageFlags::SAMPLED));
+        if !sampled {
+            return Ok(None);
+        }
+        heap.register_image_view(view)
+            .map(Some)
+            .context("failed to register image view in the bindless heap")
     }
 }
 
@@ -308,3 +478,7 @@ fn with_image_create_info<R>(
 
     f(&ici, &aci)
 }
+
+fn image_debug_name(image: &Image) -> Option<&str> {
+    image.owned.as_ref()?.spec.debug_name.as_deref()
+}
@@ -5,4 +5,5 @@ mod spec;
 
 pub use keys::*;
 pub use manager::{CompositeImageKey, CompositeImageViewKey, FrameIndex, ImageManager};
+pub use resource::ImageView;
 pub use spec::{ImageLifetime, ImageSpec, ImageViewSpec, ImageViewTarget, ResizePolicy};
@@ -1,10 +1,13 @@
 use ash::vk;
 
-use crate::image::spec::{ImageSpec, ImageViewSpec};
+use crate::{
+    image::spec::{ImageSpec, ImageViewSpec},
+    render::TextureIndex,
+};
 
 pub struct OwnedImageInfo {
     pub allocation: vk_mem::Allocation,
-    pub _spec: ImageSpec,
+    pub spec: ImageSpec,
 }
 
 pub struct Image {
@@ -13,11 +16,14 @@ pub struct Image {
 }
 
 pub struct OwnedImageViewInfo {
-    pub _spec: ImageViewSpec,
-    pub _debug_name: Option<&'static str>,
+    pub spec: ImageViewSpec,
+    /// The image's debug name, for leak reports.
+    pub debug_name: Option<String>,
 }
 
 pub struct ImageView {
     pub vk_image_view: vk::ImageView,
     pub owned: Option<OwnedImageViewInfo>,
+    /// Slot in the bindless heap, for views of images created with `SAMPLED` usage.
+    pub bindless: Option<TextureIndex>,
 }
@@ -92,6 +92,11 @@ impl ImageSpec {
         self.initial_layout = layout;
         self
     }
+
+    pub fn debug_name(mut self, name: impl Into<String>) -> Self {
+        self.debug_name = Some(name.into());
+        self
+    }
 }
 
 impl fmt::Display for ImageSpec {
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use ash::vk;
+
+/// Set to any value to fail shutdown when resources were leaked, so tests catch them.
+const STRICT_LEAKS_ENV: &str = "SKELETON_STRICT_LEAKS";
+
+/// A resource still alive at shutdown that its owner never destroyed.
+pub struct Leak {
+    pub kind: &'static str,
+    /// Debug name and spec, or whatever else identifies the resource.
+    pub description: String,
+    /// Size of its memory allocation, for resources that have one. Views, samplers, pipelines
+    /// and heap slots don't.
+    pub bytes: Option<vk::DeviceSize>,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.description)?;
+        if let Some(bytes) = self.bytes {
+            write!(f, " ({bytes} bytes)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Leaks gathered from every manager before they free what is left.
+#[derive(Default)]
+pub struct LeakReport {
+    leaks: Vec<Leak>,
+}
+
+impl LeakReport {
+    pub fn extend(&mut self, leaks: impl IntoIterator<Item = Leak>) {
+        self.leaks.extend(leaks);
+    }
+
+    /// Logs every leak. With `SKELETON_STRICT_LEAKS` set, any leak is also an error.
+    pub fn finish(self) -> anyhow::Result<()> {
+        if self.leaks.is_empty() {
+            log::debug!("No resources leaked at shutdown");
+            return Ok(());
+        }
+
+        let bytes: vk::DeviceSize = self.leaks.iter().filter_map(|leak| leak.bytes).sum();
+        log::warn!(
+            "{} resource(s) still alive at shutdown, {} bytes of memory:",
+            self.leaks.len(),
+            bytes
+        );
+        for leak in &self.leaks {
+            log::warn!("  {leak}");
+        }
+
+        if std::env::var_os(STRICT_LEAKS_ENV).is_some() {
+            anyhow::bail!(
+                "{} resource(s) leaked at shutdown ({STRICT_LEAKS_ENV} is set)",
+                self.leaks.len()
+            );
+        }
+        Ok(())
+    }
+}
@@ -12,8 +12,10 @@ mod caps;
 mod engine;
 mod gameplay;
 mod image;
+mod leaks;
 mod messages;
 mod render;
+mod sampler;
 mod upload;
 mod vulkan;
 
@@ -64,6 +66,7 @@ fn main() -> anyhow::Result<()> {
 
     if let AppState::FatalError(e) = &application.app_state {
         log::error!("{:?}", e);
+        std::process::exit(1);
     }
 
     Ok(())
@@ -1,4 +1,4 @@
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 #[derive(Debug)]
 pub struct UploadRequest {
@@ -25,15 +25,38 @@ pub enum ShutdownPhase {
 
 pub struct EngineControl {
     phase: AtomicU8,
+    exit_requested: AtomicBool,
+    failed: AtomicBool,
 }
 
 impl EngineControl {
     pub fn new() -> Self {
         Self {
             phase: AtomicU8::new(ShutdownPhase::Running as u8),
+            exit_requested: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
         }
     }
 
+    /// Asks the app to shut the engine down, as if the window had been closed.
+    pub fn request_exit(&self) {
+        self.exit_requested.store(true, Ordering::Release);
+    }
+
+    pub fn exit_requested(&self) -> bool {
+        self.exit_requested.load(Ordering::Acquire)
+    }
+
+    /// Marks the run as failed, so shutdown reports an error even when a thread fails while
+    /// it is already stopping.
+    pub fn set_failed(&self) {
+        self.failed.store(true, Ordering::Release);
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
+    }
+
     pub fn set_phase(&self, phase: ShutdownPhase) {
         self.phase.store(phase as u8, Ordering::Release);
     }
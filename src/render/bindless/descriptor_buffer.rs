@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use ash::vk;
+use vk_mem::Alloc;
+
+use crate::{
+    render::bindless::{
+        BUFFER_BINDING, BindlessLayout, Capacities, RESOURCE_SET, SAMPLER_BINDING, TEXTURE_BINDING,
+    },
+    vulkan::DeviceContext,
+};
+
+/// Host-mapped pointer into a descriptor buffer. Only written through `&mut` while the heap's
+/// mutex is held.
+struct Mapped(*mut u8);
+
+unsafe impl Send for Mapped {}
+
+/// One descriptor set's worth of descriptors living in a buffer (`VK_EXT_descriptor_buffer`).
+struct SetBuffer {
+    buffer: vk::Buffer,
+    allocation: vk_mem::Allocation,
+    address: vk::DeviceAddress,
+    usage: vk::BufferUsageFlags,
+    mapped: Mapped,
+    /// Offset of each binding's first descriptor, indexed by binding number.
+    binding_offsets: Vec<vk::DeviceSize>,
+}
+
+impl SetBuffer {
+    fn new(
+        device_context: &DeviceContext,
+        ext: &ash::ext::descriptor_buffer::Device,
+        allocator: &vk_mem::Allocator,
+        set_layout: vk::DescriptorSetLayout,
+        binding_count: u32,
+        usage: vk::BufferUsageFlags,
+        name: &str,
+    ) -> anyhow::Result<Self> {
+        let size = unsafe { ext.get_descriptor_set_layout_size(set_layout) };
+        let binding_offsets = (0..binding_count)
+            .map(|binding| unsafe {
+                ext.get_descriptor_set_layout_binding_offset(set_layout, binding)
+            })
+            .collect();
+
+        let usage = usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let alloc_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::Auto,
+            flags: vk_mem::AllocationCreateFlags::MAPPED
+                | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            required_flags: vk::MemoryPropertyFlags::HOST_COHERENT,
+            ..Default::default()
+        };
+
+        let (buffer, allocation) = unsafe {
+            allocator
+                .create_buffer(&buffer_info, &alloc_info)
+                .with_context(|| format!("failed to create {name} ({size} bytes)"))?
+        };
+        device_context.name_object(buffer, name)?;
+
+        let mapped = allocator.get_allocation_info(&allocation).mapped_data as *mut u8;
+        if mapped.is_null() {
+            anyhow::bail!("{name} is not host mapped");
+        }
+
+        let address = unsafe {
+            device_context
+                .device
+                .get_buffer_device_address(&vk::BufferDeviceAddressInfo::default().buffer(buffer))
+        };
+
+        Ok(Self {
+            buffer,
+            allocation,
+            address,
+            usage,
+            mapped: Mapped(mapped),
+            binding_offsets,
+        })
+    }
+
+    /// The bytes of descriptor `index` of `binding`.
+    fn slot(&mut self, binding: u32, index: u32, descriptor_size: usize) -> &mut [u8] {
+        let offset =
+            self.binding_offsets[binding as usize] as usize + index as usize * descriptor_size;
+        // The index pools never hand out a slot past the capacity the layout was sized for.
+        unsafe { std::slice::from_raw_parts_mut(self.mapped.0.add(offset), descriptor_size) }
+    }
+}
+
+pub(super) struct DescriptorBufferHeap {
+    device: Arc<ash::Device>,
+    ext: ash::ext::descriptor_buffer::Device,
+    resource_layout: vk::DescriptorSetLayout,
+    sampler_layout: vk::DescriptorSetLayout,
+    resources: SetBuffer,
+    samplers: SetBuffer,
+    sampled_image_size: usize,
+    storage_buffer_size: usize,
+    sampler_size: usize,
+}
+
+impl DescriptorBufferHeap {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device_context: &DeviceContext,
+        allocator: &vk_mem::Allocator,
+        capacities: &Capacities,
+    ) -> anyhow::Result<(Self, BindlessLayout)> {
+        let device = &device_context.device;
+        let ext = ash::ext::descriptor_buffer::Device::new(instance, device);
+
+        let mut properties = vk::PhysicalDeviceDescriptorBufferPropertiesEXT::default();
+        unsafe {
+            instance.get_physical_device_properties2(
+                physical_device,
+                &mut vk::PhysicalDeviceProperties2::default().push_next(&mut properties),
+            );
+        }
+
+        let resource_bindings = vec![
+            (
+                TEXTURE_BINDING,
+                vk::DescriptorType::SAMPLED_IMAGE,
+                capacities.textures,
+            ),
+            (
+                BUFFER_BINDING,
+                vk::DescriptorType::STORAGE_BUFFER,
+                capacities.buffers,
+            ),
+        ];
+        let sampler_bindings = vec![(
+            SAMPLER_BINDING,
+            vk::DescriptorType::SAMPLER,
+            capacities.samplers,
+        )];
+
+        let resource_layout = create_set_layout(device_context, &resource_bindings)?;
+        let sampler_layout = create_set_layout(device_context, &sampler_bindings)?;
+
+        let resources = SetBuffer::new(
+            device_context,
+            &ext,
+            allocator,
+            resource_layout,
+            resource_bindings.len() as u32,
+            vk::BufferUsageFlags::RESOURCE_DESCRIPTOR_BUFFER_EXT,
+            "BindlessResourceDescriptors",
+        )?;
+        let samplers = SetBuffer::new(
+            device_context,
+            &ext,
+            allocator,
+            sampler_layout,
+            sampler_bindings.len() as u32,
+            vk::BufferUsageFlags::SAMPLER_DESCRIPTOR_BUFFER_EXT,
+            "BindlessSamplerDescriptors",
+        )?;
+
+        let layout = BindlessLayout {
+            set_layouts: vec![resource_layout, sampler_layout],
+            bindings: vec![resource_bindings, sampler_bindings],
+            create_flags: vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT,
+            exclusive: true,
+        };
+
+        Ok((
+            Self {
+                device: device.clone(),
+                ext,
+                resource_layout,
+                sampler_layout,
+                resources,
+                samplers,
+                sampled_image_size: properties.sampled_image_descriptor_size,
+                storage_buffer_size: properties.storage_buffer_descriptor_size,
+                sampler_size: properties.sampler_descriptor_size,
+            },
+            layout,
+        ))
+    }
+
+    pub fn write_texture(&mut self, index: u32, view: vk::ImageView) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let info = vk::DescriptorGetInfoEXT::default()
+            .ty(vk::DescriptorType::SAMPLED_IMAGE)
+            .data(vk::DescriptorDataEXT {
+                p_sampled_image: &image_info,
+            });
+        let slot = self
+            .resources
+            .slot(TEXTURE_BINDING, index, self.sampled_image_size);
+        unsafe { self.ext.get_descriptor(&info, slot) };
+    }
+
+    pub fn write_buffer(&mut self, index: u32, address: vk::DeviceAddress, range: vk::DeviceSize) {
+        let address_info = vk::DescriptorAddressInfoEXT::default()
+            .address(address)
+            .range(range);
+        let info = vk::DescriptorGetInfoEXT::default()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .data(vk::DescriptorDataEXT {
+                p_storage_buffer: &address_info,
+            });
+        let slot = self
+            .resources
+            .slot(BUFFER_BINDING, index, self.storage_buffer_size);
+        unsafe { self.ext.get_descriptor(&info, slot) };
+    }
+
+    pub fn write_sampler(&mut self, index: u32, sampler: vk::Sampler) {
+        let info = vk::DescriptorGetInfoEXT::default()
+            .ty(vk::DescriptorType::SAMPLER)
+            .data(vk::DescriptorDataEXT {
+                p_sampler: &sampler,
+            });
+        let slot = self
+            .samplers
+            .slot(SAMPLER_BINDING, index, self.sampler_size);
+        unsafe { self.ext.get_descriptor(&info, slot) };
+    }
+
+    pub fn bind(
+        &self,
+        cmd: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+    ) {
+        let bindings = [&self.resources, &self.samplers].map(|set| {
+            vk::DescriptorBufferBindingInfoEXT::default()
+                .address(set.address)
+                .usage(set.usage)
+        });
+        unsafe {
+            self.ext.cmd_bind_descriptor_buffers(cmd, &bindings);
+            self.ext.cmd_set_descriptor_buffer_offsets(
+                cmd,
+                bind_point,
+                layout,
+                RESOURCE_SET,
+                // Buffer indices into `bindings` for the resource and sampler sets.
+                &[0, 1],
+                &[0, 0],
+            );
+        }
+    }
+
+    pub fn destroy(&mut self, allocator: &vk_mem::Allocator) {
+        for set in [&mut self.resources, &mut self.samplers] {
+            unsafe { allocator.destroy_buffer(set.buffer, &mut set.allocation) };
+        }
+        unsafe {
+            self.device
+                .destroy_descriptor_set_layout(self.resource_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.sampler_layout, None);
+        }
+    }
+}
+
+fn create_set_layout(
+    device_context: &DeviceContext,
+    bindings: &[(u32, vk::DescriptorType, u32)],
+) -> anyhow::Result<vk::DescriptorSetLayout> {
+    let vk_bindings = bindings
+        .iter()
+        .map(|&(binding, ty, count)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+        })
+        .collect::<Vec<_>>();
+
+    let layout = unsafe {
+        device_context
+            .device
+            .create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default()
+                    .flags(vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT)
+                    .bindings(&vk_bindings),
+                None,
+            )
+            .context("failed to create bindless descriptor set layout")?
+    };
+    device_context.name_object(
+        layout,
+        format!("BindlessSetLayout({} bindings)", bindings.len()),
+    )?;
+    Ok(layout)
+}
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use ash::vk;
+
+use crate::{
+    render::bindless::{
+        BUFFER_BINDING, BindlessLayout, Capacities, RESOURCE_SET, SAMPLER_BINDING, TEXTURE_BINDING,
+    },
+    vulkan::DeviceContext,
+};
+
+const BINDING_FLAGS: vk::DescriptorBindingFlags = vk::DescriptorBindingFlags::from_raw(
+    vk::DescriptorBindingFlags::PARTIALLY_BOUND.as_raw()
+        | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND.as_raw()
+        | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING.as_raw(),
+);
+
+/// The heap as two classic descriptor sets allocated once from an update-after-bind pool
+/// (Vulkan 1.2 descriptor indexing). Slots are written with `vkUpdateDescriptorSets` while
+/// frames in flight keep using the others.
+pub(super) struct DescriptorIndexingHeap {
+    device: Arc<ash::Device>,
+    resource_layout: vk::DescriptorSetLayout,
+    sampler_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    resource_set: vk::DescriptorSet,
+    sampler_set: vk::DescriptorSet,
+}
+
+impl DescriptorIndexingHeap {
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device_context: &DeviceContext,
+        capacities: &mut Capacities,
+    ) -> anyhow::Result<(Self, BindlessLayout)> {
+        let device = &device_context.device;
+
+        let mut properties12 = vk::PhysicalDeviceVulkan12Properties::default();
+        unsafe {
+            instance.get_physical_device_properties2(
+                physical_device,
+                &mut vk::PhysicalDeviceProperties2::default().push_next(&mut properties12),
+            );
+        }
+        clamp_update_after_bind(capacities, &properties12);
+
+        let resource_bindings = vec![
+            (
+                TEXTURE_BINDING,
+                vk::DescriptorType::SAMPLED_IMAGE,
+                capacities.textures,
+            ),
+            (
+                BUFFER_BINDING,
+                vk::DescriptorType::STORAGE_BUFFER,
+                capacities.buffers,
+            ),
+        ];
+        let sampler_bindings = vec![(
+            SAMPLER_BINDING,
+            vk::DescriptorType::SAMPLER,
+            capacities.samplers,
+        )];
+
+        let resource_layout = create_set_layout(device_context, &resource_bindings)?;
+        let sampler_layout = create_set_layout(device_context, &sampler_bindings)?;
+
+        let pool_sizes = resource_bindings
+            .iter()
+            .chain(&sampler_bindings)
+            .map(|&(_, ty, descriptor_count)| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count,
+            })
+            .collect::<Vec<_>>();
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::default()
+                        .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                        .max_sets(2)
+                        .pool_sizes(&pool_sizes),
+                    None,
+                )
+                .context("failed to create bindless descriptor pool")?
+        };
+        device_context.name_object(pool, "BindlessDescriptorPool")?;
+
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(pool)
+                        .set_layouts(&[resource_layout, sampler_layout]),
+                )
+                .context("failed to allocate bindless descriptor sets")?
+        };
+        device_context.name_object(sets[0], "BindlessResources")?;
+        device_context.name_object(sets[1], "BindlessSamplers")?;
+
+        let layout = BindlessLayout {
+            set_layouts: vec![resource_layout, sampler_layout],
+            bindings: vec![resource_bindings, sampler_bindings],
+            create_flags: vk::PipelineCreateFlags::empty(),
+            exclusive: false,
+        };
+
+        Ok((
+            Self {
+                device: device.clone(),
+                resource_layout,
+                sampler_layout,
+                pool,
+                resource_set: sets[0],
+                sampler_set: sets[1],
+            },
+            layout,
+        ))
+    }
+
+    pub fn write_texture(&mut self, index: u32, view: vk::ImageView) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.resource_set)
+            .dst_binding(TEXTURE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    pub fn write_buffer(&mut self, index: u32, buffer: vk::Buffer, range: vk::DeviceSize) {
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.resource_set)
+            .dst_binding(BUFFER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(std::slice::from_ref(&buffer_info));
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    pub fn write_sampler(&mut self, index: u32, sampler: vk::Sampler) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler,
+            ..Default::default()
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.sampler_set)
+            .dst_binding(SAMPLER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    pub fn bind(
+        &self,
+        cmd: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+    ) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                cmd,
+                bind_point,
+                layout,
+                RESOURCE_SET,
+                &[self.resource_set, self.sampler_set],
+                &[],
+            );
+        }
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            // Frees both sets with it.
+            self.device.destroy_descriptor_pool(self.pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.resource_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.sampler_layout, None);
+        }
+    }
+}
+
+/// Update-after-bind arrays have their own, sometimes lower, limits, including one across all
+/// resource types. Textures and buffers give way until everything fits that one.
+fn clamp_update_after_bind(
+    capacities: &mut Capacities,
+    properties: &vk::PhysicalDeviceVulkan12Properties,
+) {
+    capacities.textures = capacities
+        .textures
+        .min(properties.max_per_stage_descriptor_update_after_bind_sampled_images);
+    capacities.buffers = capacities
+        .buffers
+        .min(properties.max_per_stage_descriptor_update_after_bind_storage_buffers);
+    capacities.samplers = capacities
+        .samplers
+        .min(properties.max_per_stage_descriptor_update_after_bind_samplers);
+
+    let budget = properties.max_per_stage_update_after_bind_resources;
+    while capacities.textures + capacities.buffers + capacities.samplers > budget
+        && (capacities.textures > 1 || capacities.buffers > 1)
+    {
+        capacities.textures = (capacities.textures / 2).max(1);
+        capacities.buffers = (capacities.buffers / 2).max(1);
+    }
+}
+
+fn create_set_layout(
+    device_context: &DeviceContext,
+    bindings: &[(u32, vk::DescriptorType, u32)],
+) -> anyhow::Result<vk::DescriptorSetLayout> {
+    let vk_bindings = bindings
+        .iter()
+        .map(|&(binding, ty, count)| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+        })
+        .collect::<Vec<_>>();
+    let binding_flags = vec![BINDING_FLAGS; bindings.len()];
+    let mut flags_info =
+        vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
+
+    let layout = unsafe {
+        device_context
+            .device
+            .create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default()
+                    .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                    .bindings(&vk_bindings)
+                    .push_next(&mut flags_info),
+                None,
+            )
+            .context("failed to create bindless descriptor set layout")?
+    };
+    device_context.name_object(
+        layout,
+        format!("BindlessSetLayout({} bindings)", bindings.len()),
+    )?;
+    Ok(layout)
+}
@@ -0,0 +1,378 @@
+mod descriptor_buffer;
+mod descriptor_indexing;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Context;
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{leaks::Leak, vulkan::DeviceContext};
+
+use descriptor_buffer::DescriptorBufferHeap;
+use descriptor_indexing::DescriptorIndexingHeap;
+
+/// First of the heap's sets, holding sampled images and storage buffers. Samplers live in
+/// the set after it.
+pub const RESOURCE_SET: u32 = 0;
+
+pub const TEXTURE_BINDING: u32 = 0;
+pub const BUFFER_BINDING: u32 = 1;
+pub const SAMPLER_BINDING: u32 = 0;
+
+const MAX_TEXTURES: u32 = 16 * 1024;
+const MAX_BUFFERS: u32 = 16 * 1024;
+const MAX_SAMPLERS: u32 = 256;
+
+macro_rules! bindless_index {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+        pub struct $name(u32);
+
+        impl $name {
+            /// The value shaders index the heap with.
+            pub fn raw(self) -> u32 {
+                self.0
+            }
+        }
+
+        // A plain u32, so it can be written straight into push constants.
+        unsafe impl Zeroable for $name {}
+        unsafe impl Pod for $name {}
+    };
+}
+
+bindless_index!(
+    /// Slot of a sampled image view in `textures[]`.
+    TextureIndex
+);
+bindless_index!(
+    /// Slot of a storage buffer in `buffers[]`.
+    BufferIndex
+);
+bindless_index!(
+    /// Slot of a sampler in `samplers[]`.
+    SamplerIndex
+);
+
+/// What the heap's descriptor sets look like, so the layout cache can substitute them for the
+/// sets a bindless shader declares.
+#[derive(Clone, Debug)]
+pub struct BindlessLayout {
+    /// Indexed by set number, starting at `RESOURCE_SET`.
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    /// `(binding, type, capacity)` per set, in the same order.
+    pub bindings: Vec<Vec<(u32, vk::DescriptorType, u32)>>,
+    pub create_flags: vk::PipelineCreateFlags,
+    /// Descriptor buffer pipelines cannot also use classic descriptor sets; descriptor
+    /// indexing pipelines can put their own sets after the heap's.
+    pub exclusive: bool,
+}
+
+impl BindlessLayout {
+    pub fn is_prefix_of(&self, set_layouts: &[vk::DescriptorSetLayout]) -> bool {
+        set_layouts.starts_with(&self.set_layouts)
+    }
+}
+
+struct Capacities {
+    textures: u32,
+    buffers: u32,
+    samplers: u32,
+}
+
+enum Backend {
+    DescriptorBuffer(Box<DescriptorBufferHeap>),
+    DescriptorIndexing(DescriptorIndexingHeap),
+}
+
+impl Backend {
+    fn write_texture(&mut self, index: u32, view: vk::ImageView) {
+        match self {
+            Backend::DescriptorBuffer(heap) => heap.write_texture(index, view),
+            Backend::DescriptorIndexing(heap) => heap.write_texture(index, view),
+        }
+    }
+
+    fn write_buffer(
+        &mut self,
+        index: u32,
+        buffer: vk::Buffer,
+        address: Option<vk::DeviceAddress>,
+        range: vk::DeviceSize,
+    ) -> anyhow::Result<()> {
+        match self {
+            Backend::DescriptorBuffer(heap) => {
+                let address = address.context("descriptor buffers need the buffer's address")?;
+                heap.write_buffer(index, address, range);
+            }
+            Backend::DescriptorIndexing(heap) => heap.write_buffer(index, buffer, range),
+        }
+        Ok(())
+    }
+
+    fn write_sampler(&mut self, index: u32, sampler: vk::Sampler) {
+        match self {
+            Backend::DescriptorBuffer(heap) => heap.write_sampler(index, sampler),
+            Backend::DescriptorIndexing(heap) => heap.write_sampler(index, sampler),
+        }
+    }
+
+    fn bind(
+        &self,
+        cmd: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+    ) {
+        match self {
+            Backend::DescriptorBuffer(heap) => heap.bind(cmd, bind_point, layout),
+            Backend::DescriptorIndexing(heap) => heap.bind(cmd, bind_point, layout),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::DescriptorBuffer(_) => "descriptor buffer",
+            Backend::DescriptorIndexing(_) => "descriptor indexing",
+        }
+    }
+
+    fn destroy(&mut self, allocator: &vk_mem::Allocator) {
+        match self {
+            Backend::DescriptorBuffer(heap) => heap.destroy(allocator),
+            Backend::DescriptorIndexing(heap) => heap.destroy(),
+        }
+    }
+}
+
+/// Hands out slots of one descriptor array. Released slots are reused first, so indices stay
+/// dense.
+struct IndexPool {
+    name: &'static str,
+    next: u32,
+    free: Vec<u32>,
+    capacity: u32,
+}
+
+impl IndexPool {
+    fn new(name: &'static str, capacity: u32) -> Self {
+        Self {
+            name,
+            next: 0,
+            free: Vec::new(),
+            capacity,
+        }
+    }
+
+    fn allocate(&mut self) -> anyhow::Result<u32> {
+        if let Some(index) = self.free.pop() {
+            return Ok(index);
+        }
+        if self.next == self.capacity {
+            anyhow::bail!(
+                "bindless {} heap is full ({} slots)",
+                self.name,
+                self.capacity
+            );
+        }
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+
+    fn release(&mut self, index: u32) {
+        debug_assert!(index < self.next && !self.free.contains(&index));
+        self.free.push(index);
+    }
+
+    fn in_use(&self) -> u32 {
+        self.next - self.free.len() as u32
+    }
+}
+
+struct HeapState {
+    backend: Option<Backend>,
+    textures: IndexPool,
+    buffers: IndexPool,
+    samplers: IndexPool,
+}
+
+/// Global descriptor heap every image view, buffer and sampler can be registered in. Shaders
+/// include `bindless.glsl` and index the arrays with values passed in push constants, so
+/// pipelines never rebind per-draw descriptor sets.
+///
+/// A released index may be handed out again immediately; callers release only once no frame
+/// in flight can still read it.
+pub struct BindlessHeap {
+    layout: BindlessLayout,
+    state: Mutex<HeapState>,
+}
+
+impl BindlessHeap {
+    /// Builds the heap on descriptor buffers where the device has them and on update-after-bind
+    /// descriptor sets otherwise. Returns `None` when the device supports neither; pipelines
+    /// with runtime-sized arrays then fail to build and everything else is unaffected.
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device_context: &DeviceContext,
+        allocator: &vk_mem::Allocator,
+    ) -> anyhow::Result<Option<Arc<Self>>> {
+        let features = device_context.features;
+        if !features.runtime_descriptor_array || !features.non_uniform_indexing {
+            log::warn!("Bindless disabled: device lacks runtime descriptor arrays");
+            return Ok(None);
+        }
+
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+        let mut capacities = Capacities {
+            textures: MAX_TEXTURES.min(limits.max_per_stage_descriptor_sampled_images),
+            buffers: MAX_BUFFERS.min(limits.max_per_stage_descriptor_storage_buffers),
+            samplers: MAX_SAMPLERS.min(limits.max_per_stage_descriptor_samplers),
+        };
+
+        let (backend, layout) = if features.descriptor_buffer {
+            let (heap, layout) = DescriptorBufferHeap::new(
+                instance,
+                physical_device,
+                device_context,
+                allocator,
+                &capacities,
+            )?;
+            (Backend::DescriptorBuffer(Box::new(heap)), layout)
+        } else if features.descriptor_indexing {
+            let (heap, layout) = DescriptorIndexingHeap::new(
+                instance,
+                physical_device,
+                device_context,
+                &mut capacities,
+            )?;
+            (Backend::DescriptorIndexing(heap), layout)
+        } else {
+            log::warn!(
+                "Bindless disabled: device supports neither descriptor buffers nor \
+                 update-after-bind descriptor indexing"
+            );
+            return Ok(None);
+        };
+
+        log::info!(
+            "Bindless heap ({}): {} textures, {} buffers, {} samplers",
+            backend.name(),
+            capacities.textures,
+            capacities.buffers,
+            capacities.samplers
+        );
+
+        Ok(Some(Arc::new(Self {
+            layout,
+            state: Mutex::new(HeapState {
+                backend: Some(backend),
+                textures: IndexPool::new("texture", capacities.textures),
+                buffers: IndexPool::new("buffer", capacities.buffers),
+                samplers: IndexPool::new("sampler", capacities.samplers),
+            }),
+        })))
+    }
+
+    pub fn layout(&self) -> &BindlessLayout {
+        &self.layout
+    }
+
+    /// Whether buffers registered here need `SHADER_DEVICE_ADDRESS` usage.
+    pub fn needs_buffer_addresses(&self) -> bool {
+        matches!(self.state().backend, Some(Backend::DescriptorBuffer(_)))
+    }
+
+    /// The view must be in `SHADER_READ_ONLY_OPTIMAL` whenever a shader samples it.
+    pub fn register_image_view(&self, view: vk::ImageView) -> anyhow::Result<TextureIndex> {
+        let mut state = self.state();
+        let index = state.textures.allocate()?;
+        if let Some(backend) = &mut state.backend {
+            backend.write_texture(index, view);
+        }
+        Ok(TextureIndex(index))
+    }
+
+    /// Registers the first `range` bytes of a buffer created with `STORAGE_BUFFER` usage, and
+    /// `SHADER_DEVICE_ADDRESS` too when `needs_buffer_addresses` says so, in which case its
+    /// `address` must be given.
+    pub fn register_buffer(
+        &self,
+        buffer: vk::Buffer,
+        address: Option<vk::DeviceAddress>,
+        range: vk::DeviceSize,
+    ) -> anyhow::Result<BufferIndex> {
+        let mut state = self.state();
+        let index = state.buffers.allocate()?;
+        if let Some(backend) = &mut state.backend
+            && let Err(err) = backend.write_buffer(index, buffer, address, range)
+        {
+            state.buffers.release(index);
+            return Err(err);
+        }
+        Ok(BufferIndex(index))
+    }
+
+    pub fn register_sampler(&self, sampler: vk::Sampler) -> anyhow::Result<SamplerIndex> {
+        let mut state = self.state();
+        let index = state.samplers.allocate()?;
+        if let Some(backend) = &mut state.backend {
+            backend.write_sampler(index, sampler);
+        }
+        Ok(SamplerIndex(index))
+    }
+
+    pub fn release_image_view(&self, index: TextureIndex) {
+        self.state().textures.release(index.0);
+    }
+
+    pub fn release_buffer(&self, index: BufferIndex) {
+        self.state().buffers.release(index.0);
+    }
+
+    pub fn release_sampler(&self, index: SamplerIndex) {
+        self.state().samplers.release(index.0);
+    }
+
+    /// Binds the heap's sets to `cmd`. `layout` must have been built with the heap's layout.
+    pub fn bind(
+        &self,
+        cmd: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+    ) {
+        if let Some(backend) = &self.state().backend {
+            backend.bind(cmd, bind_point, layout);
+        }
+    }
+
+    /// Slots still registered. The image, buffer and sampler managers release theirs when
+    /// they are destroyed, so call this after them.
+    pub fn leaks(&self) -> Vec<Leak> {
+        let state = self.state();
+        [&state.textures, &state.buffers, &state.samplers]
+            .into_iter()
+            .filter(|pool| pool.in_use() > 0)
+            .map(|pool| Leak {
+                kind: "bindless slot",
+                description: format!("{} {}(s) still registered", pool.in_use(), pool.name),
+                bytes: None,
+            })
+            .collect()
+    }
+
+    /// Frees the heap's memory. Set layouts belong to the heap and stay valid until here, so
+    /// every pipeline using them must already be destroyed.
+    pub fn destroy(&self, allocator: &vk_mem::Allocator) {
+        let mut state = self.state();
+        if let Some(mut backend) = state.backend.take() {
+            backend.destroy(allocator);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, HeapState> {
+        self.state.lock().expect("bindless heap mutex poisoned")
+    }
+}
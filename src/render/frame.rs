@@ -1,7 +1,7 @@
 use anyhow::Context;
 use ash::vk;
 
-use crate::vulkan::DeviceContext;
+use crate::{render::uniform::UniformSets, vulkan::DeviceContext};
 
 pub struct Frame {
     pub index: usize,
@@ -11,6 +11,7 @@ pub struct Frame {
     pub secondary_cmds: Vec<vk::CommandBuffer>,
     pub swapchain_image_index: u32,
     pub number: u64,
+    pub uniforms: UniformSets,
 }
 
 impl Frame {
@@ -27,6 +28,8 @@ impl Frame {
 
         let primary_cmd = allocate_primary(device_context, pool, index as u32)?;
         let secondary_cmds = allocate_secondary(device_context, pool, pass_count, index as u32)?;
+        let uniforms = UniformSets::new(device_context, index)
+            .context("failed to create uniform descriptor pool")?;
 
         Ok(Self {
             index,
@@ -36,11 +39,13 @@ impl Frame {
             secondary_cmds,
             swapchain_image_index: 0,
             number: 0,
+            uniforms,
         })
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
         log::trace!("Destroying Frame");
+        self.uniforms.destroy(device);
         unsafe {
             device.destroy_semaphore(self.image_available, None);
             device.destroy_fence(self.fence, None);
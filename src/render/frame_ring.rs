@@ -26,6 +26,7 @@ impl FrameRing {
         frame.number = self.number;
         self.number += 1;
         frame.wait(device).context("failed to wait for frame")?;
+        frame.uniforms.reset(device)?;
         self.index = (self.index + 1) % len;
         Ok(frame)
     }
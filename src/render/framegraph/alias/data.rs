@@ -7,17 +7,27 @@ use crate::{
     render::framegraph::graph::ImageAlias,
 };
 
+/// What `ImageFormat::Depth` resolves to.
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ImageFormat {
     SwapchainColor,
-    _Depth,
+    Depth,
     _HDRColor,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ImageSize {
-    _Absolute { width: u32, height: u32 },
-    SwapchainRelative { scale: f32 },
+    _Absolute {
+        width: u32,
+        height: u32,
+    },
+    /// Relative to the extent the owning graph renders at, which is the swapchain extent unless
+    /// the graph was built with its own.
+    SwapchainRelative {
+        scale: f32,
+    },
     _Relative(ImageAlias, f32),
 }
 
@@ -49,7 +59,7 @@ impl fmt::Display for ImageFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             ImageFormat::SwapchainColor => "SwapchainColor",
-            ImageFormat::_Depth => "Depth",
+            ImageFormat::Depth => "Depth",
             ImageFormat::_HDRColor => "HDRColor",
         };
         f.write_str(s)
@@ -2,7 +2,7 @@ mod data;
 mod registry;
 mod resolved;
 
-pub use data::{ImageDesc, ImageFormat, ImageSize};
+pub use data::{DEPTH_FORMAT, ImageDesc, ImageFormat, ImageSize};
 
 pub use registry::{AliasRegistry, ImageResolveContext};
 
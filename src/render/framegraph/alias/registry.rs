@@ -9,7 +9,7 @@ use crate::{
     },
     render::framegraph::{
         alias::{
-            data::{ImageDesc, ImageFormat, ImageKeys, ImageSize},
+            data::{DEPTH_FORMAT, ImageDesc, ImageFormat, ImageKeys, ImageSize},
             resolved::ResolvedRegistry,
         },
         graph::ImageAlias,
@@ -68,7 +68,7 @@ impl AliasRegistry {
         let mut image_views: HashMap<ImageAlias, CompositeImageViewKey> = HashMap::default();
 
         for (alias, desc) in self.declared.iter() {
-            let spec = create_image_spec(desc, ctx)?;
+            let spec = create_image_spec(desc, ctx)?.debug_name(alias.to_string());
 
             let spec_clone = spec.clone();
 
@@ -93,10 +93,12 @@ impl AliasRegistry {
         Ok(ResolvedRegistry {
             images,
             image_views,
+            owned: self.declared.keys().copied().collect(),
         })
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct ImageResolveContext<'a> {
     pub device_context: &'a DeviceContext,
     pub swapchain_extent: vk::Extent2D,
@@ -147,7 +149,7 @@ fn derive_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
 fn create_image_spec(desc: &ImageDesc, ctx: &ImageResolveContext) -> anyhow::Result<ImageSpec> {
     let format = match desc.format {
         ImageFormat::SwapchainColor => ctx.swapchain_format,
-        ImageFormat::_Depth => vk::Format::D32_SFLOAT,
+        ImageFormat::Depth => DEPTH_FORMAT,
         ImageFormat::_HDRColor => vk::Format::R16G16B16A16_SFLOAT,
     };
 
@@ -8,4 +8,7 @@ use crate::{
 pub struct ResolvedRegistry {
     pub images: HashMap<ImageAlias, CompositeImageKey>,
     pub image_views: HashMap<ImageAlias, CompositeImageViewKey>,
+    /// Aliases whose image and view the graph created, as opposed to external ones it only
+    /// refers to.
+    pub owned: Vec<ImageAlias>,
 }
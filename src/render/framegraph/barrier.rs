@@ -2,11 +2,14 @@ use std::{collections::HashMap, fmt};
 
 use ash::vk::{self};
 
-use crate::render::framegraph::{
-    ImageState,
-    graph::ImageAlias,
-    image::ImageIndexing,
-    pass::{BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass},
+use crate::{
+    image::{CompositeImageKey, CompositeImageViewKey},
+    render::framegraph::{
+        ImageState,
+        graph::ImageAlias,
+        image::ImageIndexing,
+        pass::{BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass},
+    },
 };
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -14,6 +17,31 @@ pub enum BufferAlias {
     _Placeholder,
 }
 
+/// An image handed from one graph to another. The producing graph leaves it in `state` once it
+/// has executed, and every importing graph starts from `state` and returns it there when done.
+#[derive(Clone, Copy)]
+pub struct ExportedImage {
+    pub image: CompositeImageKey,
+    pub view: CompositeImageViewKey,
+    pub state: ImageState,
+}
+
+/// Images crossing the edge of a graph, with the state they are exchanged in.
+#[derive(Default)]
+pub struct GraphBoundary {
+    pub imports: HashMap<ImageAlias, ImageState>,
+    pub exports: HashMap<ImageAlias, ImageState>,
+}
+
+impl GraphBoundary {
+    fn boundary_state(&self, alias: &ImageAlias) -> Option<ImageState> {
+        self.imports
+            .get(alias)
+            .or_else(|| self.exports.get(alias))
+            .copied()
+    }
+}
+
 pub struct ImageBarrierDesc {
     pub alias: ImageAlias,
     pub indexing: ImageIndexing,
@@ -45,6 +73,9 @@ impl fmt::Display for ImageBarrierDesc {
 
 pub struct BarrierPlan {
     pub image_barrier_descs: HashMap<u32, Vec<ImageBarrierDesc>>,
+    /// Recorded after the last pass to put imported and exported images back into their
+    /// boundary state.
+    pub boundary_barrier_descs: Vec<ImageBarrierDesc>,
     _buffer_precursors: HashMap<u32, Vec<BufferBarrierPrecursor>>,
 }
 
@@ -52,10 +83,21 @@ impl BarrierPlan {
     pub fn from_passes<'a>(
         passes: &[Box<dyn RenderPass>],
         aliases: impl IntoIterator<Item = &'a ImageAlias>,
+        boundary: &GraphBoundary,
     ) -> Self {
+        // Exported images are left in their boundary state from the last time the graph ran,
+        // and may still be read from there by importers of an earlier frame.
         let mut image_states = initial_states(aliases);
+        image_states.extend(
+            boundary
+                .imports
+                .iter()
+                .chain(&boundary.exports)
+                .map(|(alias, state)| (*alias, *state)),
+        );
 
         let mut image_barrier_descs: HashMap<u32, Vec<ImageBarrierDesc>> = HashMap::default();
+        let mut last_access: HashMap<ImageAlias, ImageBarrierPrecursor> = HashMap::default();
 
         for pass in passes {
             for precursor in pass.image_precursors() {
@@ -69,9 +111,24 @@ impl BarrierPlan {
                     .push(barrier_desc);
 
                 image_states.insert(precursor.access.alias, precursor.access.usage.state);
+                last_access.insert(precursor.access.alias, precursor);
             }
         }
 
+        let boundary_barrier_descs = last_access
+            .values()
+            .filter_map(|last| {
+                let target = boundary.boundary_state(&last.access.alias)?;
+                (target != last.access.usage.state).then(|| ImageBarrierDesc {
+                    alias: last.access.alias,
+                    indexing: last.access.indexing,
+                    old_state: last.access.usage.state,
+                    new_state: target,
+                    subresource_range: last.access.usage.subresource_range(),
+                })
+            })
+            .collect();
+
         let buffer_precursors = passes
             .iter()
             .map(|pass| (pass.id(), pass.buffer_precursors()))
@@ -79,6 +136,7 @@ impl BarrierPlan {
 
         Self {
             image_barrier_descs,
+            boundary_barrier_descs,
             _buffer_precursors: buffer_precursors,
         }
     }
@@ -106,6 +164,13 @@ impl fmt::Display for BarrierPlan {
             }
         }
 
+        if !self.boundary_barrier_descs.is_empty() {
+            writeln!(f, "  boundary barriers:")?;
+            for (i, barrier) in self.boundary_barrier_descs.iter().enumerate() {
+                writeln!(f, "    [{}] {}", i, barrier)?;
+            }
+        }
+
         // Intentionally not dumping buffer precursors yet
         if !self._buffer_precursors.is_empty() {
             writeln!(
@@ -4,17 +4,17 @@ use anyhow::Context;
 use ash::vk;
 
 use crate::{
-    image::{CompositeImageKey, CompositeImageViewKey, ImageManager},
+    image::{CompositeImageKey, CompositeImageViewKey, ImageManager, ResizePolicy},
     render::{
         framegraph::{
-            FrameGraph,
+            FrameGraph, ImageState,
             alias::{AliasRegistry, ImageResolveContext},
-            barrier::BarrierPlan,
-            graph::ImageAlias,
+            barrier::{BarrierPlan, ExportedImage, GraphBoundary},
+            graph::{ImageAlias, PassPipelines},
             image::ImageCreation,
             pass::RenderPass,
         },
-        pipeline::PipelineManager,
+        pipeline::{PipelineDesc, PipelineManager},
     },
     vulkan::DeviceContext,
 };
@@ -22,6 +22,11 @@ use crate::{
 type RenderPassList = Vec<Box<dyn RenderPass>>;
 
 pub struct FramegraphBuilder<'a> {
+    name: String,
+    extent: Option<vk::Extent2D>,
+    swapchain_keys: Option<(CompositeImageKey, CompositeImageViewKey)>,
+    imports: HashMap<ImageAlias, ExportedImage>,
+    exports: HashMap<ImageAlias, ImageState>,
     image_manager: &'a mut ImageManager,
     allocator: &'a vk_mem::Allocator,
     device_context: DeviceContext,
@@ -41,6 +46,11 @@ impl<'a> FramegraphBuilder<'a> {
         pipeline_manager: &'a mut PipelineManager,
     ) -> Self {
         Self {
+            name: "main".to_string(),
+            extent: None,
+            swapchain_keys: None,
+            imports: HashMap::default(),
+            exports: HashMap::default(),
             image_manager,
             allocator,
             device_context,
@@ -56,14 +66,61 @@ impl<'a> FramegraphBuilder<'a> {
         self
     }
 
-    pub fn build(
-        self,
-        ctx: &ImageResolveContext,
-        keys: (CompositeImageKey, CompositeImageViewKey),
-    ) -> anyhow::Result<FrameGraph> {
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Renders at `extent` instead of the swapchain extent. Swapchain-relative image sizes are
+    /// resolved against it, and those images keep their size when the swapchain is resized.
+    pub fn extent(mut self, extent: vk::Extent2D) -> Self {
+        self.extent = Some(extent);
+        self
+    }
+
+    /// Makes the swapchain images available under `ImageAlias::SwapchainImage`.
+    pub fn swapchain(mut self, keys: (CompositeImageKey, CompositeImageViewKey)) -> Self {
+        self.swapchain_keys = Some(keys);
+        self
+    }
+
+    /// Uses an image produced by another graph under `alias`.
+    pub fn import_image(mut self, alias: ImageAlias, image: ExportedImage) -> Self {
+        self.imports.insert(alias, image);
+        self
+    }
+
+    /// Leaves `alias` in `state` after this graph executes so other graphs can import it.
+    pub fn export_image(mut self, alias: ImageAlias, state: ImageState) -> Self {
+        self.exports.insert(alias, state);
+        self
+    }
+
+    pub fn build(self, ctx: &ImageResolveContext) -> anyhow::Result<FrameGraph> {
+        let ctx = &match self.extent {
+            Some(extent) => ImageResolveContext {
+                swapchain_extent: extent,
+                default_resize_policy: ResizePolicy::Fixed,
+                ..*ctx
+            },
+            None => *ctx,
+        };
+
         let mut alias_registry = AliasRegistry::default();
 
-        alias_registry.declare_external_image(ImageAlias::SwapchainImage, keys)?;
+        if let Some(keys) = self.swapchain_keys {
+            alias_registry.declare_external_image(ImageAlias::SwapchainImage, keys)?;
+        }
+
+        let mut boundary = GraphBoundary::default();
+
+        for (alias, image) in &self.imports {
+            if self.exports.contains_key(alias) {
+                anyhow::bail!("graph '{}' both imports and exports {}", self.name, alias);
+            }
+            alias_registry.declare_external_image(*alias, (image.image, image.view))?;
+            boundary.imports.insert(*alias, image.state);
+        }
 
         compile_resources(&self.render_passes, &mut alias_registry)?;
 
@@ -73,26 +130,64 @@ impl<'a> FramegraphBuilder<'a> {
             .resolve(im, self.allocator, ctx)
             .context("FrameGraphBuilder failed to build resources")?;
 
-        let barrier_plans = build_barrier_plans(&self.render_passes, registry.images.keys())?;
+        for (alias, state) in &self.exports {
+            if !registry.images.contains_key(alias) {
+                anyhow::bail!(
+                    "graph '{}' exports {} but no pass declares it",
+                    self.name,
+                    alias
+                );
+            }
+            boundary.exports.insert(*alias, *state);
+        }
+
+        let barrier_plans =
+            build_barrier_plans(&self.render_passes, registry.images.keys(), &boundary)?;
 
         log::debug!("Barrier Plan: {}", barrier_plans);
 
         let pipeline_manager = self.pipeline_manager;
 
-        let mut pipelines = HashMap::default();
+        let mut pipelines = PassPipelines::default();
 
+        let with_formats = |mut desc: PipelineDesc| {
+            if let PipelineDesc::Graphics(desc) = &mut desc {
+                desc.color_formats = self.swapchain_formats.to_vec();
+            }
+            desc
+        };
         for pass in &self.render_passes {
-            let mut desc = pass.pipeline_desc();
-            desc.color_formats = self.swapchain_formats.to_vec();
-            let pipeline_key = pipeline_manager.get_or_create(&self.device_context, desc)?;
-            pipelines.insert(pass.id(), pipeline_key);
+            let fallback = pass
+                .fallback_pipeline_desc()
+                .map(|desc| {
+                    pipeline_manager.request(&self.device_context, with_formats(desc), None)
+                })
+                .transpose()
+                .with_context(|| {
+                    format!("failed to create fallback pipeline for pass {}", pass.id())
+                })?;
+            if let Some(fallback) = fallback {
+                pipelines.fallbacks.insert(pass.id(), fallback);
+            }
+
+            let pipeline_key = pipeline_manager
+                .request(
+                    &self.device_context,
+                    with_formats(pass.pipeline_desc()),
+                    fallback,
+                )
+                .with_context(|| format!("failed to create pipeline for pass {}", pass.id()))?;
+            pipelines.requested.insert(pass.id(), pipeline_key);
         }
 
         Ok(FrameGraph::new(
+            self.name,
+            ctx.swapchain_extent,
             self.render_passes,
             pipelines,
             registry,
             barrier_plans,
+            boundary,
         ))
     }
 }
@@ -101,8 +196,9 @@ impl<'a> FramegraphBuilder<'a> {
 fn build_barrier_plans<'a>(
     passes: &[Box<dyn RenderPass>],
     aliases: impl IntoIterator<Item = &'a ImageAlias>,
+    boundary: &GraphBoundary,
 ) -> anyhow::Result<BarrierPlan> {
-    Ok(BarrierPlan::from_passes(passes, aliases))
+    Ok(BarrierPlan::from_passes(passes, aliases, boundary))
 }
 
 /// Registers aliases with AliasRegistry
@@ -7,17 +7,17 @@ use anyhow::Context;
 use ash::vk;
 
 use crate::{
-    image::{CompositeImageKey, FrameIndex},
+    image::{CompositeImageKey, FrameIndex, ImageManager},
     render::{
         framegraph::{
             ImageState,
             alias::ResolvedRegistry,
-            barrier::BarrierPlan,
+            barrier::{BarrierPlan, ExportedImage, GraphBoundary, ImageBarrierDesc},
             image::{FrameIndexKind, ImageIndexing},
             pass::{RenderPass, RenderPassContext},
             transition_image,
         },
-        pipeline::PipelineKey,
+        pipeline::{PipelineKey, PipelineManager},
         thread::FrameExecutionContext,
     },
 };
@@ -26,6 +26,10 @@ use crate::{
 pub enum ImageAlias {
     SwapchainImage,
     ForwardColor,
+    ForwardDepth,
+    /// Offscreen targets (shadow atlases, probes, viewports) that don't warrant a variant of
+    /// their own.
+    Named(&'static str),
 }
 
 impl fmt::Display for ImageAlias {
@@ -33,6 +37,8 @@ impl fmt::Display for ImageAlias {
         let name = match self {
             ImageAlias::SwapchainImage => "SwapchainImage",
             ImageAlias::ForwardColor => "ForwardColor",
+            ImageAlias::ForwardDepth => "ForwardDepth",
+            ImageAlias::Named(name) => name,
         };
 
         f.write_str(name)
@@ -65,133 +71,240 @@ impl GraphFirstUse {
     }
 }
 
+/// The pipeline each pass records with, and the fallback of those that have one.
+#[derive(Default)]
+pub(super) struct PassPipelines {
+    pub requested: HashMap<u32, PipelineKey>,
+    pub fallbacks: HashMap<u32, PipelineKey>,
+}
+
 pub struct FrameGraph {
+    name: String,
+    extent: vk::Extent2D,
     render_passes: Vec<Box<dyn RenderPass>>,
-    pass_pipelines: HashMap<u32, PipelineKey>,
+    pass_pipelines: PassPipelines,
     registry: ResolvedRegistry,
     barrier_plan: BarrierPlan,
+    boundary: GraphBoundary,
     graph_first_use: GraphFirstUse,
 }
 
 impl FrameGraph {
-    pub fn new(
+    pub(super) fn new(
+        name: String,
+        extent: vk::Extent2D,
         render_passes: Vec<Box<dyn RenderPass>>,
-        pass_pipelines: HashMap<u32, PipelineKey>,
+        pass_pipelines: PassPipelines,
         registry: ResolvedRegistry,
         barrier_plan: BarrierPlan,
+        boundary: GraphBoundary,
     ) -> Self {
         Self {
+            name,
+            extent,
             render_passes,
             pass_pipelines,
             registry,
             barrier_plan,
+            boundary,
             graph_first_use: GraphFirstUse {
                 seen: HashSet::default(),
             },
         }
     }
 
-    pub fn execute(&mut self, ctx: &FrameExecutionContext) -> anyhow::Result<()> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.render_passes.len()
+    }
+
+    pub fn imports(&self) -> impl Iterator<Item = &ImageAlias> {
+        self.boundary.imports.keys()
+    }
+
+    pub fn exports(&self) -> impl Iterator<Item = &ImageAlias> {
+        self.boundary.exports.keys()
+    }
+
+    /// Gives back the pipeline references taken when the graph was built.
+    pub fn release_pipelines(
+        &mut self,
+        pipeline_manager: &mut PipelineManager,
+    ) -> anyhow::Result<()> {
+        let pipelines = std::mem::take(&mut self.pass_pipelines);
+        for key in pipelines
+            .requested
+            .into_values()
+            .chain(pipelines.fallbacks.into_values())
+        {
+            pipeline_manager.release(key)?;
+        }
+        Ok(())
+    }
+
+    /// Destroys the images and views the graph created. External images, including ones it
+    /// imported, are left to their owners.
+    pub fn release_images(&mut self, image_manager: &mut ImageManager) {
+        for alias in self.registry.owned.drain(..) {
+            if let Some(view) = self.registry.image_views.remove(&alias) {
+                image_manager.destroy_image_view(view);
+            }
+            if let Some(image) = self.registry.images.remove(&alias) {
+                image_manager.destroy_image(image);
+            }
+        }
+    }
+
+    /// Hands out an image this graph declared as an export so another graph can import it.
+    pub fn export(&self, alias: ImageAlias) -> anyhow::Result<ExportedImage> {
+        let state = self
+            .boundary
+            .exports
+            .get(&alias)
+            .copied()
+            .with_context(|| format!("graph '{}' does not export {}", self.name, alias))?;
+
+        let image = self
+            .registry
+            .images
+            .get(&alias)
+            .copied()
+            .with_context(|| format!("graph '{}' has no image for {}", self.name, alias))?;
+
+        let view = self
+            .registry
+            .image_views
+            .get(&alias)
+            .copied()
+            .with_context(|| format!("graph '{}' has no image view for {}", self.name, alias))?;
+
+        Ok(ExportedImage { image, view, state })
+    }
+
+    /// Records every pass into `frame.primary_cmd`, which the caller has already begun.
+    /// `secondary_cmds` must hold one command buffer per pass of this graph.
+    ///
+    /// `missing` are imports whose producer hasn't written them this frame. They are left
+    /// untouched, and passes reading them record with their fallback or not at all. Returns
+    /// whether every pass recorded with its own pipeline, i.e. whether the exports are
+    /// complete.
+    pub fn execute(
+        &mut self,
+        ctx: &FrameExecutionContext,
+        secondary_cmds: &[vk::CommandBuffer],
+        missing: &HashSet<ImageAlias>,
+    ) -> anyhow::Result<bool> {
         let device = ctx.device;
         let frame = &ctx.frame;
 
-        begin_primary(device, frame.primary_cmd)?;
+        let viewport = vk::Viewport {
+            height: self.extent.height as f32,
+            width: self.extent.width as f32,
+            ..Default::default()
+        };
+        let snizzor = vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: self.extent,
+        };
+
+        let mut complete = true;
 
         for (i, pass) in self.render_passes.iter().enumerate() {
             if let Some(barrier_descs) = self.barrier_plan.image_barrier_descs.get(&pass.id()) {
-                for desc in barrier_descs {
-                    let ckey = self
-                        .registry
-                        .images
-                        .get(&desc.alias)
-                        .context(format!("failed to find image: {:?}", desc.alias))?;
-
-                    let mut debug_frame_index: Option<FrameIndex> = None;
-
-                    let image = match desc.indexing {
-                        ImageIndexing::_Global => match ckey {
-                            CompositeImageKey::Global(image_key) => {
-                                ctx.image_manager.image_global(*image_key)
-                            }
-                            CompositeImageKey::PerFrame(_) => unreachable!(
-                                "Global Indexing should not reference per-frame composite keys"
-                            ),
-                        },
-
-                        ImageIndexing::PerFrame(frame_index_kind) => {
-                            let frame_index = match frame_index_kind {
-                                FrameIndexKind::Frame => FrameIndex::Frame(frame.index as u32),
-                                FrameIndexKind::Swapchain => {
-                                    FrameIndex::Swapchain(frame.swapchain_image_index)
-                                }
-                            };
-                            debug_frame_index = Some(frame_index);
-                            ctx.image_manager.resolve_image(*ckey, frame_index)
-                        }
-                    };
-
-                    let instance = match debug_frame_index {
-                        Some(FrameIndex::Frame(i)) => {
-                            PhysicalImageInstance::Frame(FrameIndex::Frame(i))
-                        }
-                        Some(FrameIndex::Swapchain(i)) => PhysicalImageInstance::Swapchain(i),
-                        None => PhysicalImageInstance::Global,
-                    };
-
-                    let first_use = self.graph_first_use.is_first_use((*ckey, instance));
-
-                    let old_state = if first_use {
-                        ImageState::UNDEFINED
-                    } else {
-                        desc.old_state
-                    };
-
-                    // log::trace!(
-                    //     "[Frame {:?} index: {:?}] Image={:?}[{:?}] old={:?} new={:?}",
-                    //     frame.number,
-                    //     frame.index,
-                    //     desc.alias,
-                    //     debug_frame_index,
-                    //     old_state,
-                    //     desc.new_state
-                    // );
-                    transition_image(
-                        device,
-                        frame.primary_cmd,
-                        image.vk_image,
-                        desc.subresource_range,
-                        old_state,
-                        desc.new_state,
-                        "Image".to_string().as_ref(),
-                    )
+                for desc in barrier_descs
+                    .iter()
+                    .filter(|desc| !missing.contains(&desc.alias))
+                {
+                    record_image_barrier(
+                        ctx,
+                        &self.registry,
+                        &self.boundary,
+                        &mut self.graph_first_use,
+                        desc,
+                    )?;
                 }
             }
 
-            let secondary = frame.secondary_cmds[i];
+            let secondary = *secondary_cmds.get(i).with_context(|| {
+                format!(
+                    "graph '{}' has no secondary command buffer for pass {i}",
+                    self.name
+                )
+            })?;
 
-            begin_secondary(device, secondary, pass.rendering_info())?;
-            let pipeline_key = self
+            let requested = self
                 .pass_pipelines
+                .requested
                 .get(&pass.id())
                 .context("failed to get pipeline")?;
 
+            let starved = pass
+                .image_requirements()
+                .iter()
+                .any(|req| missing.contains(&req.access.alias));
+            let pipeline_key = if starved {
+                self.pass_pipelines
+                    .fallbacks
+                    .get(&pass.id())
+                    .and_then(|fallback| ctx.pipeline_manager.resolve(*fallback))
+            } else {
+                ctx.pipeline_manager.resolve(*requested)
+            };
+
+            // Still compiling, or starved of an input, with nothing to fall back on; the pass
+            // resumes once both are there.
+            let Some(pipeline_key) = pipeline_key else {
+                log::trace!("skipping pass {} this frame", pass.id());
+                complete = false;
+                continue;
+            };
+            complete &= pipeline_key == *requested;
+
+            begin_secondary(device, secondary, pass.rendering_info())?;
+
             let pipeline = ctx
                 .pipeline_manager
-                .get_pipeline(pipeline_key)
+                .get_pipeline(&pipeline_key)
                 .with_context(|| format!("failed to get pipeline for pass {:?}", pass.id()))?;
 
+            let pipeline_layout = ctx
+                .pipeline_manager
+                .get_pipeline_layout(&pipeline_key)
+                .with_context(|| format!("failed to get layout for pass {:?}", pass.id()))?;
+
+            let bind_point = ctx.pipeline_manager.get_bind_point(&pipeline_key)?;
+
+            if pipeline_layout.bindless {
+                let heap = ctx.bindless.with_context(|| {
+                    format!("pass {} uses a bindless pipeline without a heap", pass.id())
+                })?;
+                heap.bind(secondary, bind_point, pipeline_layout.layout);
+            }
+
             let pass_ctx = RenderPassContext {
                 device,
+                device_context: ctx.device_context,
+                allocator: ctx.allocator,
                 cmd: secondary,
                 pipeline,
+                fallback: pipeline_key != *requested,
+                pipeline_layout,
+                bind_point,
                 frame_index: frame.index,
                 swapchain_image_index: frame.swapchain_image_index,
                 registry: &self.registry,
                 image_manager: ctx.image_manager,
-                swapchain_extent: ctx.swapchain_extent,
-                viewport: ctx.viewport,
-                snizzor: ctx.snizzor,
-                _render_data: ctx.render_data,
+                buffer_manager: ctx.buffer_manager,
+                extent: self.extent,
+                viewport,
+                snizzor,
+                uniform_sets: &frame.uniforms,
+                uniform_data: ctx.uniform_data,
+                features: ctx.features,
+                render_data: ctx.render_data,
             };
 
             pass.execute(&pass_ctx)
@@ -203,12 +316,90 @@ impl FrameGraph {
                 device.cmd_execute_commands(frame.primary_cmd, &[secondary]);
             }
         }
-        end_primary(device, frame.primary_cmd)?;
-        Ok(())
+
+        for desc in self
+            .barrier_plan
+            .boundary_barrier_descs
+            .iter()
+            .filter(|desc| !missing.contains(&desc.alias))
+        {
+            record_image_barrier(
+                ctx,
+                &self.registry,
+                &self.boundary,
+                &mut self.graph_first_use,
+                desc,
+            )?;
+        }
+
+        Ok(complete)
     }
 }
 
-fn begin_primary(device: &ash::Device, cmd: vk::CommandBuffer) -> anyhow::Result<()> {
+fn record_image_barrier(
+    ctx: &FrameExecutionContext,
+    registry: &ResolvedRegistry,
+    boundary: &GraphBoundary,
+    graph_first_use: &mut GraphFirstUse,
+    desc: &ImageBarrierDesc,
+) -> anyhow::Result<()> {
+    let frame = &ctx.frame;
+
+    let ckey = registry
+        .images
+        .get(&desc.alias)
+        .context(format!("failed to find image: {:?}", desc.alias))?;
+
+    let mut debug_frame_index: Option<FrameIndex> = None;
+
+    let image = match desc.indexing {
+        ImageIndexing::Global => match ckey {
+            CompositeImageKey::Global(image_key) => ctx.image_manager.image_global(*image_key),
+            CompositeImageKey::PerFrame(_) => {
+                unreachable!("Global Indexing should not reference per-frame composite keys")
+            }
+        },
+
+        ImageIndexing::PerFrame(frame_index_kind) => {
+            let frame_index = match frame_index_kind {
+                FrameIndexKind::Frame => FrameIndex::Frame(frame.index as u32),
+                FrameIndexKind::Swapchain => FrameIndex::Swapchain(frame.swapchain_image_index),
+            };
+            debug_frame_index = Some(frame_index);
+            ctx.image_manager.resolve_image(*ckey, frame_index)
+        }
+    };
+
+    let instance = match debug_frame_index {
+        Some(FrameIndex::Frame(i)) => PhysicalImageInstance::Frame(FrameIndex::Frame(i)),
+        Some(FrameIndex::Swapchain(i)) => PhysicalImageInstance::Swapchain(i),
+        None => PhysicalImageInstance::Global,
+    };
+
+    let first_use = graph_first_use.is_first_use((*ckey, instance));
+
+    // Imported images arrive in the state their producer left them in, so they must not be
+    // discarded on first use.
+    let old_state = if first_use && !boundary.imports.contains_key(&desc.alias) {
+        ImageState::UNDEFINED
+    } else {
+        desc.old_state
+    };
+
+    transition_image(
+        ctx.device,
+        frame.primary_cmd,
+        image.vk_image,
+        desc.subresource_range,
+        old_state,
+        desc.new_state,
+        "Image".to_string().as_ref(),
+    );
+
+    Ok(())
+}
+
+pub(super) fn begin_primary(device: &ash::Device, cmd: vk::CommandBuffer) -> anyhow::Result<()> {
     unsafe {
         device
             .begin_command_buffer(cmd, &vk::CommandBufferBeginInfo::default())
@@ -246,7 +437,7 @@ fn end_secondary(device: &ash::Device, cmd: vk::CommandBuffer) -> anyhow::Result
     }
 }
 
-fn end_primary(device: &ash::Device, cmd: vk::CommandBuffer) -> anyhow::Result<()> {
+pub(super) fn end_primary(device: &ash::Device, cmd: vk::CommandBuffer) -> anyhow::Result<()> {
     unsafe {
         device
             .end_command_buffer(cmd)
@@ -6,7 +6,7 @@ use crate::render::framegraph::{ImageState, alias::ImageDesc, graph::ImageAlias}
 
 #[derive(Copy, Clone, Debug)]
 pub enum ImageIndexing {
-    _Global,
+    Global,
     PerFrame(FrameIndexKind),
 }
 
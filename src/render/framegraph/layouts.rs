@@ -15,6 +15,7 @@ impl fmt::Display for ImageState {
         let layout_str = match self.layout {
             vk::ImageLayout::UNDEFINED => "UNDEFINED",
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => "COLOR_ATTACHMENT_OPTIMAL",
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => "DEPTH_STENCIL_ATTACHMENT_OPTIMAL",
             vk::ImageLayout::PRESENT_SRC_KHR => "PRESENT_SRC_KHR",
             vk::ImageLayout::GENERAL => "GENERAL",
             vk::ImageLayout::TRANSFER_SRC_OPTIMAL => "TRANSFER_SRC_OPTIMAL",
@@ -99,6 +100,33 @@ impl ImageState {
         access: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
     };
 
+    /// Depth-tested and written.
+    pub const DEPTH_ATTACHMENT_WRITE: ImageState = ImageState {
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        stage: vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw()
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw(),
+        ),
+        access: vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw(),
+        ),
+    };
+
+    /// Sampled by fragment shaders.
+    pub const SHADER_READ: ImageState = ImageState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        access: vk::AccessFlags2::SHADER_SAMPLED_READ,
+    };
+
+    /// Sampled or loaded by compute shaders.
+    pub const COMPUTE_READ: ImageState = ImageState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        stage: vk::PipelineStageFlags2::COMPUTE_SHADER,
+        access: vk::AccessFlags2::SHADER_SAMPLED_READ,
+    };
+
     pub const PRESENT: ImageState = ImageState {
         layout: vk::ImageLayout::PRESENT_SRC_KHR,
         stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
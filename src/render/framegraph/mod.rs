@@ -5,13 +5,18 @@ mod graph;
 mod image;
 mod layouts;
 mod pass;
+mod schedule;
 
-pub use graph::FrameGraph;
+pub use graph::{FrameGraph, ImageAlias};
+
+pub use schedule::{FrameGraphSet, GraphRate};
 
 pub use builder::FramegraphBuilder;
 
 pub use pass::CompositionPass;
 pub use pass::ForwardPass;
+pub use pass::InsetPass;
+pub use pass::{CullingBuffers, CullingPass, DepthPyramid, DepthPyramidPass, IndirectDraws};
 
 pub use alias::ImageResolveContext;
 
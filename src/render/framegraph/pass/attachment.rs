@@ -2,8 +2,11 @@ use anyhow::Context;
 use ash::vk;
 
 use crate::{
-    image::{FrameIndex, ImageManager},
-    render::framegraph::{alias::ResolvedRegistry, graph::ImageAlias},
+    image::{FrameIndex, ImageManager, ImageView},
+    render::{
+        TextureIndex,
+        framegraph::{alias::ResolvedRegistry, graph::ImageAlias},
+    },
 };
 
 pub struct AttachmentResolver<'a> {
@@ -15,6 +18,17 @@ pub struct AttachmentResolver<'a> {
 
 impl<'a> AttachmentResolver<'a> {
     pub fn image_view(&self, alias: ImageAlias) -> anyhow::Result<vk::ImageView> {
+        Ok(self.resolve(alias)?.vk_image_view)
+    }
+
+    /// The bindless slot of `alias`'s view, for images created with `SAMPLED` usage.
+    pub fn texture(&self, alias: ImageAlias) -> anyhow::Result<TextureIndex> {
+        self.resolve(alias)?
+            .bindless
+            .with_context(|| format!("{} is not in the bindless heap", alias))
+    }
+
+    fn resolve(&self, alias: ImageAlias) -> anyhow::Result<&ImageView> {
         let index = if alias == ImageAlias::SwapchainImage {
             FrameIndex::Swapchain(self.swapchain_image_index)
         } else {
@@ -32,8 +46,6 @@ impl<'a> AttachmentResolver<'a> {
                 )
             })?;
 
-        let image_view = self.image_manager.resolve_image_view(image_view_key, index);
-
-        Ok(image_view.vk_image_view)
+        Ok(self.image_manager.resolve_image_view(image_view_key, index))
     }
 }
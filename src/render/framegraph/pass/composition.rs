@@ -1,27 +1,64 @@
 use ash::vk::{self};
-
-use crate::render::{
-    framegraph::{
-        ImageState,
-        graph::ImageAlias,
-        image::{
-            FrameIndexKind, ImageAccess, ImageCreation, ImageIndexing, ImageRequirement, ImageUsage,
-        },
-        pass::{
-            BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass, RenderPassContext,
-            attachment::AttachmentResolver,
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    image::ImageLifetime,
+    render::{
+        SamplerIndex,
+        framegraph::{
+            ImageState,
+            alias::{ImageDesc, ImageFormat, ImageSize},
+            graph::ImageAlias,
+            image::{
+                FrameIndexKind, ImageAccess, ImageCreation, ImageIndexing, ImageRequirement,
+                ImageUsage,
+            },
+            pass::{
+                BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass, RenderPassContext,
+                attachment::AttachmentResolver,
+            },
         },
+        pipeline::{GraphicsPipelineDesc, PipelineDesc},
+        shader::ShaderId,
     },
-    pipeline::GraphicsPipelineDesc,
-    shader::ShaderId,
 };
 
 pub struct CompositionPass {
     image_requirements: Vec<ImageRequirement>,
+    /// `SwapchainImage` unless the pass renders `into_image`.
+    target: ImageAlias,
     color_value: vk::ClearValue,
     _depth_value: vk::ClearValue,
+    sampler: Option<SamplerIndex>,
+    /// Colour shown when the pass isn't `sampled`.
+    fill: [f32; 4],
 }
 
+/// Matches `Composite` in `composition_sampled.frag.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct CompositeConstants {
+    pub texture: u32,
+    pub sampler: u32,
+    pub origin: [f32; 2],
+    pub size: [f32; 2],
+}
+
+// Only 4-byte fields, so there is no padding.
+unsafe impl Zeroable for CompositeConstants {}
+unsafe impl Pod for CompositeConstants {}
+
+/// Matches `Fill` in `composition.frag`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FillUniform {
+    color: [f32; 4],
+}
+
+// Only 4-byte fields, so there is no padding.
+unsafe impl Zeroable for FillUniform {}
+unsafe impl Pod for FillUniform {}
+
 impl Default for CompositionPass {
     fn default() -> Self {
         let color_value = vk::ClearValue {
@@ -57,8 +94,7 @@ impl Default for CompositionPass {
                     access: ImageAccess {
                         alias: ImageAlias::ForwardColor,
                         usage: ImageUsage {
-                            // Create ImageState for Sampling an image
-                            state: ImageState::COLOR_ATTACHMENT_WRITE,
+                            state: ImageState::SHADER_READ,
                             aspects: vk::ImageAspectFlags::COLOR,
                         },
                         indexing: ImageIndexing::PerFrame(FrameIndexKind::Frame),
@@ -66,8 +102,11 @@ impl Default for CompositionPass {
                     creation: ImageCreation::UseExisting,
                 },
             ],
+            target: ImageAlias::SwapchainImage,
             color_value,
             _depth_value: depth_value,
+            sampler: None,
+            fill: [0.392, 0.584, 0.929, 1.0],
         }
     }
 }
@@ -94,14 +133,20 @@ impl RenderPass for CompositionPass {
         vec![]
     }
 
-    fn pipeline_desc(&self) -> GraphicsPipelineDesc {
-        GraphicsPipelineDesc {
-            vertex_id: ShaderId::CompositionVert,
-            fragment_id: ShaderId::CompositionFrag,
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            color_formats: vec![],
-            depth_format: None,
-        }
+    fn pipeline_desc(&self) -> PipelineDesc {
+        let fragment = match self.sampler {
+            Some(_) => ShaderId::COMPOSITION_SAMPLED_FRAG,
+            None => ShaderId::COMPOSITION_FRAG,
+        };
+        GraphicsPipelineDesc::new(ShaderId::COMPOSITION_VERT, fragment).into()
+    }
+
+    /// The flat fill, so the swapchain still gets cleared and presented while the sampled
+    /// pipeline compiles.
+    fn fallback_pipeline_desc(&self) -> Option<PipelineDesc> {
+        self.sampler.map(|_| {
+            GraphicsPipelineDesc::new(ShaderId::COMPOSITION_VERT, ShaderId::COMPOSITION_FRAG).into()
+        })
     }
 
     fn execute(&self, ctx: &RenderPassContext) -> anyhow::Result<()> {
@@ -112,10 +157,10 @@ impl RenderPass for CompositionPass {
             swapchain_image_index: ctx.swapchain_image_index,
         };
 
-        let swapchain_image_view = resolver.image_view(ImageAlias::SwapchainImage)?;
+        let target_image_view = resolver.image_view(self.target)?;
 
         let color_attachment_info = [vk::RenderingAttachmentInfo::default()
-            .image_view(swapchain_image_view)
+            .image_view(target_image_view)
             .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
             .load_op(vk::AttachmentLoadOp::CLEAR)
             .store_op(vk::AttachmentStoreOp::STORE)
@@ -124,7 +169,7 @@ impl RenderPass for CompositionPass {
         let rendering_info = vk::RenderingInfo::default()
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D::default(),
-                extent: ctx.swapchain_extent,
+                extent: ctx.extent,
             })
             .layer_count(1)
             .color_attachments(&color_attachment_info);
@@ -135,6 +180,17 @@ impl RenderPass for CompositionPass {
                 .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, ctx.pipeline);
             ctx.device.cmd_set_viewport(ctx.cmd, 0, &[ctx.viewport]);
             ctx.device.cmd_set_scissor(ctx.cmd, 0, &[ctx.snizzor]);
+        }
+        match self.sampler.filter(|_| !ctx.fallback) {
+            Some(sampler) => ctx.push_constants(&CompositeConstants {
+                texture: resolver.texture(ImageAlias::ForwardColor)?.raw(),
+                sampler: sampler.raw(),
+                origin: [0.0, 0.0],
+                size: [ctx.extent.width as f32, ctx.extent.height as f32],
+            })?,
+            None => ctx.upload_uniform(0, 0, &FillUniform { color: self.fill })?,
+        }
+        unsafe {
             ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0);
             ctx.device.cmd_end_rendering(ctx.cmd);
         }
@@ -151,4 +207,35 @@ impl RenderPass for CompositionPass {
     }
 }
 
-impl CompositionPass {}
+impl CompositionPass {
+    /// Shows `ForwardColor`, read through `sampler`, instead of a flat colour. Both have to be
+    /// in the bindless heap.
+    pub fn sampled(mut self, sampler: SamplerIndex) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Renders into `alias` instead of the swapchain: a global image the size of the graph's
+    /// extent, which other graphs can import and sample.
+    pub fn into_image(mut self, alias: ImageAlias) -> Self {
+        self.target = alias;
+        self.image_requirements[0] = ImageRequirement {
+            access: ImageAccess {
+                alias,
+                usage: ImageUsage {
+                    state: ImageState::COLOR_ATTACHMENT_WRITE,
+                    aspects: vk::ImageAspectFlags::COLOR,
+                },
+                indexing: ImageIndexing::Global,
+            },
+            creation: ImageCreation::Declare(ImageDesc {
+                format: ImageFormat::SwapchainColor,
+                size: ImageSize::SwapchainRelative { scale: 1.0 },
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                lifetime: ImageLifetime::Global,
+                samples: vk::SampleCountFlags::TYPE_1,
+            }),
+        };
+        self
+    }
+}
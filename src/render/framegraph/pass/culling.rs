@@ -0,0 +1,248 @@
+use anyhow::Context;
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    buffer::CompositeBufferKey,
+    render::{
+        framegraph::{
+            graph::RenderingInfo,
+            image::ImageRequirement,
+            pass::{
+                BufferBarrierPrecursor, DepthPyramid, ImageBarrierPrecursor, RenderPass,
+                RenderPassContext,
+            },
+        },
+        pipeline::{ComputePipelineDesc, PipelineDesc},
+        shader::ShaderId,
+    },
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches `OCCLUSION` in `cull.comp.wgsl`.
+const FLAG_OCCLUSION: u32 = 1;
+
+/// Buffers the culling shader reads and writes. All of them must be storage buffers registered
+/// in the bindless heap.
+#[derive(Clone, Copy)]
+pub struct CullingBuffers {
+    /// One `[f32; 4]` per instance: world-space bounding sphere centre and radius.
+    pub bounds: CompositeBufferKey,
+    /// One `vk::DrawIndexedIndirectCommand` per instance.
+    pub draws: CompositeBufferKey,
+    /// Receives the commands of visible instances, so it needs room for all of them. Also
+    /// needs `Indirect` usage.
+    pub visible: CompositeBufferKey,
+    /// A single `u32` holding the number of visible commands. Also needs `Indirect` usage.
+    pub count: CompositeBufferKey,
+}
+
+/// Culls instances against the camera frustum and compacts the draws of the survivors for
+/// `vkCmdDrawIndexedIndirectCount`. The camera and instance count come from
+/// `RenderData::cull`; without it nothing is drawn.
+pub struct CullingPass {
+    buffers: CullingBuffers,
+    readback: Option<CompositeBufferKey>,
+    occlusion: Option<DepthPyramid>,
+}
+
+impl CullingPass {
+    pub fn new(buffers: CullingBuffers) -> Self {
+        Self {
+            buffers,
+            readback: None,
+            occlusion: None,
+        }
+    }
+
+    /// Also culls instances hidden behind what `pyramid` last recorded, usually the previous
+    /// frame's depth built by a `DepthPyramidPass` later in the graph. Objects coming out from
+    /// behind an occluder can show up a frame late.
+    pub fn occlusion(mut self, pyramid: DepthPyramid) -> Self {
+        self.occlusion = Some(pyramid);
+        self
+    }
+
+    /// Also copies the visible count into `readback`, a per-frame `Readback` buffer, for the
+    /// CPU to read once the frame's fence has signalled.
+    pub fn readback(mut self, readback: CompositeBufferKey) -> Self {
+        self.readback = Some(readback);
+        self
+    }
+
+    fn bindless_index(
+        &self,
+        ctx: &RenderPassContext,
+        key: CompositeBufferKey,
+    ) -> anyhow::Result<u32> {
+        let buffer = ctx.buffer_manager.resolve_buffer(key, ctx.frame_index);
+        buffer
+            .bindless
+            .map(|index| index.raw())
+            .with_context(|| format!("culling buffer {} is not in the bindless heap", buffer.spec))
+    }
+}
+
+/// Matches `Cull` in `cull.comp.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullConstants {
+    view_proj: [f32; 16],
+    instance_count: u32,
+    flags: u32,
+    bounds: u32,
+    draws: u32,
+    visible: u32,
+    count: u32,
+    pyramid: u32,
+    pyramid_size: u32,
+}
+
+// Only 4-byte fields, so there is no padding.
+unsafe impl Zeroable for CullConstants {}
+unsafe impl Pod for CullConstants {}
+
+impl RenderPass for CullingPass {
+    fn id(&self) -> u32 {
+        2
+    }
+
+    fn execute(&self, ctx: &RenderPassContext) -> anyhow::Result<()> {
+        let count = ctx
+            .buffer_manager
+            .resolve_buffer(self.buffers.count, ctx.frame_index);
+        let visible = ctx
+            .buffer_manager
+            .resolve_buffer(self.buffers.visible, ctx.frame_index);
+
+        // Draws from the previous use of these buffers have to finish reading them first.
+        ctx.memory_barrier(
+            (
+                vk::PipelineStageFlags2::DRAW_INDIRECT,
+                vk::AccessFlags2::empty(),
+            ),
+            (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        );
+        unsafe {
+            ctx.device
+                .cmd_fill_buffer(ctx.cmd, count.vk_buffer, 0, size_of::<u32>() as u64, 0);
+            // Without drawIndirectCount every command up to the maximum is drawn, so the slots
+            // no instance lands in must draw nothing.
+            if !ctx.features.draw_indirect_count {
+                ctx.device
+                    .cmd_fill_buffer(ctx.cmd, visible.vk_buffer, 0, vk::WHOLE_SIZE, 0);
+            }
+        }
+        ctx.memory_barrier(
+            (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER | vk::PipelineStageFlags2::DRAW_INDIRECT,
+                vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_WRITE
+                    | vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            ),
+        );
+
+        let Some(view) = ctx.render_data.cull else {
+            return Ok(());
+        };
+        if view.instance_count == 0 {
+            return Ok(());
+        }
+
+        let mut constants = CullConstants {
+            view_proj: view.view_proj,
+            instance_count: view.instance_count,
+            flags: 0,
+            bounds: self.bindless_index(ctx, self.buffers.bounds)?,
+            draws: self.bindless_index(ctx, self.buffers.draws)?,
+            visible: self.bindless_index(ctx, self.buffers.visible)?,
+            count: self.bindless_index(ctx, self.buffers.count)?,
+            pyramid: 0,
+            pyramid_size: 0,
+        };
+        // The pyramid pass ends with a barrier making its writes visible to later compute
+        // reads, this one included.
+        if let Some(pyramid) = self.occlusion {
+            constants.flags |= FLAG_OCCLUSION;
+            constants.pyramid = self.bindless_index(ctx, pyramid.buffer)?;
+            constants.pyramid_size = pyramid.size;
+        }
+
+        unsafe {
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::COMPUTE, ctx.pipeline);
+        }
+        ctx.push_constants(&constants)?;
+        unsafe {
+            ctx.device
+                .cmd_dispatch(ctx.cmd, view.instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        ctx.memory_barrier(
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::DRAW_INDIRECT,
+                vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            ),
+        );
+
+        if let Some(readback) = self.readback {
+            let region = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size: size_of::<u32>() as vk::DeviceSize,
+            };
+            ctx.buffer_manager.copy(
+                ctx.device,
+                ctx.cmd,
+                self.buffers.count,
+                readback,
+                ctx.frame_index,
+                &[region],
+            )?;
+            ctx.memory_barrier(
+                (
+                    vk::PipelineStageFlags2::ALL_TRANSFER,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                ),
+                (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ),
+            );
+        }
+        Ok(())
+    }
+
+    fn image_precursors(&self) -> Vec<ImageBarrierPrecursor> {
+        vec![]
+    }
+
+    fn buffer_precursors(&self) -> Vec<BufferBarrierPrecursor> {
+        vec![]
+    }
+
+    fn image_requirements(&self) -> &[ImageRequirement] {
+        &[]
+    }
+
+    fn rendering_info(&self) -> RenderingInfo {
+        RenderingInfo {
+            color_formats: &[],
+            depth_format: None,
+            stencil_format: None,
+        }
+    }
+
+    fn pipeline_desc(&self) -> PipelineDesc {
+        ComputePipelineDesc::new(ShaderId::CULL_COMP).into()
+    }
+}
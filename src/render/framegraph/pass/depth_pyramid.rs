@@ -0,0 +1,193 @@
+use anyhow::Context;
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    buffer::CompositeBufferKey,
+    render::{
+        framegraph::{
+            ImageState,
+            graph::{ImageAlias, RenderingInfo},
+            image::{
+                FrameIndexKind, ImageAccess, ImageCreation, ImageIndexing, ImageRequirement,
+                ImageUsage,
+            },
+            pass::{
+                BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass, RenderPassContext,
+                attachment::AttachmentResolver,
+            },
+        },
+        pipeline::{ComputePipelineDesc, PipelineDesc},
+        shader::ShaderId,
+    },
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// A Hi-Z pyramid in a storage buffer, laid out as `depth_pyramid.comp.wgsl` describes: the
+/// view-projection matrix it was built with, then square levels of farthest depth from `size`
+/// texels a side down to 1.
+#[derive(Clone, Copy)]
+pub struct DepthPyramid {
+    /// A global storage buffer registered in the bindless heap, of `byte_size(size)` bytes.
+    pub buffer: CompositeBufferKey,
+    /// Side of level 0, a power of two.
+    pub size: u32,
+}
+
+impl DepthPyramid {
+    /// Bytes of the view-projection matrix ahead of level 0.
+    pub const HEADER_BYTES: vk::DeviceSize = size_of::<[f32; 16]>() as vk::DeviceSize;
+
+    pub fn byte_size(size: u32) -> vk::DeviceSize {
+        let texels: vk::DeviceSize = (0..=size.ilog2())
+            .map(|level| (size >> level) as vk::DeviceSize)
+            .map(|side| side * side)
+            .sum();
+        Self::HEADER_BYTES + texels * size_of::<f32>() as vk::DeviceSize
+    }
+
+    fn levels(self) -> u32 {
+        self.size.ilog2() + 1
+    }
+}
+
+/// Builds `pyramid` from `ForwardDepth`, for the next frame's `CullingPass` to test against.
+/// The camera comes from `RenderData::cull`; without it the pyramid is left as it was.
+pub struct DepthPyramidPass {
+    image_requirements: Vec<ImageRequirement>,
+    pyramid: DepthPyramid,
+}
+
+impl DepthPyramidPass {
+    pub fn new(pyramid: DepthPyramid) -> Self {
+        Self {
+            image_requirements: vec![ImageRequirement {
+                access: ImageAccess {
+                    alias: ImageAlias::ForwardDepth,
+                    usage: ImageUsage {
+                        state: ImageState::COMPUTE_READ,
+                        aspects: vk::ImageAspectFlags::DEPTH,
+                    },
+                    indexing: ImageIndexing::PerFrame(FrameIndexKind::Frame),
+                },
+                creation: ImageCreation::UseExisting,
+            }],
+            pyramid,
+        }
+    }
+}
+
+/// Matches `Pyramid` in `depth_pyramid.comp.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PyramidConstants {
+    view_proj: [f32; 16],
+    depth: u32,
+    pyramid: u32,
+    level: u32,
+    size: u32,
+}
+
+// Only 4-byte fields, so there is no padding.
+unsafe impl Zeroable for PyramidConstants {}
+unsafe impl Pod for PyramidConstants {}
+
+impl RenderPass for DepthPyramidPass {
+    fn id(&self) -> u32 {
+        3
+    }
+
+    fn execute(&self, ctx: &RenderPassContext) -> anyhow::Result<()> {
+        let Some(view) = ctx.render_data.cull else {
+            return Ok(());
+        };
+
+        let resolver = AttachmentResolver {
+            registry: ctx.registry,
+            image_manager: ctx.image_manager,
+            frame_index: ctx.frame_index as u32,
+            swapchain_image_index: ctx.swapchain_image_index,
+        };
+        let pyramid = ctx
+            .buffer_manager
+            .resolve_buffer(self.pyramid.buffer, ctx.frame_index);
+        let mut constants = PyramidConstants {
+            view_proj: view.view_proj,
+            depth: resolver.texture(ImageAlias::ForwardDepth)?.raw(),
+            pyramid: pyramid
+                .bindless
+                .with_context(|| {
+                    format!("depth pyramid {} is not in the bindless heap", pyramid.spec)
+                })?
+                .raw(),
+            level: 0,
+            size: self.pyramid.size,
+        };
+
+        // This frame's culling pass read the pyramid the previous frame built.
+        ctx.memory_barrier(
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+        );
+        unsafe {
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::COMPUTE, ctx.pipeline);
+        }
+        for level in 0..self.pyramid.levels() {
+            constants.level = level;
+            ctx.push_constants(&constants)?;
+            let groups = (self.pyramid.size >> level).div_ceil(WORKGROUP_SIZE);
+            unsafe {
+                ctx.device.cmd_dispatch(ctx.cmd, groups, groups, 1);
+            }
+            // Each level is reduced from the one before it.
+            ctx.memory_barrier(
+                (
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                ),
+                (
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::AccessFlags2::SHADER_STORAGE_READ,
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    fn image_precursors(&self) -> Vec<ImageBarrierPrecursor> {
+        self.image_requirements
+            .iter()
+            .map(|image_req| ImageBarrierPrecursor {
+                access: image_req.access,
+            })
+            .collect()
+    }
+
+    fn buffer_precursors(&self) -> Vec<BufferBarrierPrecursor> {
+        vec![]
+    }
+
+    fn image_requirements(&self) -> &[ImageRequirement] {
+        &self.image_requirements
+    }
+
+    fn rendering_info(&self) -> RenderingInfo {
+        RenderingInfo {
+            color_formats: &[],
+            depth_format: None,
+            stencil_format: None,
+        }
+    }
+
+    fn pipeline_desc(&self) -> PipelineDesc {
+        ComputePipelineDesc::new(ShaderId::DEPTH_PYRAMID_COMP).into()
+    }
+}
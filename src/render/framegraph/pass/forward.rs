@@ -1,27 +1,133 @@
 use anyhow::Context;
 use ash::vk;
+use bytemuck::{Pod, Zeroable};
 
 use crate::{
+    buffer::CompositeBufferKey,
     image::ImageLifetime,
     render::{
         framegraph::{
             ImageState,
-            alias::{ImageDesc, ImageFormat, ImageSize},
+            alias::{DEPTH_FORMAT, ImageDesc, ImageFormat, ImageSize},
             graph::{ImageAlias, RenderingInfo},
             image::{
                 FrameIndexKind, ImageAccess, ImageCreation, ImageIndexing, ImageRequirement,
                 ImageUsage,
             },
-            pass::{ImageBarrierPrecursor, RenderPass, attachment::AttachmentResolver},
+            pass::{
+                ImageBarrierPrecursor, RenderPass, RenderPassContext,
+                attachment::AttachmentResolver,
+            },
         },
-        pipeline::GraphicsPipelineDesc,
+        pipeline::{DepthStencilState, GraphicsPipelineDesc, PipelineDesc},
         shader::ShaderId,
     },
 };
 
+/// Indexed draws whose commands and count live in GPU buffers, usually a `CullingPass`'s
+/// output.
+#[derive(Clone, Copy)]
+pub struct IndirectDraws {
+    pub commands: CompositeBufferKey,
+    pub count: CompositeBufferKey,
+    pub index_buffer: CompositeBufferKey,
+    pub index_type: vk::IndexType,
+    pub max_draws: u32,
+    /// Storage buffers the vertex shader pulls vertices and per-instance data from. Both must
+    /// be registered in the bindless heap.
+    pub vertices: CompositeBufferKey,
+    pub instances: CompositeBufferKey,
+}
+
+/// Push constants of an indirect draw. Matches `Draw` in `forward_indirect.vert.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DrawConstants {
+    view_proj: [f32; 16],
+    vertices: u32,
+    instances: u32,
+}
+
+// Only 4-byte fields, so there is no padding.
+unsafe impl Zeroable for DrawConstants {}
+unsafe impl Pod for DrawConstants {}
+
 pub struct ForwardPass {
     image_requirements: Vec<ImageRequirement>,
     color_value: vk::ClearValue,
+    depth_value: vk::ClearValue,
+    pipeline: GraphicsPipelineDesc,
+    draws: Option<IndirectDraws>,
+}
+
+impl ForwardPass {
+    /// Draws `draws` with `pipeline` instead of the fullscreen triangle, depth-tested against
+    /// `ForwardDepth`. Its vertex shader pulls vertices and instances itself and takes the push
+    /// constants `forward_indirect.vert.wgsl` declares. The camera comes from
+    /// `RenderData::cull`; without it nothing is drawn.
+    pub fn indirect(mut self, draws: IndirectDraws, pipeline: GraphicsPipelineDesc) -> Self {
+        self.draws = Some(draws);
+        self.pipeline = pipeline
+            .depth_format(DEPTH_FORMAT)
+            .depth_stencil(DepthStencilState::LESS_WRITE);
+        self.image_requirements.push(ImageRequirement {
+            access: ImageAccess {
+                alias: ImageAlias::ForwardDepth,
+                usage: ImageUsage {
+                    state: ImageState::DEPTH_ATTACHMENT_WRITE,
+                    aspects: vk::ImageAspectFlags::DEPTH,
+                },
+                indexing: ImageIndexing::PerFrame(FrameIndexKind::Frame),
+            },
+            creation: ImageCreation::Declare(ImageDesc {
+                format: ImageFormat::Depth,
+                size: ImageSize::SwapchainRelative { scale: 1.0 },
+                // Sampled to build the depth pyramid.
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                lifetime: ImageLifetime::PerFrame,
+                samples: vk::SampleCountFlags::TYPE_1,
+            }),
+        });
+        self
+    }
+
+    fn draw_indirect(&self, ctx: &RenderPassContext, draws: IndirectDraws) -> anyhow::Result<()> {
+        let Some(view) = ctx.render_data.cull else {
+            return Ok(());
+        };
+
+        let buffers = ctx.buffer_manager;
+        let bindless_index = |key| {
+            let buffer = buffers.resolve_buffer(key, ctx.frame_index);
+            buffer
+                .bindless
+                .map(|index| index.raw())
+                .with_context(|| format!("{} is not in the bindless heap", buffer.spec))
+        };
+        ctx.push_constants(&DrawConstants {
+            view_proj: view.view_proj,
+            vertices: bindless_index(draws.vertices)?,
+            instances: bindless_index(draws.instances)?,
+        })?;
+
+        let index_buffer = buffers.resolve_buffer(draws.index_buffer, ctx.frame_index);
+        unsafe {
+            ctx.device
+                .cmd_bind_index_buffer(ctx.cmd, index_buffer.vk_buffer, 0, draws.index_type);
+        }
+        ctx.draw_indexed_indirect_count(
+            buffers
+                .resolve_buffer(draws.commands, ctx.frame_index)
+                .vk_buffer,
+            0,
+            buffers
+                .resolve_buffer(draws.count, ctx.frame_index)
+                .vk_buffer,
+            0,
+            draws.max_draws,
+        );
+        Ok(())
+    }
 }
 
 impl Default for ForwardPass {
@@ -31,6 +137,12 @@ impl Default for ForwardPass {
                 float32: [0.0, 0.584, 0.929, 1.0],
             },
         };
+        let depth_value = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        };
         Self {
             image_requirements: vec![ImageRequirement {
                 access: ImageAccess {
@@ -50,6 +162,9 @@ impl Default for ForwardPass {
                 }),
             }],
             color_value,
+            depth_value,
+            pipeline: GraphicsPipelineDesc::new(ShaderId::FORWARD_VERT, ShaderId::FORWARD_FRAG),
+            draws: None,
         }
     }
 }
@@ -59,7 +174,7 @@ impl RenderPass for ForwardPass {
         0
     }
 
-    fn execute(&self, ctx: &super::RenderPassContext) -> anyhow::Result<()> {
+    fn execute(&self, ctx: &RenderPassContext) -> anyhow::Result<()> {
         let resolver = AttachmentResolver {
             registry: ctx.registry,
             image_manager: ctx.image_manager,
@@ -78,13 +193,28 @@ impl RenderPass for ForwardPass {
             .store_op(vk::AttachmentStoreOp::STORE)
             .clear_value(self.color_value)];
 
-        let rendering_info = vk::RenderingInfo::default()
+        let depth_attachment_info = match self.draws {
+            Some(_) => Some(
+                vk::RenderingAttachmentInfo::default()
+                    .image_view(resolver.image_view(ImageAlias::ForwardDepth)?)
+                    .image_layout(ImageState::DEPTH_ATTACHMENT_WRITE.layout)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .clear_value(self.depth_value),
+            ),
+            None => None,
+        };
+
+        let mut rendering_info = vk::RenderingInfo::default()
             .render_area(vk::Rect2D {
                 offset: vk::Offset2D::default(),
-                extent: ctx.swapchain_extent,
+                extent: ctx.extent,
             })
             .layer_count(1)
             .color_attachments(&color_attachment_info);
+        if let Some(depth) = &depth_attachment_info {
+            rendering_info = rendering_info.depth_attachment(depth);
+        }
 
         unsafe {
             ctx.device.cmd_begin_rendering(ctx.cmd, &rendering_info);
@@ -92,7 +222,14 @@ impl RenderPass for ForwardPass {
                 .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, ctx.pipeline);
             ctx.device.cmd_set_viewport(ctx.cmd, 0, &[ctx.viewport]);
             ctx.device.cmd_set_scissor(ctx.cmd, 0, &[ctx.snizzor]);
-            ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0);
+        }
+
+        match self.draws {
+            Some(draws) => self.draw_indirect(ctx, draws)?,
+            None => unsafe { ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0) },
+        }
+
+        unsafe {
             ctx.device.cmd_end_rendering(ctx.cmd);
         }
 
@@ -119,18 +256,12 @@ impl RenderPass for ForwardPass {
     fn rendering_info(&self) -> crate::render::framegraph::graph::RenderingInfo {
         RenderingInfo {
             color_formats: &[vk::Format::B8G8R8A8_SRGB],
-            depth_format: None,
+            depth_format: self.draws.map(|_| DEPTH_FORMAT),
             stencil_format: None,
         }
     }
 
-    fn pipeline_desc(&self) -> crate::render::pipeline::GraphicsPipelineDesc {
-        GraphicsPipelineDesc {
-            vertex_id: ShaderId::ForwardVert,
-            fragment_id: ShaderId::ForwardFrag,
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            color_formats: vec![],
-            depth_format: None,
-        }
+    fn pipeline_desc(&self) -> PipelineDesc {
+        self.pipeline.clone().into()
     }
 }
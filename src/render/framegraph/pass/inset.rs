@@ -0,0 +1,162 @@
+use ash::vk;
+
+use crate::render::{
+    SamplerIndex,
+    framegraph::{
+        ImageState,
+        graph::{ImageAlias, RenderingInfo},
+        image::{
+            FrameIndexKind, ImageAccess, ImageCreation, ImageIndexing, ImageRequirement, ImageUsage,
+        },
+        pass::{
+            BufferBarrierPrecursor, ImageBarrierPrecursor, RenderPass, RenderPassContext,
+            attachment::AttachmentResolver, composition::CompositeConstants,
+        },
+    },
+    pipeline::{GraphicsPipelineDesc, PipelineDesc},
+    shader::ShaderId,
+};
+
+/// Gap between the inset and the edges of the swapchain, in pixels.
+const MARGIN: u32 = 16;
+
+/// Draws `source`, a global image imported from another graph, over the bottom-right corner of
+/// the swapchain at `extent`. It has no fallback, so it is skipped in frames in which `source`
+/// isn't complete.
+pub struct InsetPass {
+    image_requirements: Vec<ImageRequirement>,
+    source: ImageAlias,
+    sampler: SamplerIndex,
+    extent: vk::Extent2D,
+}
+
+impl InsetPass {
+    pub fn new(source: ImageAlias, sampler: SamplerIndex, extent: vk::Extent2D) -> Self {
+        Self {
+            image_requirements: vec![
+                ImageRequirement {
+                    access: ImageAccess {
+                        alias: ImageAlias::SwapchainImage,
+                        usage: ImageUsage {
+                            state: ImageState::COLOR_ATTACHMENT_WRITE,
+                            aspects: vk::ImageAspectFlags::COLOR,
+                        },
+                        indexing: ImageIndexing::PerFrame(FrameIndexKind::Swapchain),
+                    },
+                    creation: ImageCreation::UseExisting,
+                },
+                ImageRequirement {
+                    access: ImageAccess {
+                        alias: source,
+                        usage: ImageUsage {
+                            state: ImageState::SHADER_READ,
+                            aspects: vk::ImageAspectFlags::COLOR,
+                        },
+                        indexing: ImageIndexing::Global,
+                    },
+                    creation: ImageCreation::UseExisting,
+                },
+            ],
+            source,
+            sampler,
+            extent,
+        }
+    }
+}
+
+impl RenderPass for InsetPass {
+    fn id(&self) -> u32 {
+        4
+    }
+
+    fn execute(&self, ctx: &RenderPassContext) -> anyhow::Result<()> {
+        let resolver = AttachmentResolver {
+            registry: ctx.registry,
+            image_manager: ctx.image_manager,
+            frame_index: ctx.frame_index as u32,
+            swapchain_image_index: ctx.swapchain_image_index,
+        };
+
+        // Drawn over whatever composition left in the swapchain image.
+        let color_attachment_info = [vk::RenderingAttachmentInfo::default()
+            .image_view(resolver.image_view(ImageAlias::SwapchainImage)?)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(ctx.snizzor)
+            .layer_count(1)
+            .color_attachments(&color_attachment_info);
+
+        let extent = vk::Extent2D {
+            width: self.extent.width.min(ctx.extent.width),
+            height: self.extent.height.min(ctx.extent.height),
+        };
+        let offset = vk::Offset2D {
+            x: ctx.extent.width.saturating_sub(extent.width + MARGIN) as i32,
+            y: ctx.extent.height.saturating_sub(extent.height + MARGIN) as i32,
+        };
+        let viewport = vk::Viewport {
+            x: offset.x as f32,
+            y: offset.y as f32,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            ..ctx.viewport
+        };
+
+        unsafe {
+            ctx.device.cmd_begin_rendering(ctx.cmd, &rendering_info);
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, ctx.pipeline);
+            ctx.device.cmd_set_viewport(ctx.cmd, 0, &[viewport]);
+            ctx.device
+                .cmd_set_scissor(ctx.cmd, 0, &[vk::Rect2D { offset, extent }]);
+        }
+        ctx.push_constants(&CompositeConstants {
+            texture: resolver.texture(self.source)?.raw(),
+            sampler: self.sampler.raw(),
+            origin: [viewport.x, viewport.y],
+            size: [viewport.width, viewport.height],
+        })?;
+        unsafe {
+            ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0);
+            ctx.device.cmd_end_rendering(ctx.cmd);
+        }
+
+        Ok(())
+    }
+
+    fn image_precursors(&self) -> Vec<ImageBarrierPrecursor> {
+        self.image_requirements
+            .iter()
+            .map(|image_req| ImageBarrierPrecursor {
+                access: image_req.access,
+            })
+            .collect()
+    }
+
+    fn buffer_precursors(&self) -> Vec<BufferBarrierPrecursor> {
+        vec![]
+    }
+
+    fn image_requirements(&self) -> &[ImageRequirement] {
+        &self.image_requirements
+    }
+
+    fn rendering_info(&self) -> RenderingInfo {
+        RenderingInfo {
+            color_formats: &[vk::Format::B8G8R8A8_SRGB],
+            depth_format: None,
+            stencil_format: None,
+        }
+    }
+
+    fn pipeline_desc(&self) -> PipelineDesc {
+        GraphicsPipelineDesc::new(
+            ShaderId::COMPOSITION_VERT,
+            ShaderId::COMPOSITION_SAMPLED_FRAG,
+        )
+        .into()
+    }
+}
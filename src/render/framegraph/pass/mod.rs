@@ -1,10 +1,16 @@
 mod attachment;
 mod composition;
+mod culling;
+mod depth_pyramid;
 mod forward;
+mod inset;
 
+use anyhow::Context;
 use ash::vk;
+use bytemuck::Pod;
 
 use crate::{
+    buffer::{BufferManager, LinearKey},
     image::ImageManager,
     render::{
         framegraph::{
@@ -12,9 +18,12 @@ use crate::{
             barrier::BufferAlias,
             image::{ImageAccess, ImageRequirement},
         },
-        pipeline::GraphicsPipelineDesc,
+        indirect::DRAW_INDEXED_STRIDE,
+        pipeline::{PipelineDesc, PipelineLayoutInfo},
         render_packet::RenderData,
+        uniform::UniformSets,
     },
+    vulkan::{DeviceContext, DeviceFeatures},
 };
 
 pub struct BufferBarrierPrecursor {
@@ -29,16 +38,213 @@ pub struct ImageBarrierPrecursor {
 
 pub struct RenderPassContext<'a> {
     pub device: &'a ash::Device,
+    pub device_context: &'a DeviceContext,
+    pub allocator: &'a vk_mem::Allocator,
     pub cmd: vk::CommandBuffer,
     pub pipeline: vk::Pipeline,
+    /// Set when `pipeline` is the pass's fallback, because its own is still compiling or an
+    /// image it reads wasn't produced this frame.
+    pub fallback: bool,
+    pub pipeline_layout: &'a PipelineLayoutInfo,
+    pub bind_point: vk::PipelineBindPoint,
     pub frame_index: usize,
     pub swapchain_image_index: u32,
     pub registry: &'a ResolvedRegistry,
     pub image_manager: &'a ImageManager,
-    pub swapchain_extent: vk::Extent2D,
+    pub buffer_manager: &'a BufferManager,
+    pub extent: vk::Extent2D,
     pub viewport: vk::Viewport,
     pub snizzor: vk::Rect2D,
-    pub _render_data: &'a RenderData,
+    pub uniform_sets: &'a UniformSets,
+    /// Linear allocator `upload_uniform` takes this frame's uniform data from.
+    pub uniform_data: LinearKey,
+    pub features: DeviceFeatures,
+    pub render_data: &'a RenderData,
+}
+
+impl RenderPassContext<'_> {
+    /// Pushes `value` at the start of the pipeline's push-constant range.
+    pub fn push_constants<T: Pod>(&self, value: &T) -> anyhow::Result<()> {
+        self.push_constants_at(0, value)
+    }
+
+    pub fn push_constants_at<T: Pod>(&self, offset: u32, value: &T) -> anyhow::Result<()> {
+        let range = self
+            .pipeline_layout
+            .push_constants
+            .context("pipeline declares no push constants")?;
+
+        let bytes = bytemuck::bytes_of(value);
+        let end = offset as usize + bytes.len();
+        if end > range.size as usize {
+            anyhow::bail!(
+                "push constant write of {} bytes at offset {} exceeds the {}-byte range",
+                bytes.len(),
+                offset,
+                range.size
+            );
+        }
+
+        unsafe {
+            self.device.cmd_push_constants(
+                self.cmd,
+                self.pipeline_layout.layout,
+                range.stage_flags,
+                offset,
+                bytes,
+            );
+        }
+        Ok(())
+    }
+
+    /// Orders the pass's own memory accesses. The framegraph only tracks images, so passes
+    /// touching buffers synchronise those themselves.
+    pub fn memory_barrier(
+        &self,
+        (src_stage, src_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+        (dst_stage, dst_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) {
+        let barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_stage_mask(dst_stage)
+            .dst_access_mask(dst_access);
+        unsafe {
+            self.device.cmd_pipeline_barrier2(
+                self.cmd,
+                &vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&barrier)),
+            );
+        }
+    }
+
+    /// Copies `value` into this frame's block of the uniform linear allocator and binds it as
+    /// `binding` of descriptor set `set`. Other bindings in that set are left unwritten, so the
+    /// set should hold only per-pass uniforms.
+    pub fn upload_uniform<T: Pod>(&self, set: u32, binding: u32, value: &T) -> anyhow::Result<()> {
+        let set_layout = *self
+            .pipeline_layout
+            .set_layouts
+            .get(set as usize)
+            .with_context(|| {
+                format!(
+                    "pipeline layout has {} descriptor set(s), no set {}",
+                    self.pipeline_layout.set_layouts.len(),
+                    set
+                )
+            })?;
+
+        match self.pipeline_layout.bindings.get(&(set, binding)).copied() {
+            Some(vk::DescriptorType::UNIFORM_BUFFER) => {}
+            Some(other) => anyhow::bail!(
+                "binding {}.{} is a {:?}, not a uniform buffer",
+                set,
+                binding,
+                other
+            ),
+            None => anyhow::bail!("pipeline's shaders declare no binding {}.{}", set, binding),
+        }
+
+        let bytes = bytemuck::bytes_of(value);
+        let mut allocation = self.buffer_manager.linear_alloc(
+            self.allocator,
+            self.device_context,
+            self.uniform_data,
+            self.frame_index,
+            bytes.len() as vk::DeviceSize,
+            1,
+        )?;
+        // Host-coherent memory whose previous use has retired, so a plain copy is enough.
+        allocation.mapped().copy_from_slice(bytes);
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer: allocation.buffer,
+            offset: allocation.offset,
+            range: bytes.len() as vk::DeviceSize,
+        };
+        let descriptor_set = self.uniform_sets.allocate_set(self.device, set_layout)?;
+
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(std::slice::from_ref(&buffer_info));
+
+        unsafe {
+            self.device.update_descriptor_sets(&[write], &[]);
+            self.device.cmd_bind_descriptor_sets(
+                self.cmd,
+                self.bind_point,
+                self.pipeline_layout.layout,
+                set,
+                &[descriptor_set],
+                &[],
+            );
+        }
+        Ok(())
+    }
+
+    /// Issues `draw_count` indexed draws read from `buffer` at `offset`, tightly packed. Falls
+    /// back to one call per draw on devices without multi-draw-indirect.
+    pub fn draw_indexed_indirect(
+        &self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+    ) {
+        if draw_count <= 1 || self.features.multi_draw_indirect {
+            unsafe {
+                self.device.cmd_draw_indexed_indirect(
+                    self.cmd,
+                    buffer,
+                    offset,
+                    draw_count,
+                    DRAW_INDEXED_STRIDE,
+                );
+            }
+            return;
+        }
+
+        for i in 0..draw_count {
+            let offset = offset + i as vk::DeviceSize * DRAW_INDEXED_STRIDE as vk::DeviceSize;
+            unsafe {
+                self.device.cmd_draw_indexed_indirect(
+                    self.cmd,
+                    buffer,
+                    offset,
+                    1,
+                    DRAW_INDEXED_STRIDE,
+                );
+            }
+        }
+    }
+
+    /// Like `draw_indexed_indirect`, but the GPU reads the draw count as a `u32` from
+    /// `count_buffer`, typically written by a culling pass. At most `max_draw_count` draws run.
+    /// Devices without drawIndirectCount run all `max_draw_count` of them instead, so commands
+    /// past the count must have zero instances.
+    pub fn draw_indexed_indirect_count(
+        &self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: vk::Buffer,
+        count_offset: vk::DeviceSize,
+        max_draw_count: u32,
+    ) {
+        if !self.features.draw_indirect_count {
+            self.draw_indexed_indirect(buffer, offset, max_draw_count);
+            return;
+        }
+        unsafe {
+            self.device.cmd_draw_indexed_indirect_count(
+                self.cmd,
+                buffer,
+                offset,
+                count_buffer,
+                count_offset,
+                max_draw_count,
+                DRAW_INDEXED_STRIDE,
+            );
+        }
+    }
 }
 
 pub trait RenderPass {
@@ -48,9 +254,20 @@ pub trait RenderPass {
     fn buffer_precursors(&self) -> Vec<BufferBarrierPrecursor>;
     fn image_requirements(&self) -> &[ImageRequirement];
     fn rendering_info(&self) -> super::graph::RenderingInfo;
-    fn pipeline_desc(&self) -> GraphicsPipelineDesc;
+    fn pipeline_desc(&self) -> PipelineDesc;
+    /// Recorded with instead of `pipeline_desc` until that has compiled. Without one, the pass
+    /// is skipped meanwhile.
+    fn fallback_pipeline_desc(&self) -> Option<PipelineDesc> {
+        None
+    }
 }
 
 pub use composition::CompositionPass;
 
-pub use forward::ForwardPass;
+pub use culling::{CullingBuffers, CullingPass};
+
+pub use depth_pyramid::{DepthPyramid, DepthPyramidPass};
+
+pub use forward::{ForwardPass, IndirectDraws};
+
+pub use inset::InsetPass;
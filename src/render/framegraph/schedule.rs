@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use ash::vk;
+
+use crate::{
+    image::{CompositeImageKey, ImageManager},
+    render::{
+        framegraph::{
+            FrameGraph,
+            graph::{ImageAlias, begin_primary, end_primary},
+        },
+        pipeline::PipelineManager,
+        thread::FrameExecutionContext,
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GraphId(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphRate {
+    EveryFrame,
+    /// Runs on every `n`th frame number.
+    Interval(u64),
+    /// Runs only after `FrameGraphSet::request`.
+    OnDemand,
+}
+
+struct ScheduledGraph {
+    graph: FrameGraph,
+    rate: GraphRate,
+    /// Set by `request` and cleared once the graph has produced all of its exports.
+    requested: bool,
+    secondary_offset: usize,
+}
+
+/// Executes several framegraphs into the same primary command buffer, in the order they were
+/// added and each at its own rate. Graphs that import images must be added after the graph
+/// exporting them.
+#[derive(Default)]
+pub struct FrameGraphSet {
+    graphs: Vec<ScheduledGraph>,
+    exported: HashSet<ImageAlias>,
+    /// Exports backed by one image rather than one per frame in flight, which keep their
+    /// contents across frames in which their graph doesn't run.
+    global_exports: HashSet<ImageAlias>,
+    /// Exports whose contents are complete for the frame being recorded.
+    produced: HashSet<ImageAlias>,
+    pass_count: usize,
+}
+
+impl FrameGraphSet {
+    pub fn add(&mut self, graph: FrameGraph, rate: GraphRate) -> anyhow::Result<GraphId> {
+        for alias in graph.imports() {
+            if !self.exported.contains(alias) {
+                anyhow::bail!(
+                    "graph '{}' imports {} before any graph exports it",
+                    graph.name(),
+                    alias
+                );
+            }
+        }
+
+        for alias in graph.exports() {
+            let global = matches!(graph.export(*alias)?.image, CompositeImageKey::Global(_));
+            if rate != GraphRate::EveryFrame && !global {
+                anyhow::bail!(
+                    "graph '{}' does not run every frame, so exported {} must be a global image",
+                    graph.name(),
+                    alias
+                );
+            }
+            if !self.exported.insert(*alias) {
+                anyhow::bail!("{} is exported by more than one graph", alias);
+            }
+            if global {
+                self.global_exports.insert(*alias);
+            }
+        }
+
+        let id = GraphId(self.graphs.len());
+        let secondary_offset = self.pass_count;
+        self.pass_count += graph.pass_count();
+
+        self.graphs.push(ScheduledGraph {
+            graph,
+            rate,
+            requested: false,
+            secondary_offset,
+        });
+
+        Ok(id)
+    }
+
+    /// Total number of passes across all graphs, i.e. the secondary command buffers each
+    /// `Frame` needs.
+    pub fn pass_count(&self) -> usize {
+        self.pass_count
+    }
+
+    /// Runs `id` on the next frame, whatever its rate, and on every frame after that until it
+    /// has produced all of its exports.
+    pub fn request(&mut self, id: GraphId) -> anyhow::Result<()> {
+        let scheduled = self
+            .graphs
+            .get_mut(id.0)
+            .with_context(|| format!("no graph registered for {:?}", id))?;
+        scheduled.requested = true;
+        Ok(())
+    }
+
+    pub fn execute(&mut self, ctx: &FrameExecutionContext) -> anyhow::Result<()> {
+        let device = ctx.device;
+        let frame = &ctx.frame;
+
+        begin_primary(device, frame.primary_cmd)?;
+
+        // A per-frame export is only complete once its producer has run for this frame.
+        let global_exports = &self.global_exports;
+        self.produced.retain(|alias| global_exports.contains(alias));
+
+        for scheduled in &mut self.graphs {
+            let due = match scheduled.rate {
+                GraphRate::EveryFrame => true,
+                GraphRate::Interval(n) => frame.number.is_multiple_of(n.max(1)),
+                GraphRate::OnDemand => false,
+            };
+
+            if !due && !scheduled.requested {
+                continue;
+            }
+
+            // Consumers of an image that wasn't produced would read stale or undefined
+            // contents, so the graph leaves those images alone and the passes reading them
+            // fall back.
+            let missing: HashSet<ImageAlias> = scheduled
+                .graph
+                .imports()
+                .filter(|alias| !self.produced.contains(alias))
+                .copied()
+                .collect();
+
+            let offset = scheduled.secondary_offset;
+            let secondary_cmds: &[vk::CommandBuffer] = frame
+                .secondary_cmds
+                .get(offset..offset + scheduled.graph.pass_count())
+                .with_context(|| {
+                    format!(
+                        "frame has too few secondary command buffers for graph '{}'",
+                        scheduled.graph.name()
+                    )
+                })?;
+
+            let complete = scheduled
+                .graph
+                .execute(ctx, secondary_cmds, &missing)
+                .with_context(|| format!("failed to execute graph '{}'", scheduled.graph.name()))?;
+
+            for alias in scheduled.graph.exports() {
+                if complete {
+                    self.produced.insert(*alias);
+                } else {
+                    self.produced.remove(alias);
+                }
+            }
+            if complete {
+                scheduled.requested = false;
+            }
+        }
+
+        end_primary(device, frame.primary_cmd)?;
+        Ok(())
+    }
+
+    /// Releases every graph's pipelines and the images it created. Images are retired like
+    /// any other, so they are freed once the frames using them have finished.
+    pub fn destroy(
+        &mut self,
+        pipeline_manager: &mut PipelineManager,
+        image_manager: &mut ImageManager,
+    ) -> anyhow::Result<()> {
+        for scheduled in &mut self.graphs {
+            scheduled.graph.release_images(image_manager);
+            scheduled
+                .graph
+                .release_pipelines(pipeline_manager)
+                .with_context(|| {
+                    format!(
+                        "failed to release pipelines of graph '{}'",
+                        scheduled.graph.name()
+                    )
+                })?;
+        }
+        self.graphs.clear();
+        self.exported.clear();
+        self.global_exports.clear();
+        self.produced.clear();
+        self.pass_count = 0;
+        Ok(())
+    }
+}
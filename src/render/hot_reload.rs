@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use crossbeam_channel::{Receiver, unbounded};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::render::shader::{
+    ShaderId,
+    compile::{Compiler, ShaderFile},
+};
+
+pub struct ShaderReload {
+    pub id: ShaderId,
+    pub spirv: Vec<u8>,
+}
+
+/// Watches the shader sources and recompiles them on change. Compilation happens on the
+/// watcher's thread; the render thread only picks up finished SPIR-V.
+pub struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    rx: Receiver<ShaderReload>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (tx, rx) = unbounded();
+        // Canonical so event paths can be matched against the include directory.
+        let source_dir = dir
+            .as_ref()
+            .canonicalize()
+            .with_context(|| format!("failed to resolve {}", dir.as_ref().display()))?;
+        let include_dir = source_dir.join("include");
+        let compiler = Compiler::detect();
+        if !compiler.has_glslc() {
+            log::info!("glslc not found; hot reload compiles GLSL with naga");
+        }
+        let watched_dir = source_dir.clone();
+        // What each stage included when it last compiled here. Stages not compiled since
+        // startup are missing and recompile on any include change.
+        let mut dependencies: HashMap<PathBuf, Vec<PathBuf>> = HashMap::default();
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let event = match res {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("shader watcher error: {e}");
+                    return;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+
+            for path in &event.paths {
+                for shader in sources_for_path(path, &source_dir, &dependencies) {
+                    match compiler.compile(&shader, &include_dir) {
+                        Ok(compiled) => {
+                            log::info!("Recompiled {}", shader.path.display());
+                            dependencies.insert(shader.path.clone(), compiled.dependencies);
+                            let _ = tx.send(ShaderReload {
+                                id: ShaderId::intern(&shader.name),
+                                spirv: compiled.spirv,
+                            });
+                        }
+                        Err(e) => log::error!(
+                            "failed to compile {}; keeping the previous version:\n{e}",
+                            shader.path.display()
+                        ),
+                    }
+                }
+            }
+        })
+        .context("failed to create shader watcher")?;
+
+        watcher
+            .watch(&watched_dir, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch {}", dir.as_ref().display()))?;
+
+        log::info!("Watching {} for shader changes", dir.as_ref().display());
+
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// Drains finished compiles, keeping only the newest per shader.
+    pub fn poll(&self) -> Vec<ShaderReload> {
+        let mut latest: HashMap<ShaderId, ShaderReload> = HashMap::default();
+        for reload in self.rx.try_iter() {
+            latest.insert(reload.id, reload);
+        }
+        latest.into_values().collect()
+    }
+}
+
+/// The changed file itself if it is a shader stage, or the stages in `source_dir` that may
+/// include it when an include changed.
+fn sources_for_path(
+    path: &Path,
+    source_dir: &Path,
+    dependencies: &HashMap<PathBuf, Vec<PathBuf>>,
+) -> Vec<ShaderFile> {
+    if let Some(shader) = ShaderFile::classify(path) {
+        return vec![shader];
+    }
+    if !path.starts_with(source_dir.join("include")) {
+        return Vec::new();
+    }
+    std::fs::read_dir(source_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| ShaderFile::classify(&entry.path()))
+                .filter(|shader| {
+                    dependencies
+                        .get(&shader.path)
+                        .is_none_or(|deps| deps.iter().any(|dep| dep == path))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
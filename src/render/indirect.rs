@@ -0,0 +1,54 @@
+use ash::vk;
+
+/// Bytes between consecutive commands in an indexed indirect buffer.
+pub const DRAW_INDEXED_STRIDE: u32 = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+/// Indexed draws collected on the CPU in the layout `vkCmdDrawIndexedIndirect` reads, ready to
+/// be copied into an indirect buffer.
+#[derive(Default)]
+pub struct IndirectDrawBuilder {
+    commands: Vec<vk::DrawIndexedIndirectCommand>,
+}
+
+impl IndirectDrawBuilder {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            commands: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// `vertex_offset` is added to each index; `first_instance` is visible to shaders as
+    /// `gl_BaseInstance`, which is how per-object data is usually found.
+    pub fn draw(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) -> &mut Self {
+        self.commands.push(vk::DrawIndexedIndirectCommand {
+            index_count,
+            instance_count,
+            first_index,
+            vertex_offset,
+            first_instance,
+        });
+        self
+    }
+
+    pub fn byte_size(&self) -> vk::DeviceSize {
+        self.commands.len() as vk::DeviceSize * DRAW_INDEXED_STRIDE as vk::DeviceSize
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: the command is `repr(C)` and made of five 4-byte integers, so it has no
+        // padding and any byte pattern read from it is initialised.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.commands.as_ptr().cast::<u8>(),
+                self.byte_size() as usize,
+            )
+        }
+    }
+}
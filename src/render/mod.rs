@@ -1,14 +1,21 @@
+mod bindless;
 mod frame;
 mod frame_ring;
 mod framegraph;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+mod indirect;
 mod pipeline;
 mod present;
 mod render_packet;
+mod scene;
 mod shader;
 mod submit;
 mod swapchain;
 mod thread;
+mod uniform;
 
+pub use bindless::{BindlessHeap, BufferIndex, SamplerIndex, TextureIndex};
 pub use frame::Frame;
 pub use frame_ring::FrameRing;
 pub use thread::render_thread;
@@ -0,0 +1,325 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use ash::vk;
+
+const CACHE_FILE_NAME: &str = "pipelines.bin";
+/// Subdirectory of the per-user cache directory the file goes into.
+const CACHE_DIR_NAME: &str = env!("CARGO_PKG_NAME");
+const CACHE_MAGIC: [u8; 4] = *b"SKPC";
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// magic, format version, vendor id, device id, driver version, uuid, data length, checksum
+const FILE_HEADER_LEN: usize = 4 + 4 + 4 + 4 + 4 + vk::UUID_SIZE + 8 + 8;
+
+/// Length of `VkPipelineCacheHeaderVersionOne`, which prefixes the driver's cache data.
+const VK_HEADER_LEN: usize = 16 + vk::UUID_SIZE;
+
+/// A `vk::PipelineCache` persisted to disk between runs.
+///
+/// The file is only trusted when it was written by the same device and driver. Anything else
+/// (another GPU, a driver update, truncated or corrupt data) is discarded and the cache starts
+/// out empty.
+pub struct PipelineCache {
+    cache: vk::PipelineCache,
+    path: PathBuf,
+    identity: DeviceIdentity,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct DeviceIdentity {
+    vendor_id: u32,
+    device_id: u32,
+    driver_version: u32,
+    uuid: [u8; vk::UUID_SIZE],
+}
+
+impl DeviceIdentity {
+    fn new(properties: &vk::PhysicalDeviceProperties) -> Self {
+        Self {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            uuid: properties.pipeline_cache_uuid,
+        }
+    }
+}
+
+impl PipelineCache {
+    /// The per-user cache directory (`XDG_CACHE_HOME`, `HOME/.cache`, then `LOCALAPPDATA`),
+    /// or a `cache` directory next to the executable when none of them is set. Never relative
+    /// to the working directory, which depends on how the app was launched.
+    pub fn default_dir() -> anyhow::Result<PathBuf> {
+        if let Some(dir) = user_cache_dir(|name| std::env::var_os(name)) {
+            return Ok(dir.join(CACHE_DIR_NAME));
+        }
+        let exe = std::env::current_exe().context("failed to locate the executable")?;
+        let dir = exe
+            .parent()
+            .with_context(|| format!("{} has no parent directory", exe.display()))?;
+        Ok(dir.join("cache"))
+    }
+
+    pub fn load(
+        device: &ash::Device,
+        properties: &vk::PhysicalDeviceProperties,
+        dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let path = dir.as_ref().join(CACHE_FILE_NAME);
+        let identity = DeviceIdentity::new(properties);
+
+        let initial_data = match fs::read(&path) {
+            Ok(bytes) => match validate(&bytes, &identity) {
+                Ok(data) => {
+                    log::debug!(
+                        "Loaded {} bytes of pipeline cache from {}",
+                        data.len(),
+                        path.display()
+                    );
+                    data.to_vec()
+                }
+                Err(e) => {
+                    log::warn!("Discarding pipeline cache {}: {e:#}", path.display());
+                    Vec::new()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                log::warn!("Failed to read pipeline cache {}: {e}", path.display());
+                Vec::new()
+            }
+        };
+
+        let cache = unsafe {
+            device
+                .create_pipeline_cache(
+                    &vk::PipelineCacheCreateInfo::default().initial_data(&initial_data),
+                    None,
+                )
+                .or_else(|e| {
+                    // Drivers may still reject data that passed our checks.
+                    log::warn!("Driver rejected pipeline cache data ({e:?}); starting empty");
+                    device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+                })
+                .context("failed to create pipeline cache")?
+        };
+
+        Ok(Self {
+            cache,
+            path,
+            identity,
+        })
+    }
+
+    pub fn handle(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    /// Writes the cache to disk, replacing the previous file atomically.
+    pub fn save(&self, device: &ash::Device) -> anyhow::Result<()> {
+        let data = unsafe {
+            device
+                .get_pipeline_cache_data(self.cache)
+                .context("failed to get pipeline cache data")?
+        };
+
+        let mut bytes = Vec::with_capacity(FILE_HEADER_LEN + data.len());
+        bytes.extend_from_slice(&CACHE_MAGIC);
+        bytes.extend_from_slice(&CACHE_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.identity.vendor_id.to_le_bytes());
+        bytes.extend_from_slice(&self.identity.device_id.to_le_bytes());
+        bytes.extend_from_slice(&self.identity.driver_version.to_le_bytes());
+        bytes.extend_from_slice(&self.identity.uuid);
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&checksum(&data).to_le_bytes());
+        bytes.extend_from_slice(&data);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, &bytes)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("failed to replace {}", self.path.display()))?;
+
+        log::debug!(
+            "Saved {} bytes of pipeline cache to {}",
+            data.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline_cache(self.cache, None);
+        }
+        self.cache = vk::PipelineCache::null();
+    }
+}
+
+/// Checks the file header against the current device and returns the driver's cache data.
+/// Relative values are ignored, as the XDG spec asks.
+fn user_cache_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let absolute = |name| {
+        var(name)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    absolute("XDG_CACHE_HOME")
+        .or_else(|| absolute("HOME").map(|home| home.join(".cache")))
+        .or_else(|| absolute("LOCALAPPDATA"))
+}
+
+fn validate<'a>(bytes: &'a [u8], identity: &DeviceIdentity) -> anyhow::Result<&'a [u8]> {
+    let mut reader = ByteReader { bytes, offset: 0 };
+
+    if reader.take(4)? != CACHE_MAGIC {
+        anyhow::bail!("not a pipeline cache file");
+    }
+
+    let version = reader.u32()?;
+    if version != CACHE_FORMAT_VERSION {
+        anyhow::bail!("unsupported cache format version {version}");
+    }
+
+    let stored = DeviceIdentity {
+        vendor_id: reader.u32()?,
+        device_id: reader.u32()?,
+        driver_version: reader.u32()?,
+        uuid: reader.take(vk::UUID_SIZE)?.try_into()?,
+    };
+    if stored != *identity {
+        anyhow::bail!("written for {:?}, current device is {:?}", stored, identity);
+    }
+
+    let data_len = reader.u64()? as usize;
+    let expected_checksum = reader.u64()?;
+    let data = reader.take(data_len)?;
+
+    if checksum(data) != expected_checksum {
+        anyhow::bail!("checksum mismatch");
+    }
+
+    validate_vk_header(data, identity)?;
+
+    Ok(data)
+}
+
+/// Mirrors the checks drivers perform on `VkPipelineCacheHeaderVersionOne`.
+fn validate_vk_header(data: &[u8], identity: &DeviceIdentity) -> anyhow::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let mut reader = ByteReader {
+        bytes: data,
+        offset: 0,
+    };
+
+    let header_len = reader.u32_ne()? as usize;
+    let header_version = reader.u32_ne()?;
+    let vendor_id = reader.u32_ne()?;
+    let device_id = reader.u32_ne()?;
+    let uuid = reader.take(vk::UUID_SIZE)?;
+
+    if header_len < VK_HEADER_LEN || header_len > data.len() {
+        anyhow::bail!("invalid driver header length {header_len}");
+    }
+    if vk::PipelineCacheHeaderVersion::from_raw(header_version as i32)
+        != vk::PipelineCacheHeaderVersion::ONE
+    {
+        anyhow::bail!("unknown driver header version {header_version}");
+    }
+    if vendor_id != identity.vendor_id || device_id != identity.device_id {
+        anyhow::bail!("driver header belongs to a different device");
+    }
+    if uuid != identity.uuid {
+        anyhow::bail!("driver header pipeline cache UUID mismatch");
+    }
+
+    Ok(())
+}
+
+/// FNV-1a, enough to catch truncated or bit-flipped files.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .context("unexpected end of file")?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u32_ne(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_ne_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_dir(vars: &[(&str, &str)]) -> Option<PathBuf> {
+        user_cache_dir(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prefers_xdg_then_home() {
+        assert_eq!(
+            cache_dir(&[("XDG_CACHE_HOME", "/xdg"), ("HOME", "/home/user")]),
+            Some(PathBuf::from("/xdg"))
+        );
+        assert_eq!(
+            cache_dir(&[("HOME", "/home/user"), ("LOCALAPPDATA", "/local")]),
+            Some(PathBuf::from("/home/user/.cache"))
+        );
+        assert_eq!(
+            cache_dir(&[("LOCALAPPDATA", "/local")]),
+            Some(PathBuf::from("/local"))
+        );
+    }
+
+    #[test]
+    fn ignores_relative_and_missing_dirs() {
+        assert_eq!(
+            cache_dir(&[("XDG_CACHE_HOME", "cache"), ("HOME", "")]),
+            None
+        );
+        assert_eq!(cache_dir(&[]), None);
+    }
+}
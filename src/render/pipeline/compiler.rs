@@ -0,0 +1,107 @@
+use std::{sync::Arc, thread};
+
+use ash::vk;
+use crossbeam_channel::{Receiver, Sender, unbounded};
+
+use crate::render::pipeline::manager::{PipelineKey, PreparedPipeline, build_pipeline};
+
+pub(super) struct CompileJob {
+    pub key: PipelineKey,
+    /// Identifies this request; a result whose ticket no longer matches the entry is stale.
+    pub ticket: u64,
+    pub prepared: PreparedPipeline,
+}
+
+pub(super) struct CompileResult {
+    pub key: PipelineKey,
+    pub ticket: u64,
+    pub prepared: PreparedPipeline,
+    pub pipeline: anyhow::Result<vk::Pipeline>,
+}
+
+/// Worker threads running the driver side of pipeline creation. Shader loading, validation
+/// and layouts are done before a job is queued, so workers only touch the device and the
+/// pipeline cache, which Vulkan synchronizes internally.
+pub(super) struct PipelineCompiler {
+    jobs: Option<Sender<CompileJob>>,
+    results: Receiver<CompileResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    in_flight: usize,
+}
+
+impl PipelineCompiler {
+    pub fn new(
+        device: Arc<ash::Device>,
+        pipeline_cache: vk::PipelineCache,
+        thread_count: usize,
+    ) -> anyhow::Result<Self> {
+        let (job_tx, job_rx) = unbounded::<CompileJob>();
+        let (result_tx, result_rx) = unbounded();
+
+        let mut workers = Vec::with_capacity(thread_count);
+        for i in 0..thread_count {
+            let device = device.clone();
+            let jobs = job_rx.clone();
+            let results = result_tx.clone();
+            workers.push(
+                thread::Builder::new()
+                    .name(format!("pipeline_compiler_{i}"))
+                    .spawn(move || {
+                        for job in jobs {
+                            let pipeline = build_pipeline(&device, &job.prepared, pipeline_cache);
+                            let result = CompileResult {
+                                key: job.key,
+                                ticket: job.ticket,
+                                prepared: job.prepared,
+                                pipeline,
+                            };
+                            if results.send(result).is_err() {
+                                break;
+                            }
+                        }
+                    })?,
+            );
+        }
+
+        Ok(Self {
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+            in_flight: 0,
+        })
+    }
+
+    pub fn submit(&mut self, job: CompileJob) -> anyhow::Result<()> {
+        self.jobs
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("pipeline compiler is shut down"))?
+            .send(job)
+            .map_err(|_| anyhow::anyhow!("pipeline compiler threads have exited"))?;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    pub fn try_recv(&mut self) -> Option<CompileResult> {
+        let result = self.results.try_recv().ok()?;
+        self.in_flight -= 1;
+        Some(result)
+    }
+
+    /// Jobs submitted whose results have not been received yet.
+    #[cfg(feature = "hot-reload")]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Lets the workers finish every queued job, then stops them. Returns the results that
+    /// were not collected yet so their pipelines can be destroyed.
+    pub fn shutdown(&mut self) -> Vec<CompileResult> {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+        let results = self.results.try_iter().collect::<Vec<_>>();
+        self.in_flight = 0;
+        results
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+use ash::vk;
+
+use crate::{
+    render::{
+        bindless::BindlessLayout,
+        shader::{ShaderReflection, format_kind},
+    },
+    vulkan::DeviceContext,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct SetLayoutBinding {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+    stages: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineLayoutKey {
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constants: Option<(vk::ShaderStageFlags, u32)>,
+}
+
+/// A pipeline layout with what went into it, so recording code can push constants and
+/// allocate descriptor sets without another reflection pass.
+#[derive(Clone, Debug, Default)]
+pub struct PipelineLayoutInfo {
+    pub layout: vk::PipelineLayout,
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub push_constants: Option<vk::PushConstantRange>,
+    /// Descriptor type of every `(set, binding)` the shaders declare.
+    pub bindings: HashMap<(u32, u32), vk::DescriptorType>,
+    /// The leading sets are the bindless heap's, which must be bound before drawing.
+    pub bindless: bool,
+    /// Flags pipelines built with this layout need, such as `DESCRIPTOR_BUFFER_EXT`.
+    pub create_flags: vk::PipelineCreateFlags,
+}
+
+/// Descriptor set and pipeline layouts derived from shader reflection, shared by every
+/// pipeline whose stages declare the same interface.
+#[derive(Default)]
+pub struct LayoutCache {
+    set_layouts: HashMap<Vec<SetLayoutBinding>, vk::DescriptorSetLayout>,
+    pipeline_layouts: HashMap<PipelineLayoutKey, vk::PipelineLayout>,
+    bindless: Option<BindlessLayout>,
+}
+
+impl LayoutCache {
+    /// Shaders declaring runtime-sized arrays in the heap's sets get the heap's set layouts
+    /// from here on.
+    pub fn set_bindless(&mut self, layout: BindlessLayout) {
+        self.bindless = Some(layout);
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        device_context: &DeviceContext,
+        stages: &[&ShaderReflection],
+    ) -> anyhow::Result<PipelineLayoutInfo> {
+        let sets = merge_bindings(stages)?;
+
+        let bindless = self
+            .bindless
+            .clone()
+            .filter(|bindless| uses_bindless(&sets, bindless));
+        if let Some(bindless) = &bindless {
+            validate_bindless(&sets, bindless)?;
+        }
+        let bindless_sets = bindless.as_ref().map_or(0, |b| b.set_layouts.len() as u32);
+
+        let set_count = sets
+            .keys()
+            .next_back()
+            .map_or(0, |set| set + 1)
+            .max(bindless_sets);
+        let mut set_layouts = Vec::with_capacity(set_count as usize);
+        for set in 0..set_count {
+            if let Some(bindless) = &bindless
+                && set < bindless_sets
+            {
+                set_layouts.push(bindless.set_layouts[set as usize]);
+                continue;
+            }
+
+            // Unused set numbers still need a (empty) layout to keep indices contiguous.
+            let bindings: Vec<SetLayoutBinding> = sets
+                .get(&set)
+                .map(|bindings| bindings.values().copied().collect())
+                .unwrap_or_default();
+            if let Some(binding) = bindings.iter().find(|binding| binding.count == 0) {
+                anyhow::bail!(
+                    "binding {}.{} is a runtime-sized array, which needs a bindless layout",
+                    set,
+                    binding.binding
+                );
+            }
+            set_layouts.push(self.set_layout(device_context, bindings)?);
+        }
+
+        let push_constants = merge_push_constants(stages);
+
+        self.pipeline_layout(
+            device_context,
+            PipelineLayoutKey {
+                set_layouts,
+                push_constants,
+            },
+            descriptor_types(&sets),
+        )
+    }
+
+    fn pipeline_layout(
+        &mut self,
+        device_context: &DeviceContext,
+        key: PipelineLayoutKey,
+        bindings: HashMap<(u32, u32), vk::DescriptorType>,
+    ) -> anyhow::Result<PipelineLayoutInfo> {
+        let push_constants = key
+            .push_constants
+            .map(|(stage_flags, size)| vk::PushConstantRange {
+                stage_flags,
+                offset: 0,
+                size,
+            });
+
+        let bindless = self
+            .bindless
+            .as_ref()
+            .filter(|bindless| bindless.is_prefix_of(&key.set_layouts));
+        if let Some(bindless) = bindless
+            && bindless.exclusive
+            && key.set_layouts.len() > bindless.set_layouts.len()
+        {
+            anyhow::bail!(
+                "layout has {} descriptor set(s) after the bindless heap, but descriptor buffer \
+                 pipelines can't use classic sets; pass the data through the heap or push \
+                 constants",
+                key.set_layouts.len() - bindless.set_layouts.len()
+            );
+        }
+        let create_flags = bindless.map_or(vk::PipelineCreateFlags::empty(), |b| b.create_flags);
+
+        if let Some(layout) = self.pipeline_layouts.get(&key) {
+            return Ok(PipelineLayoutInfo {
+                layout: *layout,
+                set_layouts: key.set_layouts,
+                push_constants,
+                bindings,
+                bindless: bindless.is_some(),
+                create_flags,
+            });
+        }
+
+        let ranges = push_constants.into_iter().collect::<Vec<_>>();
+
+        let layout = unsafe {
+            device_context.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&key.set_layouts)
+                    .push_constant_ranges(&ranges),
+                None,
+            )?
+        };
+        device_context.name_object(
+            layout,
+            format!(
+                "PipelineLayout(sets={}, push={:?})",
+                key.set_layouts.len(),
+                key.push_constants.map(|(_, size)| size)
+            ),
+        )?;
+
+        let info = PipelineLayoutInfo {
+            layout,
+            set_layouts: key.set_layouts.clone(),
+            push_constants,
+            bindings,
+            bindless: bindless.is_some(),
+            create_flags,
+        };
+        self.pipeline_layouts.insert(key, layout);
+        Ok(info)
+    }
+
+    fn set_layout(
+        &mut self,
+        device_context: &DeviceContext,
+        bindings: Vec<SetLayoutBinding>,
+    ) -> anyhow::Result<vk::DescriptorSetLayout> {
+        if let Some(layout) = self.set_layouts.get(&bindings) {
+            return Ok(*layout);
+        }
+
+        let vk_bindings = bindings
+            .iter()
+            .map(|b| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(b.binding)
+                    .descriptor_type(b.descriptor_type)
+                    .descriptor_count(b.count)
+                    .stage_flags(b.stages)
+            })
+            .collect::<Vec<_>>();
+
+        let layout = unsafe {
+            device_context
+                .device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::default().bindings(&vk_bindings),
+                    None,
+                )
+                .context("failed to create descriptor set layout")?
+        };
+
+        self.set_layouts.insert(bindings, layout);
+        Ok(layout)
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for (_, layout) in self.pipeline_layouts.drain() {
+            unsafe { device.destroy_pipeline_layout(layout, None) };
+        }
+        for (_, layout) in self.set_layouts.drain() {
+            unsafe { device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+/// Combines the bindings of all stages per set, failing when two stages disagree on what a
+/// binding is.
+fn merge_bindings(
+    stages: &[&ShaderReflection],
+) -> anyhow::Result<BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>>> {
+    let mut sets: BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>> = BTreeMap::new();
+
+    for stage in stages {
+        for binding in &stage.descriptor_bindings {
+            let entry = sets
+                .entry(binding.set)
+                .or_default()
+                .entry(binding.binding)
+                .or_insert(SetLayoutBinding {
+                    binding: binding.binding,
+                    descriptor_type: binding.descriptor_type,
+                    count: binding.count,
+                    stages: vk::ShaderStageFlags::empty(),
+                });
+
+            if entry.descriptor_type != binding.descriptor_type || entry.count != binding.count {
+                anyhow::bail!(
+                    "binding {}.{} ({:?}) is {:?}[{}] in {:?} but {:?}[{}] in an earlier stage",
+                    binding.set,
+                    binding.binding,
+                    binding.name,
+                    binding.descriptor_type,
+                    binding.count,
+                    stage.stage,
+                    entry.descriptor_type,
+                    entry.count
+                );
+            }
+
+            entry.stages |= stage.stage;
+        }
+    }
+
+    Ok(sets)
+}
+
+fn descriptor_types(
+    sets: &BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>>,
+) -> HashMap<(u32, u32), vk::DescriptorType> {
+    sets.iter()
+        .flat_map(|(set, bindings)| {
+            bindings
+                .values()
+                .map(|binding| ((*set, binding.binding), binding.descriptor_type))
+        })
+        .collect()
+}
+
+/// Runtime-sized arrays in the heap's sets mark a shader as bindless.
+fn uses_bindless(
+    sets: &BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>>,
+    bindless: &BindlessLayout,
+) -> bool {
+    sets.range(..bindless.set_layouts.len() as u32)
+        .any(|(_, bindings)| bindings.values().any(|binding| binding.count == 0))
+}
+
+/// Every binding a bindless shader declares in the heap's sets has to be one the heap provides.
+fn validate_bindless(
+    sets: &BTreeMap<u32, BTreeMap<u32, SetLayoutBinding>>,
+    bindless: &BindlessLayout,
+) -> anyhow::Result<()> {
+    for (set, bindings) in sets.range(..bindless.set_layouts.len() as u32) {
+        for binding in bindings.values() {
+            let Some(&(_, descriptor_type, capacity)) = bindless.bindings[*set as usize]
+                .iter()
+                .find(|(heap_binding, _, _)| *heap_binding == binding.binding)
+            else {
+                anyhow::bail!(
+                    "binding {}.{} is not part of the bindless heap",
+                    set,
+                    binding.binding
+                );
+            };
+
+            if binding.descriptor_type != descriptor_type || binding.count > capacity {
+                anyhow::bail!(
+                    "binding {}.{} is {:?}[{}] but the bindless heap has {:?}[{}] there",
+                    set,
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.count,
+                    descriptor_type,
+                    capacity
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A single range from offset zero covering the largest block, visible to every stage that
+/// declares one.
+fn merge_push_constants(stages: &[&ShaderReflection]) -> Option<(vk::ShaderStageFlags, u32)> {
+    stages
+        .iter()
+        .filter(|stage| stage.push_constant_size > 0)
+        .fold(None, |acc, stage| {
+            let (flags, size) = acc.unwrap_or((vk::ShaderStageFlags::empty(), 0));
+            Some((flags | stage.stage, size.max(stage.push_constant_size)))
+        })
+}
+
+/// Checks fragment outputs against the attachments they write to.
+pub fn validate_color_outputs(
+    fragment: &ShaderReflection,
+    color_formats: &[vk::Format],
+) -> anyhow::Result<()> {
+    for output in &fragment.outputs {
+        let Some(format) = color_formats.get(output.location as usize) else {
+            log::warn!(
+                "fragment shader writes location {} but the pipeline has {} color attachment(s); \
+                 the output is discarded",
+                output.location,
+                color_formats.len()
+            );
+            continue;
+        };
+
+        if let Some(kind) = format_kind(*format)
+            && kind != output.kind
+        {
+            anyhow::bail!(
+                "fragment output {} is {:?} but color attachment {} is {:?} ({:?})",
+                output.location,
+                output.kind,
+                output.location,
+                format,
+                kind
+            );
+        }
+    }
+
+    for (location, format) in color_formats.iter().enumerate() {
+        if !fragment
+            .outputs
+            .iter()
+            .any(|output| output.location as usize == location)
+        {
+            log::warn!(
+                "color attachment {} ({:?}) is not written by the fragment shader",
+                location,
+                format
+            );
+        }
+    }
+
+    Ok(())
+}
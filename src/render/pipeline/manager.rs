@@ -1,99 +1,842 @@
-use std::ffi::CString;
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Context;
 use ash::vk;
 use slotmap::{SlotMap, new_key_type};
 
 use crate::{
-    render::shader::{ShaderId, ShaderManager},
+    leaks::Leak,
+    render::{
+        bindless::BindlessLayout,
+        pipeline::{
+            cache::PipelineCache,
+            compiler::{CompileJob, PipelineCompiler},
+            layout::{LayoutCache, PipelineLayoutInfo, validate_color_outputs},
+            state::{
+                ColorBlendAttachment, DepthStencilState, RasterizationState, VertexInputState,
+            },
+        },
+        shader::format_kind,
+        shader::{ShaderId, ShaderManager, ShaderVariant, SpecializedShader},
+    },
     vulkan::DeviceContext,
 };
 
 new_key_type! { pub struct PipelineKey; }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct GraphicsPipelineDesc {
-    pub vertex_id: ShaderId,
-    pub fragment_id: ShaderId,
+    pub vertex: ShaderVariant,
+    pub fragment: ShaderVariant,
     pub topology: vk::PrimitiveTopology,
     pub color_formats: Vec<vk::Format>,
     pub depth_format: Option<vk::Format>,
+    pub stencil_format: Option<vk::Format>,
+    pub rasterization: RasterizationState,
+    /// One entry per color attachment. Left empty, every attachment is written opaquely.
+    pub blend: Vec<ColorBlendAttachment>,
+    pub depth_stencil: DepthStencilState,
+    pub vertex_input: VertexInputState,
+    /// Dynamic states beyond viewport and scissor, which are always dynamic.
+    pub dynamic_states: Vec<vk::DynamicState>,
+}
+
+impl GraphicsPipelineDesc {
+    pub fn new(vertex: impl Into<ShaderVariant>, fragment: impl Into<ShaderVariant>) -> Self {
+        Self {
+            vertex: vertex.into(),
+            fragment: fragment.into(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            color_formats: Vec::new(),
+            depth_format: None,
+            stencil_format: None,
+            rasterization: RasterizationState::default(),
+            blend: Vec::new(),
+            depth_stencil: DepthStencilState::default(),
+            vertex_input: VertexInputState::default(),
+            dynamic_states: Vec::new(),
+        }
+    }
+
+    pub fn depth_format(mut self, format: vk::Format) -> Self {
+        self.depth_format = Some(format);
+        self
+    }
+
+    pub fn rasterization(mut self, rasterization: RasterizationState) -> Self {
+        self.rasterization = rasterization;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: DepthStencilState) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+}
+
+/// A compute pipeline, laid out as its shader's reflection declares.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ComputePipelineDesc {
+    pub shader: ShaderVariant,
+}
+
+impl ComputePipelineDesc {
+    pub fn new(shader: impl Into<ShaderVariant>) -> Self {
+        Self {
+            shader: shader.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PipelineDesc {
+    Graphics(Box<GraphicsPipelineDesc>),
+    Compute(ComputePipelineDesc),
+}
+
+impl PipelineDesc {
+    #[cfg(feature = "hot-reload")]
+    fn uses_shader(&self, id: ShaderId) -> bool {
+        match self {
+            PipelineDesc::Graphics(desc) => desc.vertex.id == id || desc.fragment.id == id,
+            PipelineDesc::Compute(desc) => desc.shader.id == id,
+        }
+    }
+
+    fn variants(&self) -> Vec<&ShaderVariant> {
+        match self {
+            PipelineDesc::Graphics(desc) => vec![&desc.vertex, &desc.fragment],
+            PipelineDesc::Compute(desc) => vec![&desc.shader],
+        }
+    }
+
+    fn bind_point(&self) -> vk::PipelineBindPoint {
+        match self {
+            PipelineDesc::Graphics(_) => vk::PipelineBindPoint::GRAPHICS,
+            PipelineDesc::Compute(_) => vk::PipelineBindPoint::COMPUTE,
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            PipelineDesc::Graphics(desc) => {
+                format!(
+                    "graphics pipeline ({}, {})",
+                    desc.vertex.id, desc.fragment.id
+                )
+            }
+            PipelineDesc::Compute(desc) => format!("compute pipeline ({})", desc.shader.id),
+        }
+    }
+}
+
+impl From<GraphicsPipelineDesc> for PipelineDesc {
+    fn from(desc: GraphicsPipelineDesc) -> Self {
+        PipelineDesc::Graphics(Box::new(desc))
+    }
+}
+
+impl From<ComputePipelineDesc> for PipelineDesc {
+    fn from(desc: ComputePipelineDesc) -> Self {
+        PipelineDesc::Compute(desc)
+    }
 }
 
 pub struct PipelineEntry {
     pipeline: vk::Pipeline,
-    layout: vk::PipelineLayout,
-    _generation: u32,
+    layout: PipelineLayoutInfo,
+    bind_point: vk::PipelineBindPoint,
+    /// How many times the pipeline was rebuilt by a shader reload, plus one.
+    #[cfg(feature = "hot-reload")]
+    generation: u32,
+}
+
+enum RetiredResource {
+    Pipeline(PipelineEntry),
+    /// Replaced by a shader reload.
+    #[cfg(feature = "hot-reload")]
+    ShaderModule(vk::ShaderModule),
+}
+
+/// A resource replaced while `frame_number` was being recorded. Frames recorded earlier may
+/// still reference it.
+struct Retired {
+    frame_number: u64,
+    resource: RetiredResource,
+}
+
+enum PipelineState {
+    /// Queued on the compiler. Only the result carrying `ticket` is accepted.
+    Pending {
+        ticket: u64,
+    },
+    Ready(PipelineEntry),
+    /// The driver rejected the pipeline. Draws keep using the fallback until a shader reload
+    /// succeeds.
+    Failed,
+}
+
+struct ManagedPipeline {
+    state: PipelineState,
+    desc: PipelineDesc,
+    /// Drawn with while this pipeline is not ready. Holds no reference of its own.
+    fallback: Option<PipelineKey>,
+    ref_count: u32,
+}
+
+impl ManagedPipeline {
+    fn entry(&self) -> Option<&PipelineEntry> {
+        match &self.state {
+            PipelineState::Ready(entry) => Some(entry),
+            _ => None,
+        }
+    }
+}
+
+/// Everything pipeline creation needs once validation has passed, owned so it can move to a
+/// compiler thread.
+pub(super) struct PreparedPipeline {
+    desc: PipelineDesc,
+    layout: PipelineLayoutInfo,
+    /// Specialized stages in `PipelineDesc::variants` order.
+    shaders: Vec<SpecializedShader>,
+}
+
+impl PreparedPipeline {
+    fn into_entry(self, pipeline: vk::Pipeline) -> PipelineEntry {
+        PipelineEntry {
+            pipeline,
+            layout: self.layout,
+            bind_point: self.desc.bind_point(),
+            #[cfg(feature = "hot-reload")]
+            generation: 1,
+        }
+    }
+}
+
+/// Background compiles use one thread per spare core, capped so they never crowd out the
+/// engine's own threads.
+const MAX_COMPILE_THREADS: usize = 4;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PipelineStats {
+    /// `request` calls answered by an existing pipeline.
+    pub hits: u64,
+    /// `request` calls that compiled a new pipeline.
+    pub misses: u64,
+    /// Of the misses, pipelines handed to the compiler threads by `request`.
+    pub background: u64,
+    /// Pipelines retired after their last user released them.
+    pub released: u64,
 }
 
 pub struct PipelineManager {
-    entries: SlotMap<PipelineKey, PipelineEntry>,
+    entries: SlotMap<PipelineKey, ManagedPipeline>,
+    lookup: HashMap<PipelineDesc, PipelineKey>,
+    stats: PipelineStats,
+    retired: Vec<Retired>,
+    /// The frame being recorded, as last passed to `collect_retired`.
+    frame_number: u64,
     shader_manager: ShaderManager,
+    layout_cache: LayoutCache,
+    pipeline_cache: PipelineCache,
+    compiler: PipelineCompiler,
+    next_ticket: u64,
 }
 
 impl PipelineManager {
-    pub fn new(device: &ash::Device) -> anyhow::Result<Self> {
+    pub fn new(device: Arc<ash::Device>, pipeline_cache: PipelineCache) -> anyhow::Result<Self> {
         let mut shader_manager = ShaderManager::default();
-        shader_manager.load_builtin(device)?;
+        shader_manager.register_builtin();
+
+        let thread_count = std::thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .clamp(1, MAX_COMPILE_THREADS);
+        let compiler = PipelineCompiler::new(device, pipeline_cache.handle(), thread_count)
+            .context("failed to start pipeline compiler threads")?;
+
         Ok(Self {
             entries: Default::default(),
+            lookup: HashMap::default(),
+            stats: PipelineStats::default(),
+            retired: Vec::new(),
+            frame_number: 0,
             shader_manager,
+            layout_cache: LayoutCache::default(),
+            pipeline_cache,
+            compiler,
+            next_ticket: 0,
         })
     }
 
-    pub fn get_or_create(
+    /// Lets pipelines whose shaders index the bindless heap use its set layouts. Must be
+    /// called before any such pipeline is requested.
+    pub fn set_bindless_layout(&mut self, layout: BindlessLayout) {
+        self.layout_cache.set_bindless(layout);
+    }
+
+    /// Makes a `.spv` file available to pipeline descriptions under its file name, replacing a
+    /// built-in shader of that name. It is loaded on first use.
+    pub fn register_shader_path(
+        &mut self,
+        path: impl Into<std::path::PathBuf>,
+    ) -> anyhow::Result<ShaderId> {
+        self.shader_manager.register_path(path)
+    }
+
+    /// Returns the pipeline for `desc`, queueing it on the compiler threads only if no
+    /// identical description is live, and returns right away. Every call takes a reference that
+    /// must be given back with `release`. Until the pipeline is ready, `resolve` answers with
+    /// `fallback` (when that is ready itself) or nothing, and the draw is skipped. Shaders and
+    /// layouts are still checked here, so a description that can never work fails immediately.
+    pub fn request(
         &mut self,
         device_context: &DeviceContext,
-        desc: GraphicsPipelineDesc,
+        desc: impl Into<PipelineDesc>,
+        fallback: Option<PipelineKey>,
     ) -> anyhow::Result<PipelineKey> {
-        Ok(self.entries.insert(create_graphics_pipeline(
+        let desc = desc.into();
+        if let Some(key) = self.acquire_existing(&desc)? {
+            return Ok(key);
+        }
+
+        let prepared = prepare_pipeline(
             device_context,
             &desc,
-            &self.shader_manager,
-        )?))
+            &mut self.shader_manager,
+            &mut self.layout_cache,
+        )?;
+
+        let ticket = self.next_ticket();
+        let key = self.insert(desc, PipelineState::Pending { ticket }, fallback);
+        if let Err(e) = self.compiler.submit(CompileJob {
+            key,
+            ticket,
+            prepared,
+        }) {
+            if let Some(managed) = self.entries.remove(key) {
+                self.lookup.remove(&managed.desc);
+            }
+            return Err(e);
+        }
+        self.stats.misses += 1;
+        self.stats.background += 1;
+        Ok(key)
+    }
+
+    fn acquire_existing(&mut self, desc: &PipelineDesc) -> anyhow::Result<Option<PipelineKey>> {
+        let Some(key) = self.lookup.get(desc).copied() else {
+            return Ok(None);
+        };
+        let managed = self
+            .entries
+            .get_mut(key)
+            .context("pipeline lookup refers to a destroyed pipeline")?;
+        managed.ref_count += 1;
+        self.stats.hits += 1;
+        Ok(Some(key))
+    }
+
+    fn insert(
+        &mut self,
+        desc: PipelineDesc,
+        state: PipelineState,
+        fallback: Option<PipelineKey>,
+    ) -> PipelineKey {
+        let key = self.entries.insert(ManagedPipeline {
+            state,
+            desc: desc.clone(),
+            fallback,
+            ref_count: 1,
+        });
+        self.lookup.insert(desc, key);
+        key
+    }
+
+    fn next_ticket(&mut self) -> u64 {
+        self.next_ticket += 1;
+        self.next_ticket
+    }
+
+    /// Installs pipelines the compiler threads have finished. Draws switch over to them at the
+    /// next `resolve`. Results for pipelines released or rebuilt in the meantime are destroyed.
+    pub fn collect_compiled(&mut self, device_context: &DeviceContext) {
+        while let Some(result) = self.compiler.try_recv() {
+            let managed = self.entries.get_mut(result.key).filter(|managed| {
+                matches!(managed.state, PipelineState::Pending { ticket } if ticket == result.ticket)
+            });
+
+            match (managed, result.pipeline) {
+                (Some(managed), Ok(pipeline)) => {
+                    if let Err(e) = name_pipeline(device_context, &managed.desc, pipeline) {
+                        log::warn!("{e:#}");
+                    }
+                    log::debug!("Compiled {} in the background", managed.desc.label());
+                    managed.state = PipelineState::Ready(result.prepared.into_entry(pipeline));
+                }
+                (Some(managed), Err(e)) => {
+                    log::error!("Failed to compile {}: {e:#}", managed.desc.label());
+                    managed.state = PipelineState::Failed;
+                }
+                (None, Ok(pipeline)) => unsafe {
+                    device_context.device.destroy_pipeline(pipeline, None);
+                },
+                (None, Err(_)) => {}
+            }
+        }
+    }
+
+    /// The pipeline to draw with for `key` this frame: `key` once it is compiled, otherwise
+    /// its fallback if that is ready. `None` means the draw should be skipped.
+    pub fn resolve(&self, key: PipelineKey) -> Option<PipelineKey> {
+        let managed = self.entries.get(key)?;
+        if managed.entry().is_some() {
+            return Some(key);
+        }
+        let fallback = managed.fallback?;
+        self.entries.get(fallback)?.entry().map(|_| fallback)
+    }
+
+    /// Drops one reference to `key`. After the last one the pipeline is retired, so it is
+    /// destroyed once the frames that may still use it have finished.
+    pub fn release(&mut self, key: PipelineKey) -> anyhow::Result<()> {
+        let managed = self
+            .entries
+            .get_mut(key)
+            .with_context(|| format!("released unknown pipeline: {:?}", key))?;
+
+        managed.ref_count = managed.ref_count.saturating_sub(1);
+        if managed.ref_count > 0 {
+            return Ok(());
+        }
+
+        if let Some(managed) = self.entries.remove(key) {
+            self.lookup.remove(&managed.desc);
+            // A pending compile finds its entry gone and destroys the result itself.
+            if let PipelineState::Ready(entry) = managed.state {
+                self.retired.push(Retired {
+                    frame_number: self.frame_number,
+                    resource: RetiredResource::Pipeline(entry),
+                });
+            }
+            self.stats.released += 1;
+        }
+        Ok(())
+    }
+
+    /// Replaces the module for `id` and rebuilds every pipeline using it, keeping their keys.
+    /// If any rebuild fails, the previous module and pipelines stay in use. Pipelines still
+    /// compiling, or whose compile failed, are queued again with the new module.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &mut self,
+        device_context: &DeviceContext,
+        id: ShaderId,
+        spirv: &[u8],
+        frame_number: u64,
+    ) -> anyhow::Result<()> {
+        let device = &device_context.device;
+
+        let previous = self
+            .shader_manager
+            .replace(device, id, spirv)
+            .with_context(|| format!("failed to create shader module for {:?}", id))?;
+
+        let (affected, requeue): (Vec<_>, Vec<_>) = self
+            .entries
+            .iter()
+            .filter(|(_, managed)| managed.desc.uses_shader(id))
+            .map(|(key, managed)| (key, managed.entry().is_some()))
+            .partition(|(_, ready)| *ready);
+
+        let mut rebuilt = Vec::with_capacity(affected.len());
+        for (key, _) in affected {
+            let managed = &self.entries[key];
+            match create_pipeline(
+                device_context,
+                &managed.desc,
+                &mut self.shader_manager,
+                &mut self.layout_cache,
+                self.pipeline_cache.handle(),
+            ) {
+                Ok(entry) => rebuilt.push((key, entry)),
+                Err(e) => {
+                    for (_, entry) in rebuilt {
+                        destroy_entry(device, entry);
+                    }
+                    if let Some(previous) = previous
+                        && let Some(new_shader) = self.shader_manager.restore(id, previous)
+                    {
+                        unsafe { device.destroy_shader_module(new_shader.module, None) };
+                    }
+                    return Err(e).with_context(|| {
+                        format!("failed to rebuild pipelines for {:?}; keeping old ones", id)
+                    });
+                }
+            }
+        }
+
+        for (key, mut entry) in rebuilt {
+            let managed = &mut self.entries[key];
+            entry.generation = managed.entry().map_or(0, |current| current.generation) + 1;
+            log::info!(
+                "Rebuilt pipeline {:?} for {:?} (generation {})",
+                key,
+                id,
+                entry.generation
+            );
+            if let PipelineState::Ready(old) =
+                std::mem::replace(&mut managed.state, PipelineState::Ready(entry))
+            {
+                self.retired.push(Retired {
+                    frame_number,
+                    resource: RetiredResource::Pipeline(old),
+                });
+            }
+        }
+
+        // Retired before requeueing, so a failed submit below can't leak it. Jobs queued before
+        // the replacement still build from it; `collect_retired` waits for them.
+        if let Some(previous) = previous {
+            self.retired.push(Retired {
+                frame_number,
+                resource: RetiredResource::ShaderModule(previous.module),
+            });
+        }
+
+        let mut failed = None;
+        for (key, _) in requeue {
+            let prepared = match prepare_pipeline(
+                device_context,
+                &self.entries[key].desc,
+                &mut self.shader_manager,
+                &mut self.layout_cache,
+            ) {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    log::error!("Cannot requeue pipeline {:?} for {:?}: {e:#}", key, id);
+                    continue;
+                }
+            };
+            let ticket = self.next_ticket();
+            let submitted = self.compiler.submit(CompileJob {
+                key,
+                ticket,
+                prepared,
+            });
+            self.entries[key].state = match submitted {
+                Ok(()) => PipelineState::Pending { ticket },
+                Err(e) => {
+                    log::error!("Cannot requeue pipeline {:?} for {:?}: {e:#}", key, id);
+                    failed.get_or_insert(e);
+                    PipelineState::Failed
+                }
+            };
+        }
+
+        match failed {
+            Some(e) => Err(e).with_context(|| format!("failed to requeue pipelines for {:?}", id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Destroys replaced and released pipelines and modules once every frame that could
+    /// reference them has retired. Modules also wait for the compiler to go idle, since jobs
+    /// queued before the replacement still build from them. Call at the start of each frame:
+    /// anything released from here on is retired against `frame_number`.
+    pub fn collect_retired(&mut self, device: &ash::Device, frame_number: u64, frame_count: u64) {
+        self.frame_number = frame_number;
+        #[cfg(feature = "hot-reload")]
+        let compiling = self.compiler.in_flight() > 0;
+        self.retired.retain(|retired| {
+            if retired.frame_number + frame_count > frame_number {
+                return true;
+            }
+            #[cfg(feature = "hot-reload")]
+            if compiling && matches!(retired.resource, RetiredResource::ShaderModule(_)) {
+                return true;
+            }
+            destroy_retired(device, &retired.resource);
+            false
+        });
+    }
+
+    pub fn stats(&self) -> PipelineStats {
+        self.stats
     }
 
     #[track_caller]
     pub fn get_pipeline(&self, key: &PipelineKey) -> anyhow::Result<vk::Pipeline> {
-        Ok(self
-            .entries
+        Ok(self.ready_entry(key)?.pipeline)
+    }
+
+    #[track_caller]
+    pub fn get_pipeline_layout(&self, key: &PipelineKey) -> anyhow::Result<&PipelineLayoutInfo> {
+        Ok(&self.ready_entry(key)?.layout)
+    }
+
+    #[track_caller]
+    pub fn get_bind_point(&self, key: &PipelineKey) -> anyhow::Result<vk::PipelineBindPoint> {
+        Ok(self.ready_entry(key)?.bind_point)
+    }
+
+    #[track_caller]
+    fn ready_entry(&self, key: &PipelineKey) -> anyhow::Result<&PipelineEntry> {
+        let loc = std::panic::Location::caller();
+        self.entries
             .get(*key)
             .with_context(|| format!("no pipeline registered for key: {:?}", key))?
-            .pipeline)
+            .entry()
+            .with_context(|| {
+                format!(
+                    "pipeline {:?} is not compiled yet; use `resolve` first ({}:{})",
+                    key,
+                    loc.file(),
+                    loc.line()
+                )
+            })
     }
 
-    // pub fn get_pipeline_layout(&self, key: &PipelineKey) -> anyhow::Result<vk::PipelineLayout> {
-    //     Ok(self
-    //         .entries
-    //         .get(*key)
-    //         .with_context(|| format!("no pipeline layout registered for key: {:?}", key))?
-    //         .layout)
-    // }
+    /// Pipelines still referenced, and the shader modules they use. Modules are otherwise
+    /// cached for the manager's lifetime, so unused ones are not leaks.
+    pub fn leaks(&self) -> Vec<Leak> {
+        let mut shaders = Vec::new();
+        let mut leaks = Vec::new();
+        for managed in self.entries.values() {
+            leaks.push(Leak {
+                kind: "pipeline",
+                description: format!(
+                    "{}, {} reference(s)",
+                    managed.desc.label(),
+                    managed.ref_count
+                ),
+                bytes: None,
+            });
+            for variant in managed.desc.variants() {
+                if !shaders.contains(&variant.id) {
+                    shaders.push(variant.id);
+                }
+            }
+        }
+        leaks.extend(shaders.into_iter().map(|id| Leak {
+            kind: "shader module",
+            description: id.to_string(),
+            bytes: None,
+        }));
+        leaks
+    }
 
     pub fn destroy(&mut self, device: &ash::Device) -> anyhow::Result<()> {
-        for (_, entry) in self.entries.drain() {
-            unsafe {
-                device.destroy_pipeline_layout(entry.layout, None);
-                device.destroy_pipeline(entry.pipeline, None);
+        // Workers write to the pipeline cache and read shader modules, so they stop first.
+        for result in self.compiler.shutdown() {
+            if let Ok(pipeline) = result.pipeline {
+                unsafe { device.destroy_pipeline(pipeline, None) };
+            }
+        }
+
+        if let Err(e) = self.pipeline_cache.save(device) {
+            log::warn!("Failed to save pipeline cache: {e:#}");
+        }
+        self.pipeline_cache.destroy(device);
+
+        log::debug!(
+            "Pipeline stats: {:?}, {} still referenced at shutdown",
+            self.stats(),
+            self.entries.len()
+        );
+
+        for retired in self.retired.drain(..) {
+            destroy_retired(device, &retired.resource);
+        }
+
+        self.lookup.clear();
+        for (_, managed) in self.entries.drain() {
+            if let PipelineState::Ready(entry) = managed.state {
+                destroy_entry(device, entry);
             }
-            self.shader_manager.destroy(device);
         }
+        self.layout_cache.destroy(device);
+        self.shader_manager.destroy(device);
         Ok(())
     }
 }
 
-pub fn create_graphics_pipeline(
+/// Layouts are owned by the `LayoutCache` and outlive the pipelines built from them.
+fn destroy_entry(device: &ash::Device, entry: PipelineEntry) {
+    unsafe { device.destroy_pipeline(entry.pipeline, None) };
+}
+
+fn destroy_retired(device: &ash::Device, resource: &RetiredResource) {
+    match resource {
+        RetiredResource::Pipeline(entry) => unsafe {
+            device.destroy_pipeline(entry.pipeline, None);
+        },
+        #[cfg(feature = "hot-reload")]
+        RetiredResource::ShaderModule(module) => unsafe {
+            device.destroy_shader_module(*module, None);
+        },
+    }
+}
+
+/// Prepares and builds `desc` on the calling thread.
+#[cfg(feature = "hot-reload")]
+fn create_pipeline(
     device_context: &DeviceContext,
-    desc: &GraphicsPipelineDesc,
-    shader_manager: &ShaderManager,
+    desc: &PipelineDesc,
+    shader_manager: &mut ShaderManager,
+    layout_cache: &mut LayoutCache,
+    pipeline_cache: vk::PipelineCache,
 ) -> anyhow::Result<PipelineEntry> {
-    let pipeline_layout = unsafe {
-        device_context
-            .device
-            .create_pipeline_layout(&vk::PipelineLayoutCreateInfo::default(), None)?
+    let prepared = prepare_pipeline(device_context, desc, shader_manager, layout_cache)?;
+    let pipeline = build_pipeline(&device_context.device, &prepared, pipeline_cache)?;
+    name_pipeline(device_context, desc, pipeline)?;
+    Ok(prepared.into_entry(pipeline))
+}
+
+/// Loads and validates the shaders and resolves the layout. Everything that needs the
+/// manager's state happens here, leaving only the driver compile for `build_pipeline`.
+fn prepare_pipeline(
+    device_context: &DeviceContext,
+    desc: &PipelineDesc,
+    shader_manager: &mut ShaderManager,
+    layout_cache: &mut LayoutCache,
+) -> anyhow::Result<PreparedPipeline> {
+    for variant in desc.variants() {
+        shader_manager
+            .specialize(&device_context.device, variant)
+            .with_context(|| format!("{} requests shader '{}'", desc.label(), variant.id))?;
+    }
+
+    let layout = match desc {
+        PipelineDesc::Graphics(desc) => {
+            prepare_graphics_pipeline(device_context, desc, shader_manager, layout_cache)?
+        }
+        PipelineDesc::Compute(desc) => {
+            prepare_compute_pipeline(device_context, desc, shader_manager, layout_cache)?
+        }
     };
-    device_context.name_object(pipeline_layout, "RegularPipelineLayout")?;
 
+    let shaders = desc
+        .variants()
+        .into_iter()
+        .map(|variant| shader_manager.specialized(variant).cloned())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(PreparedPipeline {
+        desc: desc.clone(),
+        layout,
+        shaders,
+    })
+}
+
+/// The driver side of pipeline creation. Safe to call from a compiler thread.
+pub(super) fn build_pipeline(
+    device: &ash::Device,
+    prepared: &PreparedPipeline,
+    pipeline_cache: vk::PipelineCache,
+) -> anyhow::Result<vk::Pipeline> {
+    match (&prepared.desc, prepared.shaders.as_slice()) {
+        (PipelineDesc::Graphics(desc), [vert, frag]) => {
+            build_graphics_pipeline(device, desc, vert, frag, &prepared.layout, pipeline_cache)
+        }
+        (PipelineDesc::Compute(_), [shader]) => {
+            build_compute_pipeline(device, shader, &prepared.layout, pipeline_cache)
+        }
+        _ => anyhow::bail!(
+            "{} was prepared with the wrong stages",
+            prepared.desc.label()
+        ),
+    }
+}
+
+fn name_pipeline(
+    device_context: &DeviceContext,
+    desc: &PipelineDesc,
+    pipeline: vk::Pipeline,
+) -> anyhow::Result<()> {
+    match desc {
+        PipelineDesc::Graphics(_) => Ok(()),
+        PipelineDesc::Compute(desc) => {
+            device_context.name_object(pipeline, format!("ComputePipeline({:?})", desc.shader.id))
+        }
+    }
+}
+
+fn prepare_graphics_pipeline(
+    device_context: &DeviceContext,
+    desc: &GraphicsPipelineDesc,
+    shader_manager: &ShaderManager,
+    layout_cache: &mut LayoutCache,
+) -> anyhow::Result<PipelineLayoutInfo> {
+    let vert_reflection = shader_manager.reflection(desc.vertex.id)?;
+    let frag_reflection = shader_manager.reflection(desc.fragment.id)?;
+
+    if vert_reflection.stage != vk::ShaderStageFlags::VERTEX {
+        anyhow::bail!(
+            "{:?} is a {:?} shader, not a vertex shader",
+            desc.vertex.id,
+            vert_reflection.stage
+        );
+    }
+    if frag_reflection.stage != vk::ShaderStageFlags::FRAGMENT {
+        anyhow::bail!(
+            "{:?} is a {:?} shader, not a fragment shader",
+            desc.fragment.id,
+            frag_reflection.stage
+        );
+    }
+
+    validate_color_outputs(frag_reflection, &desc.color_formats).with_context(|| {
+        format!(
+            "{:?} does not match the pipeline's color formats",
+            desc.fragment.id
+        )
+    })?;
+
+    validate_fixed_function(desc)?;
+
+    for input in &vert_reflection.inputs {
+        let Some(attribute) = desc
+            .vertex_input
+            .attributes
+            .iter()
+            .find(|attribute| attribute.location == input.location)
+        else {
+            anyhow::bail!(
+                "{:?} reads vertex input location {} but the pipeline declares no attribute for it",
+                desc.vertex.id,
+                input.location
+            );
+        };
+        if format_kind(attribute.format) != Some(input.kind) {
+            anyhow::bail!(
+                "{:?} reads location {} as {:?} but the attribute format is {:?}",
+                desc.vertex.id,
+                input.location,
+                input.kind,
+                attribute.format
+            );
+        }
+    }
+
+    layout_cache
+        .get_or_create(device_context, &[vert_reflection, frag_reflection])
+        .with_context(|| {
+            format!(
+                "failed to derive pipeline layout for {:?}/{:?}",
+                desc.vertex.id, desc.fragment.id
+            )
+        })
+}
+
+fn build_graphics_pipeline(
+    device: &ash::Device,
+    desc: &GraphicsPipelineDesc,
+    vert: &SpecializedShader,
+    frag: &SpecializedShader,
+    layout: &PipelineLayoutInfo,
+    pipeline_cache: vk::PipelineCache,
+) -> anyhow::Result<vk::Pipeline> {
     let mut rendering_info =
         vk::PipelineRenderingCreateInfo::default().color_attachment_formats(&desc.color_formats);
 
@@ -101,6 +844,10 @@ pub fn create_graphics_pipeline(
         rendering_info = rendering_info.depth_attachment_format(depth);
     }
 
+    if let Some(stencil) = desc.stencil_format {
+        rendering_info = rendering_info.stencil_attachment_format(stencil);
+    }
+
     let input_assembly =
         vk::PipelineInputAssemblyStateCreateInfo::default().topology(desc.topology);
 
@@ -108,73 +855,162 @@ pub fn create_graphics_pipeline(
         .viewport_count(1)
         .scissor_count(1);
 
-    let raster = vk::PipelineRasterizationStateCreateInfo::default()
-        .polygon_mode(vk::PolygonMode::FILL)
-        .cull_mode(vk::CullModeFlags::NONE)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .line_width(1.0);
+    let raster = desc.rasterization.to_vk();
 
     let multisample = vk::PipelineMultisampleStateCreateInfo::default()
         .rasterization_samples(vk::SampleCountFlags::TYPE_1);
 
-    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::default()
-        .color_write_mask(vk::ColorComponentFlags::RGBA);
+    let color_blend_attachments = if desc.blend.is_empty() {
+        vec![ColorBlendAttachment::OPAQUE.to_vk(); desc.color_formats.len()]
+    } else {
+        desc.blend.iter().map(|blend| blend.to_vk()).collect()
+    };
+
+    let color_blend =
+        vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_blend_attachments);
 
-    let color_blend = vk::PipelineColorBlendStateCreateInfo::default()
-        .attachments(std::slice::from_ref(&color_blend_attachment));
+    let depth_stencil = desc.depth_stencil.to_vk();
 
-    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    if desc.rasterization.depth_bias {
+        dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+    }
+    for state in &desc.dynamic_states {
+        if !dynamic_states.contains(state) {
+            dynamic_states.push(*state);
+        }
+    }
     let dynamic_state =
         vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
-    let vert_module = shader_manager
-        .module(desc.vertex_id)
-        .context("create_graphics_pipeline failed to get vert module")?;
-
-    let frag_module = shader_manager
-        .module(desc.fragment_id)
-        .context("create_graphics_pipeline failed to get frag module")?;
-
-    let entry = CString::new("main")?;
+    let vert_specialization = vert.info();
+    let frag_specialization = frag.info();
 
     let stages = [
         vk::PipelineShaderStageCreateInfo::default()
             .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vert_module)
-            .name(&entry),
+            .module(vert.module)
+            .name(&vert.entry_point)
+            .specialization_info(&vert_specialization),
         vk::PipelineShaderStageCreateInfo::default()
             .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(frag_module)
-            .name(&entry),
+            .module(frag.module)
+            .name(&frag.entry_point)
+            .specialization_info(&frag_specialization),
     ];
 
-    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+    let vertex_bindings = desc.vertex_input.vk_bindings();
+    let vertex_attributes = desc.vertex_input.vk_attributes();
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+        .vertex_binding_descriptions(&vertex_bindings)
+        .vertex_attribute_descriptions(&vertex_attributes);
 
     let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+        .flags(layout.create_flags)
         .stages(&stages)
         .vertex_input_state(&vertex_input)
         .input_assembly_state(&input_assembly)
         .viewport_state(&viewport_state)
         .rasterization_state(&raster)
         .multisample_state(&multisample)
+        .depth_stencil_state(&depth_stencil)
         .color_blend_state(&color_blend)
         .dynamic_state(&dynamic_state)
-        .layout(pipeline_layout)
+        .layout(layout.layout)
         .push_next(&mut rendering_info);
 
-    let pipeline = unsafe {
-        device_context
-            .device
-            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+    unsafe {
+        device
+            .create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
             .map_err(|e| anyhow::anyhow!("failed to create pipeline: {e:?}"))?
             .into_iter()
             .next()
-            .ok_or_else(|| anyhow::anyhow!("no pipeline returned"))?
-    };
+            .ok_or_else(|| anyhow::anyhow!("no pipeline returned"))
+    }
+}
 
-    Ok(PipelineEntry {
-        pipeline,
-        layout: pipeline_layout,
-        _generation: 1,
-    })
+/// Catches state combinations the driver would otherwise reject or silently ignore.
+fn validate_fixed_function(desc: &GraphicsPipelineDesc) -> anyhow::Result<()> {
+    if !desc.blend.is_empty() && desc.blend.len() != desc.color_formats.len() {
+        anyhow::bail!(
+            "{} blend state(s) for {} color attachment(s)",
+            desc.blend.len(),
+            desc.color_formats.len()
+        );
+    }
+
+    if desc.depth_stencil.uses_depth() && desc.depth_format.is_none() {
+        anyhow::bail!("depth testing or writes enabled without a depth attachment format");
+    }
+
+    if desc.depth_stencil.stencil.is_some() && desc.stencil_format.is_none() {
+        anyhow::bail!("stencil testing enabled without a stencil attachment format");
+    }
+
+    for attribute in &desc.vertex_input.attributes {
+        if !desc
+            .vertex_input
+            .bindings
+            .iter()
+            .any(|binding| binding.binding == attribute.binding)
+        {
+            anyhow::bail!(
+                "vertex attribute at location {} uses undeclared binding {}",
+                attribute.location,
+                attribute.binding
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn prepare_compute_pipeline(
+    device_context: &DeviceContext,
+    desc: &ComputePipelineDesc,
+    shader_manager: &ShaderManager,
+    layout_cache: &mut LayoutCache,
+) -> anyhow::Result<PipelineLayoutInfo> {
+    let reflection = shader_manager.reflection(desc.shader.id)?;
+
+    if reflection.stage != vk::ShaderStageFlags::COMPUTE {
+        anyhow::bail!(
+            "{:?} is a {:?} shader, not a compute shader",
+            desc.shader.id,
+            reflection.stage
+        );
+    }
+
+    layout_cache
+        .get_or_create(device_context, &[reflection])
+        .with_context(|| format!("failed to create pipeline layout for {:?}", desc.shader.id))
+}
+
+fn build_compute_pipeline(
+    device: &ash::Device,
+    shader: &SpecializedShader,
+    layout: &PipelineLayoutInfo,
+    pipeline_cache: vk::PipelineCache,
+) -> anyhow::Result<vk::Pipeline> {
+    let specialization_info = shader.info();
+
+    let stage = vk::PipelineShaderStageCreateInfo::default()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader.module)
+        .name(&shader.entry_point)
+        .specialization_info(&specialization_info);
+
+    let pipeline_info = vk::ComputePipelineCreateInfo::default()
+        .flags(layout.create_flags)
+        .stage(stage)
+        .layout(layout.layout);
+
+    unsafe {
+        device
+            .create_compute_pipelines(pipeline_cache, &[pipeline_info], None)
+            .map_err(|(_, e)| anyhow::anyhow!("failed to create compute pipeline: {e:?}"))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no pipeline returned"))
+    }
 }
@@ -1,3 +1,14 @@
+mod cache;
+mod compiler;
+mod layout;
 mod manager;
+mod state;
 
-pub use manager::{GraphicsPipelineDesc, PipelineKey, PipelineManager};
+pub use layout::PipelineLayoutInfo;
+pub use manager::{
+    ComputePipelineDesc, GraphicsPipelineDesc, PipelineDesc, PipelineKey, PipelineManager,
+};
+
+pub use cache::PipelineCache;
+
+pub use state::{DepthStencilState, RasterizationState};
@@ -0,0 +1,230 @@
+use ash::vk;
+
+/// Rasterizer configuration. Depth bias factors are always dynamic: enabling `depth_bias`
+/// adds `vk::DynamicState::DEPTH_BIAS` and passes set the values with `cmd_set_depth_bias`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct RasterizationState {
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_clamp: bool,
+    pub depth_bias: bool,
+}
+
+impl Default for RasterizationState {
+    fn default() -> Self {
+        Self {
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_clamp: false,
+            depth_bias: false,
+        }
+    }
+}
+
+impl RasterizationState {
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub(super) fn to_vk(self) -> vk::PipelineRasterizationStateCreateInfo<'static> {
+        vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_clamp_enable(self.depth_clamp)
+            .depth_bias_enable(self.depth_bias)
+            .line_width(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct BlendEquation {
+    pub src_color: vk::BlendFactor,
+    pub dst_color: vk::BlendFactor,
+    pub color_op: vk::BlendOp,
+    pub src_alpha: vk::BlendFactor,
+    pub dst_alpha: vk::BlendFactor,
+    pub alpha_op: vk::BlendOp,
+}
+
+/// Blend state of one color attachment. `blend: None` writes the fragment output unchanged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ColorBlendAttachment {
+    pub blend: Option<BlendEquation>,
+    pub write_mask: vk::ColorComponentFlags,
+}
+
+impl Default for ColorBlendAttachment {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
+
+impl ColorBlendAttachment {
+    pub const OPAQUE: Self = Self {
+        blend: None,
+        write_mask: vk::ColorComponentFlags::RGBA,
+    };
+
+    pub(super) fn to_vk(self) -> vk::PipelineColorBlendAttachmentState {
+        let state =
+            vk::PipelineColorBlendAttachmentState::default().color_write_mask(self.write_mask);
+        match self.blend {
+            None => state,
+            Some(eq) => state
+                .blend_enable(true)
+                .src_color_blend_factor(eq.src_color)
+                .dst_color_blend_factor(eq.dst_color)
+                .color_blend_op(eq.color_op)
+                .src_alpha_blend_factor(eq.src_alpha)
+                .dst_alpha_blend_factor(eq.dst_alpha)
+                .alpha_blend_op(eq.alpha_op),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct StencilFaceState {
+    pub fail_op: vk::StencilOp,
+    pub pass_op: vk::StencilOp,
+    pub depth_fail_op: vk::StencilOp,
+    pub compare_op: vk::CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+
+impl Default for StencilFaceState {
+    fn default() -> Self {
+        Self {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::ALWAYS,
+            compare_mask: !0,
+            write_mask: !0,
+            reference: 0,
+        }
+    }
+}
+
+impl StencilFaceState {
+    fn to_vk(self) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op,
+            pass_op: self.pass_op,
+            depth_fail_op: self.depth_fail_op,
+            compare_op: self.compare_op,
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct StencilState {
+    pub front: StencilFaceState,
+    pub back: StencilFaceState,
+}
+
+/// Depth and stencil testing. The default tests nothing, matching a pipeline without a depth
+/// attachment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct DepthStencilState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub compare_op: vk::CompareOp,
+    pub stencil: Option<StencilState>,
+}
+
+impl Default for DepthStencilState {
+    fn default() -> Self {
+        Self::DISABLED
+    }
+}
+
+impl DepthStencilState {
+    pub const DISABLED: Self = Self {
+        depth_test: false,
+        depth_write: false,
+        compare_op: vk::CompareOp::ALWAYS,
+        stencil: None,
+    };
+
+    /// Closer fragments win and write their depth.
+    pub const LESS_WRITE: Self = Self {
+        depth_test: true,
+        depth_write: true,
+        compare_op: vk::CompareOp::LESS,
+        stencil: None,
+    };
+
+    pub(super) fn uses_depth(&self) -> bool {
+        self.depth_test || self.depth_write
+    }
+
+    pub(super) fn to_vk(self) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+        let info = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.compare_op);
+        match self.stencil {
+            None => info,
+            Some(stencil) => info
+                .stencil_test_enable(true)
+                .front(stencil.front.to_vk())
+                .back(stencil.back.to_vk()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    pub input_rate: vk::VertexInputRate,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub binding: u32,
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+/// Vertex buffer layout. Empty by default for shaders that generate their vertices.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct VertexInputState {
+    pub bindings: Vec<VertexBinding>,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexInputState {
+    pub(super) fn vk_bindings(&self) -> Vec<vk::VertexInputBindingDescription> {
+        self.bindings
+            .iter()
+            .map(|b| vk::VertexInputBindingDescription {
+                binding: b.binding,
+                stride: b.stride,
+                input_rate: b.input_rate,
+            })
+            .collect()
+    }
+
+    pub(super) fn vk_attributes(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|a| vk::VertexInputAttributeDescription {
+                location: a.location,
+                binding: a.binding,
+                format: a.format,
+                offset: a.offset,
+            })
+            .collect()
+    }
+}
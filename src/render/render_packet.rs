@@ -1,3 +1,12 @@
 pub struct RenderData {
     pub _id: u32,
+    pub cull: Option<CullView>,
+}
+
+/// What GPU culling needs from the scene each frame.
+#[derive(Clone, Copy)]
+pub struct CullView {
+    /// Column-major, with Vulkan's 0..1 depth range.
+    pub view_proj: [f32; 16],
+    pub instance_count: u32,
 }
@@ -0,0 +1,547 @@
+use anyhow::Context;
+use ash::vk;
+use tracy_client::plot;
+
+use crate::{
+    buffer::{
+        AllocationStrategy, ArenaHandle, ArenaKey, BufferLifetime, BufferManager, BufferSpec,
+        BufferType, BufferUsage, CompositeBufferKey,
+    },
+    render::{
+        framegraph::{CullingBuffers, DepthPyramid, IndirectDraws},
+        indirect::{DRAW_INDEXED_STRIDE, IndirectDrawBuilder},
+        render_packet::CullView,
+        submit::submit_once,
+    },
+    vulkan::DeviceContext,
+};
+
+/// Cubes along each side of the grid.
+const GRID_SIZE: u32 = 32;
+const GRID_SPACING: f32 = 3.0;
+/// Radius of each cube's bounding sphere, which its corners lie on.
+const CUBE_RADIUS: f32 = 1.0;
+/// Instances the per-instance buffers have room for at first. They grow to fit the grid the
+/// way they would as a scene streams in more objects.
+const INITIAL_CAPACITY: usize = 256;
+
+/// Side of the depth pyramid's level 0 in texels.
+const PYRAMID_SIZE: u32 = 256;
+
+const ARENA_SIZE: usize = 64 * 1024;
+/// One `vec4` position per vertex; a power of two, so vertex blocks can be aligned to it.
+const VERTEX_STRIDE: vk::DeviceSize = size_of::<[f32; 4]>() as vk::DeviceSize;
+
+const CUBE_VERTICES: [[f32; 4]; 8] = [
+    [-1.0, -1.0, -1.0, 1.0],
+    [1.0, -1.0, -1.0, 1.0],
+    [-1.0, 1.0, -1.0, 1.0],
+    [1.0, 1.0, -1.0, 1.0],
+    [-1.0, -1.0, 1.0, 1.0],
+    [1.0, -1.0, 1.0, 1.0],
+    [-1.0, 1.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0, 1.0],
+];
+
+#[rustfmt::skip]
+const CUBE_INDICES: [u32; 36] = [
+    0, 2, 1, 1, 2, 3, // -z
+    4, 5, 6, 5, 7, 6, // +z
+    0, 1, 4, 1, 5, 4, // -y
+    2, 6, 3, 3, 6, 7, // +y
+    0, 4, 2, 2, 4, 6, // -x
+    1, 3, 5, 3, 7, 5, // +x
+];
+
+const ORBIT_RADIUS: f32 = 40.0;
+const ORBIT_HEIGHT: f32 = 15.0;
+/// Radians per frame.
+const ORBIT_SPEED: f32 = 0.005;
+const FOV_Y: f32 = std::f32::consts::FRAC_PI_3;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 500.0;
+
+/// Device arenas that every mesh's vertices and indices are sub-allocated from. They outlive
+/// the scenes using them, which free their ranges when they go.
+pub struct MeshArenas {
+    vertices: ArenaKey,
+    indices: ArenaKey,
+}
+
+impl MeshArenas {
+    pub fn new(
+        buffer_manager: &mut BufferManager,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+    ) -> anyhow::Result<Self> {
+        let spec = BufferSpec::default()
+            .allocation_strategy(AllocationStrategy::Arena)
+            .buffer_type(BufferType::DeviceArena)
+            .size(ARENA_SIZE);
+        Ok(Self {
+            vertices: buffer_manager.create_arena(
+                allocator,
+                device_context,
+                spec.clone()
+                    .usage(BufferUsage::STORAGE)
+                    .debug_name("MeshVertices"),
+            )?,
+            indices: buffer_manager.create_arena(
+                allocator,
+                device_context,
+                spec.usage(BufferUsage::INDEX).debug_name("MeshIndices"),
+            )?,
+        })
+    }
+
+    pub fn plot_stats(&self, buffer_manager: &BufferManager) {
+        let vertices = buffer_manager.arena_stats(self.vertices);
+        let indices = buffer_manager.arena_stats(self.indices);
+        plot!("vertex arena bytes", vertices.used as f64);
+        plot!(
+            "vertex arena fragmentation",
+            vertices.fragmentation() as f64
+        );
+        plot!("index arena bytes", indices.used as f64);
+        plot!("index arena fragmentation", indices.fragmentation() as f64);
+    }
+
+    /// Destroys both arenas. Meshes still in them go with them.
+    pub fn destroy(self, buffer_manager: &mut BufferManager) {
+        buffer_manager.destroy_arena(self.vertices);
+        buffer_manager.destroy_arena(self.indices);
+    }
+}
+
+/// Sub-allocates from `arena`. When the space is there but split up, the arena is defragmented
+/// into `cmd` first. That moves every other allocation in it, so this is for load time, before
+/// anything has baked their offsets into draws.
+fn arena_alloc_or_defragment(
+    buffer_manager: &mut BufferManager,
+    allocator: &vk_mem::Allocator,
+    device_context: &DeviceContext,
+    cmd: vk::CommandBuffer,
+    arena: ArenaKey,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+) -> anyhow::Result<ArenaHandle> {
+    let error = match buffer_manager.arena_alloc(arena, size, alignment) {
+        Ok(handle) => return Ok(handle),
+        Err(error) => error,
+    };
+    let stats = buffer_manager.arena_stats(arena);
+    // Compacting can't help unless there is enough free space in total.
+    if stats.capacity - stats.used < size {
+        return Err(error);
+    }
+    buffer_manager.defragment_arena(allocator, device_context, cmd, arena)?;
+    buffer_manager.arena_alloc(arena, size, alignment)
+}
+
+/// A grid of cubes under an orbiting camera, standing in for a real scene until one can be
+/// loaded. The mesh lives in the mesh arenas and is pulled by the vertex shader; bounds and
+/// one draw per cube are uploaded once for the culling pass to compact every frame.
+pub struct CubeGrid {
+    vertices: ArenaKey,
+    indices: ArenaKey,
+    mesh: [ArenaHandle; 2],
+    culling: CullingBuffers,
+    /// Per-frame copy of the visible count, for stats.
+    readback: CompositeBufferKey,
+    pyramid: DepthPyramid,
+    instance_count: u32,
+}
+
+impl CubeGrid {
+    pub fn new(
+        buffer_manager: &mut BufferManager,
+        arenas: &MeshArenas,
+        allocator: &vk_mem::Allocator,
+        device_context: &DeviceContext,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        frame_count: u32,
+    ) -> anyhow::Result<Self> {
+        let instance_count = GRID_SIZE * GRID_SIZE;
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&CUBE_VERTICES);
+        let index_bytes: &[u8] = bytemuck::cast_slice(&CUBE_INDICES);
+        let (vertices, indices) = (arenas.vertices, arenas.indices);
+        let [vertex_handle, index_handle] =
+            submit_once(&device_context.device, queue, command_pool, |cmd| {
+                Ok([
+                    arena_alloc_or_defragment(
+                        buffer_manager,
+                        allocator,
+                        device_context,
+                        cmd,
+                        vertices,
+                        vertex_bytes.len() as vk::DeviceSize,
+                        VERTEX_STRIDE,
+                    )?,
+                    arena_alloc_or_defragment(
+                        buffer_manager,
+                        allocator,
+                        device_context,
+                        cmd,
+                        indices,
+                        index_bytes.len() as vk::DeviceSize,
+                        size_of::<u32>() as vk::DeviceSize,
+                    )?,
+                ])
+            })
+            .context("failed to allocate the cube mesh")?;
+        let vertex_block = buffer_manager.arena_block(vertex_handle);
+        let index_block = buffer_manager.arena_block(index_handle);
+
+        let mut draws = IndirectDrawBuilder::with_capacity(instance_count as usize);
+        for instance in 0..instance_count {
+            draws.draw(
+                CUBE_INDICES.len() as u32,
+                1,
+                (index_block.offset / size_of::<u32>() as vk::DeviceSize) as u32,
+                (vertex_block.offset / VERTEX_STRIDE) as i32,
+                instance,
+            );
+        }
+
+        let half_extent = (GRID_SIZE - 1) as f32 * GRID_SPACING / 2.0;
+        let bounds = (0..instance_count)
+            .map(|i| {
+                [
+                    (i % GRID_SIZE) as f32 * GRID_SPACING - half_extent,
+                    0.0,
+                    (i / GRID_SIZE) as f32 * GRID_SPACING - half_extent,
+                    CUBE_RADIUS,
+                ]
+            })
+            .collect::<Vec<[f32; 4]>>();
+
+        let instance_spec = BufferSpec::default()
+            .allocation_strategy(AllocationStrategy::Resizable)
+            .usage(BufferUsage::STORAGE);
+        // Written by the culling shader and consumed by indirect draws.
+        let command_spec = instance_spec
+            .clone()
+            .buffer_type(BufferType::IndirectCommand)
+            .usage(BufferUsage::STORAGE | BufferUsage::INDIRECT);
+        let culling = CullingBuffers {
+            bounds: buffer_manager.create_buffer(
+                allocator,
+                device_context,
+                instance_spec
+                    .items(size_of::<[f32; 4]>(), INITIAL_CAPACITY)
+                    .debug_name("CullBounds"),
+                1,
+            )?,
+            draws: buffer_manager.create_buffer(
+                allocator,
+                device_context,
+                command_spec
+                    .clone()
+                    .items(DRAW_INDEXED_STRIDE as usize, INITIAL_CAPACITY)
+                    .debug_name("CullDraws"),
+                1,
+            )?,
+            visible: buffer_manager.create_buffer(
+                allocator,
+                device_context,
+                command_spec
+                    .clone()
+                    .items(DRAW_INDEXED_STRIDE as usize, INITIAL_CAPACITY)
+                    .debug_name("VisibleDraws"),
+                1,
+            )?,
+            count: buffer_manager.create_buffer(
+                allocator,
+                device_context,
+                command_spec
+                    .allocation_strategy(AllocationStrategy::Fixed)
+                    // Copied into the readback buffer each frame.
+                    .usage(BufferUsage::STORAGE | BufferUsage::INDIRECT | BufferUsage::TRANSFER)
+                    .size(size_of::<u32>())
+                    .debug_name("VisibleDrawCount"),
+                1,
+            )?,
+        };
+
+        let readback = buffer_manager.create_buffer(
+            allocator,
+            device_context,
+            BufferSpec::default()
+                .lifetime(BufferLifetime::PerFrame)
+                .buffer_type(BufferType::Readback)
+                .usage(BufferUsage::TRANSFER)
+                .size(size_of::<u32>())
+                .debug_name("VisibleDrawCountReadback"),
+            frame_count,
+        )?;
+        for frame_index in 0..frame_count as usize {
+            buffer_manager.write(allocator, readback, frame_index, 0, &[0u32])?;
+        }
+
+        // Built from one frame's depth and tested against by the next, so shared by all frames.
+        let pyramid = DepthPyramid {
+            buffer: buffer_manager.create_buffer(
+                allocator,
+                device_context,
+                BufferSpec::default()
+                    .usage(BufferUsage::STORAGE | BufferUsage::TRANSFER)
+                    .size(DepthPyramid::byte_size(PYRAMID_SIZE) as usize)
+                    .debug_name("DepthPyramid"),
+                1,
+            )?,
+            size: PYRAMID_SIZE,
+        };
+
+        // The visible list and count start zeroed, so draws recorded before the culling
+        // pipeline is ready draw nothing.
+        let zeroes = vec![0u8; draws.byte_size() as usize];
+        let uploads: [(&[u8], CompositeBufferKey, vk::DeviceSize); 6] = [
+            (
+                vertex_bytes,
+                buffer_manager.arena_buffer_key(vertices),
+                vertex_block.offset,
+            ),
+            (
+                index_bytes,
+                buffer_manager.arena_buffer_key(indices),
+                index_block.offset,
+            ),
+            (bytemuck::cast_slice(&bounds), culling.bounds, 0),
+            (draws.as_bytes(), culling.draws, 0),
+            (&zeroes, culling.visible, 0),
+            (&zeroes[..size_of::<u32>()], culling.count, 0),
+        ];
+
+        let staging_size = uploads.iter().map(|(bytes, _, _)| bytes.len()).sum();
+        let staging = buffer_manager.create_buffer(
+            allocator,
+            device_context,
+            BufferSpec::default()
+                .buffer_type(BufferType::HostTransient)
+                .usage(BufferUsage::TRANSFER)
+                .size(staging_size)
+                .debug_name("SceneUpload"),
+            1,
+        )?;
+
+        let mut copies = Vec::with_capacity(uploads.len());
+        let mut offset = 0;
+        for (bytes, dst, dst_offset) in uploads {
+            buffer_manager.write(allocator, staging, 0, offset, bytes)?;
+            copies.push((
+                dst,
+                vk::BufferCopy {
+                    src_offset: offset,
+                    dst_offset,
+                    size: bytes.len() as vk::DeviceSize,
+                },
+            ));
+            offset += bytes.len() as vk::DeviceSize;
+        }
+
+        submit_once(&device_context.device, queue, command_pool, |cmd| {
+            let instance_count = instance_count as vk::DeviceSize;
+            for (key, stride) in [
+                (culling.bounds, size_of::<[f32; 4]>() as vk::DeviceSize),
+                (culling.draws, DRAW_INDEXED_STRIDE as vk::DeviceSize),
+                (culling.visible, DRAW_INDEXED_STRIDE as vk::DeviceSize),
+            ] {
+                buffer_manager.ensure_capacity(
+                    allocator,
+                    device_context,
+                    cmd,
+                    key,
+                    stride * instance_count,
+                    0,
+                )?;
+            }
+            for (dst, region) in &copies {
+                buffer_manager.copy(&device_context.device, cmd, staging, *dst, 0, &[*region])?;
+            }
+            // Until the first pyramid is built, a zero camera and the far plane everywhere,
+            // which the culling shader treats as occluding nothing.
+            let pyramid_buffer = buffer_manager.resolve_buffer(pyramid.buffer, 0).vk_buffer;
+            unsafe {
+                device_context.device.cmd_fill_buffer(
+                    cmd,
+                    pyramid_buffer,
+                    0,
+                    DepthPyramid::HEADER_BYTES,
+                    0,
+                );
+                device_context.device.cmd_fill_buffer(
+                    cmd,
+                    pyramid_buffer,
+                    DepthPyramid::HEADER_BYTES,
+                    vk::WHOLE_SIZE,
+                    1.0f32.to_bits(),
+                );
+            }
+            Ok(())
+        })
+        .context("failed to upload the cube grid")?;
+        buffer_manager.destroy(staging);
+
+        Ok(Self {
+            vertices,
+            indices,
+            mesh: [vertex_handle, index_handle],
+            culling,
+            readback,
+            pyramid,
+            instance_count,
+        })
+    }
+
+    pub fn culling_buffers(&self) -> CullingBuffers {
+        self.culling
+    }
+
+    pub fn depth_pyramid(&self) -> DepthPyramid {
+        self.pyramid
+    }
+
+    pub fn visible_count_readback(&self) -> CompositeBufferKey {
+        self.readback
+    }
+
+    /// How many cubes survived culling the last time frame `frame_index` was recorded. Only
+    /// valid once that frame's fence has signalled.
+    pub fn visible_count(
+        &self,
+        buffer_manager: &BufferManager,
+        allocator: &vk_mem::Allocator,
+        frame_index: usize,
+    ) -> anyhow::Result<u32> {
+        let mut count = [0u32];
+        buffer_manager.read(allocator, self.readback, frame_index, 0, &mut count)?;
+        Ok(count[0])
+    }
+
+    /// The culling pass's output, drawn through the mesh arenas.
+    pub fn indirect_draws(&self, buffer_manager: &BufferManager) -> IndirectDraws {
+        IndirectDraws {
+            commands: self.culling.visible,
+            count: self.culling.count,
+            index_buffer: buffer_manager.arena_buffer_key(self.indices),
+            index_type: vk::IndexType::UINT32,
+            max_draws: self.instance_count,
+            vertices: buffer_manager.arena_buffer_key(self.vertices),
+            instances: self.culling.bounds,
+        }
+    }
+
+    /// The camera for `frame_number`, orbiting the grid's centre.
+    pub fn cull_view(&self, frame_number: u64, extent: vk::Extent2D) -> CullView {
+        let angle = frame_number as f32 * ORBIT_SPEED;
+        let eye = [
+            ORBIT_RADIUS * angle.cos(),
+            ORBIT_HEIGHT,
+            ORBIT_RADIUS * angle.sin(),
+        ];
+        let aspect = extent.width as f32 / extent.height.max(1) as f32;
+        CullView {
+            view_proj: mul(
+                &perspective(FOV_Y, aspect, NEAR, FAR),
+                &look_at(eye, [0.0; 3], [0.0, 1.0, 0.0]),
+            ),
+            instance_count: self.instance_count,
+        }
+    }
+
+    /// Releases the mesh ranges and every buffer, once the frames that may still use them have
+    /// finished.
+    pub fn destroy(self, buffer_manager: &mut BufferManager) {
+        for handle in self.mesh {
+            buffer_manager.arena_free(handle);
+        }
+        buffer_manager.destroy(self.culling.bounds);
+        buffer_manager.destroy(self.culling.draws);
+        buffer_manager.destroy(self.culling.visible);
+        buffer_manager.destroy(self.culling.count);
+        buffer_manager.destroy(self.readback);
+        buffer_manager.destroy(self.pyramid.buffer);
+    }
+}
+
+// Column-major 4x4 matrices, as shaders read them.
+type Mat4 = [f32; 16];
+
+/// Right-handed, looking down -z in view space, with Vulkan's 0..1 depth range and y pointing
+/// down in clip space.
+fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    let depth = far / (near - far);
+    [
+        f / aspect,
+        0.0,
+        0.0,
+        0.0, //
+        0.0,
+        -f,
+        0.0,
+        0.0, //
+        0.0,
+        0.0,
+        depth,
+        -1.0, //
+        0.0,
+        0.0,
+        near * depth,
+        0.0,
+    ]
+}
+
+fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Mat4 {
+    let forward = normalize(sub(target, eye));
+    let side = normalize(cross(forward, up));
+    let up = cross(side, forward);
+    [
+        side[0],
+        up[0],
+        -forward[0],
+        0.0, //
+        side[1],
+        up[1],
+        -forward[1],
+        0.0, //
+        side[2],
+        up[2],
+        -forward[2],
+        0.0, //
+        -dot(side, eye),
+        -dot(up, eye),
+        dot(forward, eye),
+        1.0,
+    ]
+}
+
+fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    std::array::from_fn(|i| {
+        let (column, row) = (i / 4, i % 4);
+        (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum()
+    })
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
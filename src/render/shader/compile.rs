@@ -0,0 +1,378 @@
+//! Shader source to SPIR-V. Shared with the build script through `#[path]`, so this only
+//! depends on `std` and `naga`, and errors are plain strings ready to print.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    Glsl,
+    Wgsl,
+    Hlsl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+    Compute,
+    Geometry,
+    TessControl,
+    TessEval,
+}
+
+impl Stage {
+    fn from_extension(ext: &str) -> Option<Self> {
+        Some(match ext {
+            "vert" => Stage::Vertex,
+            "frag" => Stage::Fragment,
+            "comp" => Stage::Compute,
+            "geom" => Stage::Geometry,
+            "tesc" => Stage::TessControl,
+            "tese" => Stage::TessEval,
+            _ => return None,
+        })
+    }
+
+    fn naga(self) -> Option<naga::ShaderStage> {
+        match self {
+            Stage::Vertex => Some(naga::ShaderStage::Vertex),
+            Stage::Fragment => Some(naga::ShaderStage::Fragment),
+            Stage::Compute => Some(naga::ShaderStage::Compute),
+            _ => None,
+        }
+    }
+}
+
+/// A compilable shader stage. The stage comes from the extension in front of the language
+/// suffix, so `water.frag` and `water.frag.wgsl` both compile to the shader named `water.frag`.
+#[derive(Clone, Debug)]
+pub struct ShaderFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub stage: Stage,
+    pub language: Language,
+}
+
+impl ShaderFile {
+    pub fn classify(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        let (name, language) = match file_name.rsplit_once('.')? {
+            (name, "wgsl") => (name, Language::Wgsl),
+            (name, "hlsl") => (name, Language::Hlsl),
+            _ => (file_name, Language::Glsl),
+        };
+        let stage = Stage::from_extension(name.rsplit_once('.')?.1)?;
+        Some(Self {
+            path: path.to_path_buf(),
+            name: name.to_owned(),
+            stage,
+            language,
+        })
+    }
+}
+
+pub struct Compiled {
+    pub spirv: Vec<u8>,
+    /// Every file the output depends on, including the source itself.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// External compilers found on this machine. GLSL prefers glslc and falls back to naga; WGSL
+/// always uses naga.
+pub struct Compiler {
+    glslc: Option<PathBuf>,
+}
+
+impl Compiler {
+    pub fn detect() -> Self {
+        Self {
+            glslc: find_tool("GLSLC", "glslc"),
+        }
+    }
+
+    pub fn has_glslc(&self) -> bool {
+        self.glslc.is_some()
+    }
+
+    pub fn compile(&self, file: &ShaderFile, include_dir: &Path) -> Result<Compiled, String> {
+        match (file.language, &self.glslc) {
+            (Language::Glsl, Some(glslc)) => compile_glslc(glslc, file, include_dir),
+            (Language::Glsl, None) | (Language::Wgsl, _) => compile_naga(file, include_dir),
+            // Recognised so an HLSL file fails loudly instead of being skipped.
+            (Language::Hlsl, _) => Err(format!(
+                "{}: HLSL is not supported; naga has no HLSL front end, so write the shader in \
+                 WGSL or GLSL",
+                file.path.display()
+            )),
+        }
+    }
+}
+
+fn find_tool(env_var: &str, name: &str) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(path) = std::env::var_os(env_var) {
+        candidates.push(PathBuf::from(path));
+    }
+    if let Some(sdk) = std::env::var_os("VULKAN_SDK") {
+        candidates.push(Path::new(&sdk).join("bin").join(name));
+    }
+    candidates.push(PathBuf::from(name));
+
+    candidates.into_iter().find(|tool| {
+        Command::new(tool)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    })
+}
+
+fn temp_path(file: &ShaderFile, suffix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "skeleton-{}-{}.{suffix}",
+        std::process::id(),
+        file.name
+    ))
+}
+
+fn run(command: &mut Command, tool: &Path) -> Result<(), String> {
+    let output = command.output().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => format!("{} not found", tool.display()),
+        _ => format!("failed to run {}: {e}", tool.display()),
+    })?;
+
+    // glslc reports `file:line: error: ...`, so stderr is forwarded verbatim.
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim_end()
+            .to_owned());
+    }
+    Ok(())
+}
+
+fn compile_glslc(glslc: &Path, file: &ShaderFile, include_dir: &Path) -> Result<Compiled, String> {
+    let out = temp_path(file, "spv");
+    let depfile = temp_path(file, "d");
+
+    run(
+        Command::new(glslc)
+            .arg("--target-env=vulkan1.3")
+            .arg("-I")
+            .arg(include_dir)
+            .arg("-MD")
+            .arg("-MF")
+            .arg(&depfile)
+            .arg(&file.path)
+            .arg("-o")
+            .arg(&out),
+        glslc,
+    )?;
+
+    let spirv = fs::read(&out).map_err(|e| format!("failed to read {}: {e}", out.display()))?;
+    let mut dependencies = fs::read_to_string(&depfile)
+        .map(|deps| parse_depfile(&deps))
+        .unwrap_or_default();
+    if !dependencies.contains(&file.path) {
+        dependencies.push(file.path.clone());
+    }
+
+    let _ = fs::remove_file(&out);
+    let _ = fs::remove_file(&depfile);
+
+    Ok(Compiled {
+        spirv,
+        dependencies,
+    })
+}
+
+fn compile_naga(file: &ShaderFile, include_dir: &Path) -> Result<Compiled, String> {
+    let stage = file.stage.naga().ok_or_else(|| {
+        format!(
+            "{}: {:?} shaders need glslc",
+            file.path.display(),
+            file.stage
+        )
+    })?;
+
+    let mut source = Source::default();
+    source.expand(&file.path, include_dir, 0)?;
+
+    let module = match file.language {
+        Language::Wgsl => naga::front::wgsl::parse_str(&source.text).map_err(|e| {
+            source.error_at(e.location(&source.text).map(|loc| loc.line_number), &e)
+        })?,
+        _ => naga::front::glsl::Frontend::default()
+            .parse(&naga::front::glsl::Options::from(stage), &source.text)
+            .map_err(|e| {
+                e.errors
+                    .iter()
+                    .map(|error| {
+                        let line = error.meta.location(&source.text).line_number;
+                        source.error_at(Some(line), &error.kind)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?,
+    };
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| source.error_at(e.location(&source.text).map(|loc| loc.line_number), &e))?;
+
+    let entry_point = {
+        let mut candidates = module.entry_points.iter().filter(|ep| ep.stage == stage);
+        match (candidates.next(), candidates.next()) {
+            (Some(ep), None) => ep.name.clone(),
+            (None, _) => {
+                return Err(format!(
+                    "{}: no {:?} entry point",
+                    file.path.display(),
+                    stage
+                ));
+            }
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "{}: more than one {:?} entry point",
+                    file.path.display(),
+                    stage
+                ));
+            }
+        }
+    };
+
+    let mut options = naga::back::spv::Options {
+        lang_version: (1, 3),
+        ..Default::default()
+    };
+    // GLSL written for Vulkan is already in Vulkan clip space; WGSL is y-up and needs the flip.
+    if file.language == Language::Glsl {
+        options
+            .flags
+            .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    }
+
+    let words = naga::back::spv::write_vec(
+        &module,
+        &info,
+        &options,
+        Some(&naga::back::spv::PipelineOptions {
+            shader_stage: stage,
+            entry_point,
+        }),
+    )
+    .map_err(|e| format!("{}: failed to write SPIR-V: {e}", file.path.display()))?;
+
+    Ok(Compiled {
+        spirv: words.iter().flat_map(|word| word.to_le_bytes()).collect(),
+        dependencies: source.files,
+    })
+}
+
+/// Source text with `#include "..."` expanded inline, remembering where each line came from
+/// so errors point at the original file.
+#[derive(Default)]
+struct Source {
+    text: String,
+    lines: Vec<(PathBuf, usize)>,
+    files: Vec<PathBuf>,
+}
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+impl Source {
+    fn expand(&mut self, path: &Path, include_dir: &Path, depth: usize) -> Result<(), String> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!("{}: includes nested too deeply", path.display()));
+        }
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        if !self.files.iter().any(|file| file == path) {
+            self.files.push(path.to_path_buf());
+        }
+
+        for (i, line) in text.lines().enumerate() {
+            let Some(name) = include_target(line) else {
+                self.text.push_str(line);
+                self.text.push('\n');
+                self.lines.push((path.to_path_buf(), i + 1));
+                continue;
+            };
+
+            let local = path.parent().unwrap_or(Path::new(".")).join(name);
+            let resolved = if local.exists() {
+                local
+            } else {
+                include_dir.join(name)
+            };
+            if !resolved.exists() {
+                return Err(format!(
+                    "{}:{}: error: cannot find include \"{}\"",
+                    path.display(),
+                    i + 1,
+                    name
+                ));
+            }
+            self.expand(&resolved, include_dir, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// `file:line: error: ...` with the error's sources appended, matching glslc's format.
+    /// Without a line the error is reported against the stage file itself.
+    fn error_at(&self, line: Option<u32>, error: &dyn std::error::Error) -> String {
+        let origin = match line.and_then(|line| self.lines.get((line as usize).checked_sub(1)?)) {
+            Some((path, line)) => format!("{}:{}", path.display(), line),
+            None => self.files[0].display().to_string(),
+        };
+        let mut message = format!("{origin}: error: {error}");
+        let mut source = error.source();
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        message
+    }
+}
+
+fn include_target(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("#include")?.trim();
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Make-style dependency list: `target: dep dep \` with escaped spaces.
+fn parse_depfile(contents: &str) -> Vec<PathBuf> {
+    let joined = contents.replace("\\\n", " ");
+    let Some((_, deps)) = joined.split_once(": ") else {
+        return Vec::new();
+    };
+
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut chars = deps.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => {
+                current.push(' ');
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    result.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(PathBuf::from(current));
+    }
+    result
+}
@@ -0,0 +1,53 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Mutex, OnceLock},
+};
+
+/// Name a shader is registered under, by convention its source file name (`forward.vert`).
+/// Cheap to copy and compare; names only known at runtime are interned with `ShaderId::intern`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderId(&'static str);
+
+impl ShaderId {
+    pub const FORWARD_VERT: ShaderId = ShaderId::new("forward.vert");
+    pub const FORWARD_FRAG: ShaderId = ShaderId::new("forward.frag");
+    pub const FORWARD_INDIRECT_VERT: ShaderId = ShaderId::new("forward_indirect.vert");
+    pub const COMPOSITION_VERT: ShaderId = ShaderId::new("composition.vert");
+    pub const COMPOSITION_FRAG: ShaderId = ShaderId::new("composition.frag");
+    pub const COMPOSITION_SAMPLED_FRAG: ShaderId = ShaderId::new("composition_sampled.frag");
+    pub const CULL_COMP: ShaderId = ShaderId::new("cull.comp");
+    pub const DEPTH_PYRAMID_COMP: ShaderId = ShaderId::new("depth_pyramid.comp");
+
+    pub const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+
+    /// Returns the id for a runtime name. Each distinct name is allocated once and kept for
+    /// the life of the process.
+    pub fn intern(name: &str) -> Self {
+        static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut names = NAMES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(existing) = names.get(name) {
+            return Self(existing);
+        }
+        let leaked: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        names.insert(leaked);
+        Self(leaked)
+    }
+}
+
+impl fmt::Debug for ShaderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ShaderId({:?})", self.0)
+    }
+}
+
+impl fmt::Display for ShaderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
@@ -0,0 +1,265 @@
+use std::{borrow::Cow, collections::HashMap, ffi::CString, fmt, path::PathBuf};
+
+use anyhow::Context;
+use ash::{util::read_spv, vk};
+
+use crate::render::shader::{
+    ShaderId,
+    reflect::{ScalarKind, ShaderReflection, reflect},
+    variant::{ShaderVariant, SpecializedShader},
+};
+
+/// Where a registered shader's SPIR-V comes from. Nothing is read until a pipeline first
+/// uses the shader.
+pub enum ShaderSource {
+    /// A `.spv` file on disk.
+    Path(PathBuf),
+    /// SPIR-V compiled into the binary, e.g. with `include_bytes!`.
+    Static(&'static [u8]),
+}
+
+impl ShaderSource {
+    fn read(&self) -> anyhow::Result<Cow<'_, [u8]>> {
+        Ok(match self {
+            ShaderSource::Path(path) => Cow::Owned(
+                std::fs::read(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?,
+            ),
+            ShaderSource::Static(bytes) => Cow::Borrowed(bytes),
+        })
+    }
+}
+
+impl fmt::Debug for ShaderSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderSource::Path(path) => write!(f, "Path({})", path.display()),
+            ShaderSource::Static(bytes) => write!(f, "Static({} bytes)", bytes.len()),
+        }
+    }
+}
+
+pub struct LoadedShader {
+    pub module: vk::ShaderModule,
+    pub reflection: ShaderReflection,
+}
+
+#[derive(Default)]
+pub struct ShaderManager {
+    sources: HashMap<ShaderId, ShaderSource>,
+    modules: HashMap<ShaderId, LoadedShader>,
+    specialized: HashMap<ShaderVariant, SpecializedShader>,
+}
+
+impl ShaderManager {
+    pub fn register_builtin(&mut self) {
+        self.register(
+            ShaderId::FORWARD_VERT,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/forward.vert.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::FORWARD_FRAG,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/forward.frag.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::FORWARD_INDIRECT_VERT,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/forward_indirect.vert.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::COMPOSITION_VERT,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/composition.vert.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::COMPOSITION_FRAG,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/composition.frag.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::COMPOSITION_SAMPLED_FRAG,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/composition_sampled.frag.spv"
+            ))),
+        );
+        self.register(
+            ShaderId::CULL_COMP,
+            ShaderSource::Static(include_bytes!(concat!(env!("OUT_DIR"), "/cull.comp.spv"))),
+        );
+        self.register(
+            ShaderId::DEPTH_PYRAMID_COMP,
+            ShaderSource::Static(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/depth_pyramid.comp.spv"
+            ))),
+        );
+    }
+
+    /// Makes `id` available to pipelines. Registering an id again replaces its source for
+    /// future loads; a module that is already loaded stays in use.
+    pub fn register(&mut self, id: ShaderId, source: ShaderSource) {
+        if let Some(previous) = self.sources.insert(id, source) {
+            log::debug!("Shader {} re-registered, replacing {:?}", id, previous);
+        }
+    }
+
+    /// Registers a `.spv` file under its file name without the extension, so
+    /// `shaders/water.frag.spv` becomes `water.frag`.
+    pub fn register_path(&mut self, path: impl Into<PathBuf>) -> anyhow::Result<ShaderId> {
+        let path = path.into();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("{} has no usable file name", path.display()))?;
+        let id = ShaderId::intern(name.strip_suffix(".spv").unwrap_or(name));
+        self.register(id, ShaderSource::Path(path));
+        Ok(id)
+    }
+
+    /// Loads `id` from its registered source if no module exists yet.
+    pub fn ensure_loaded(&mut self, device: &ash::Device, id: ShaderId) -> anyhow::Result<()> {
+        if self.modules.contains_key(&id) {
+            return Ok(());
+        }
+
+        let source = self
+            .sources
+            .get(&id)
+            .with_context(|| format!("shader '{}' is not registered", id))?;
+        let spirv = source
+            .read()
+            .with_context(|| format!("failed to load shader '{}' from {:?}", id, source))?;
+        let shader = create_module(device, &spirv)
+            .with_context(|| format!("failed to create shader module for '{}'", id))?;
+
+        log::debug!("Loaded shader {} from {:?}", id, source);
+        self.modules.insert(id, shader);
+        Ok(())
+    }
+
+    /// Swaps in a new module for `id`, returning the previous one. The caller owns the returned
+    /// module and must destroy it once no pipeline creation or in-flight frame needs it.
+    #[cfg(feature = "hot-reload")]
+    pub fn replace(
+        &mut self,
+        device: &ash::Device,
+        id: ShaderId,
+        spirv: &[u8],
+    ) -> anyhow::Result<Option<LoadedShader>> {
+        let shader = create_module(device, spirv)?;
+        self.specialized.retain(|variant, _| variant.id != id);
+        Ok(self.modules.insert(id, shader))
+    }
+
+    /// Puts back a module previously returned by `replace`, returning the one it displaced.
+    #[cfg(feature = "hot-reload")]
+    pub fn restore(&mut self, id: ShaderId, shader: LoadedShader) -> Option<LoadedShader> {
+        self.specialized.retain(|variant, _| variant.id != id);
+        self.modules.insert(id, shader)
+    }
+
+    /// Checks `variant` against the module's reflected specialization constants and caches
+    /// the result. Pipelines read it back with `specialized`.
+    pub fn specialize(
+        &mut self,
+        device: &ash::Device,
+        variant: &ShaderVariant,
+    ) -> anyhow::Result<()> {
+        if self.specialized.contains_key(variant) {
+            return Ok(());
+        }
+
+        self.ensure_loaded(device, variant.id)?;
+
+        let shader = self.shader(variant.id)?;
+        for constant in variant.constants() {
+            let declared = shader
+                .reflection
+                .spec_constants
+                .iter()
+                .find(|declared| declared.id == constant.id)
+                .with_context(|| {
+                    format!(
+                        "'{}' declares no specialization constant {}",
+                        variant.id, constant.id
+                    )
+                })?;
+            if declared.kind == ScalarKind::Bool && constant.value > 1 {
+                anyhow::bail!(
+                    "'{}' constant {} ({:?}) is a bool but was given {}",
+                    variant.id,
+                    constant.id,
+                    declared.name,
+                    constant.value
+                );
+            }
+        }
+
+        let entry_point = CString::new(shader.reflection.entry_point.as_str())?;
+        let specialized = SpecializedShader::new(shader.module, entry_point, variant.constants());
+        self.specialized.insert(variant.clone(), specialized);
+        Ok(())
+    }
+
+    #[track_caller]
+    pub fn specialized(&self, variant: &ShaderVariant) -> anyhow::Result<&SpecializedShader> {
+        let loc = std::panic::Location::caller();
+        self.specialized.get(variant).with_context(|| {
+            format!(
+                "{:?} was not specialized before use ({}:{})",
+                variant,
+                loc.file(),
+                loc.line()
+            )
+        })
+    }
+
+    #[track_caller]
+    pub fn reflection(&self, id: ShaderId) -> anyhow::Result<&ShaderReflection> {
+        Ok(&self.shader(id)?.reflection)
+    }
+
+    #[track_caller]
+    fn shader(&self, id: ShaderId) -> anyhow::Result<&LoadedShader> {
+        let loc = std::panic::Location::caller();
+        self.modules.get(&id).with_context(|| {
+            format!(
+                "shader '{}' is not loaded ({}:{})",
+                id,
+                loc.file(),
+                loc.line()
+            )
+        })
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.specialized.clear();
+        for (_, shader) in self.modules.drain() {
+            unsafe {
+                device.destroy_shader_module(shader.module, None);
+            }
+        }
+    }
+}
+
+fn create_module(device: &ash::Device, spirv: &[u8]) -> anyhow::Result<LoadedShader> {
+    let spv = read_spv(&mut std::io::Cursor::new(spirv)).context("failed to read spirv")?;
+    let reflection = reflect(&spv).context("failed to reflect spirv")?;
+    let module = unsafe {
+        device.create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&spv), None)?
+    };
+    Ok(LoadedShader { module, reflection })
+}
@@ -0,0 +1,15 @@
+// Also built into the build script, which uses the parts hot reload doesn't.
+#[cfg(feature = "hot-reload")]
+pub mod compile;
+mod id;
+mod manager;
+mod reflect;
+mod variant;
+
+pub use id::ShaderId;
+
+pub use manager::ShaderManager;
+
+pub use reflect::{ShaderReflection, format_kind};
+
+pub use variant::{ShaderVariant, SpecializedShader};
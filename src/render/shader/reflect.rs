@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use ash::vk;
+use spirv::{Decoration, Dim, ExecutionModel, Op, StorageClass};
+
+/// Numeric class of a shader interface variable or attachment format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScalarKind {
+    Float,
+    Sint,
+    Uint,
+    Bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Zero for runtime-sized arrays.
+    pub count: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub kind: ScalarKind,
+    pub width: u32,
+    pub components: u32,
+}
+
+/// A specialization constant the module declares with `constant_id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecConstantInfo {
+    pub id: u32,
+    pub name: Option<String>,
+    pub kind: ScalarKind,
+    /// Raw 32-bit default value.
+    pub default: u32,
+}
+
+/// What a single shader module declares: resources it binds and the interface it exposes to
+/// neighbouring stages.
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    /// Size in bytes of the push-constant block, zero if there is none.
+    pub push_constant_size: u32,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub spec_constants: Vec<SpecConstantInfo>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Void,
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: Dim, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+struct Variable {
+    id: u32,
+    pointer_type: u32,
+    storage_class: StorageClass,
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(ExecutionModel, String)>,
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+    /// `(result id, result type, default value)` of every specialization constant.
+    spec_constants: Vec<(u32, u32, u32)>,
+    decorations: HashMap<(u32, Decoration), u32>,
+    flags: HashMap<u32, Vec<Decoration>>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    member_matrix_strides: HashMap<(u32, u32), u32>,
+    member_builtins: HashMap<u32, bool>,
+}
+
+pub fn reflect(words: &[u32]) -> anyhow::Result<ShaderReflection> {
+    let module = parse(words)?;
+
+    let (model, entry_point) = module
+        .entry_point
+        .clone()
+        .context("SPIR-V module has no entry point")?;
+
+    let stage = match model {
+        ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+        ExecutionModel::TessellationControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        ExecutionModel::TessellationEvaluation => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        ExecutionModel::Geometry => vk::ShaderStageFlags::GEOMETRY,
+        ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+        ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+        other => anyhow::bail!("unsupported execution model {:?}", other),
+    };
+
+    let mut descriptor_bindings = Vec::new();
+    let mut push_constant_size = 0;
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+
+    for variable in &module.variables {
+        let Some(Type::Pointer { pointee }) = module.types.get(&variable.pointer_type) else {
+            continue;
+        };
+        let pointee = *pointee;
+
+        match variable.storage_class {
+            StorageClass::UniformConstant | StorageClass::Uniform | StorageClass::StorageBuffer => {
+                descriptor_bindings.push(module.descriptor_binding(variable, pointee)?);
+            }
+            StorageClass::PushConstant => {
+                push_constant_size = push_constant_size.max(module.size_of(pointee, None)?);
+            }
+            StorageClass::Input | StorageClass::Output => {
+                if module.is_builtin(variable.id, pointee) {
+                    continue;
+                }
+                let Some(location) = module.decoration(variable.id, Decoration::Location) else {
+                    continue;
+                };
+                let target = if variable.storage_class == StorageClass::Input {
+                    &mut inputs
+                } else {
+                    &mut outputs
+                };
+                module.interface_variables(pointee, location, target)?;
+            }
+            _ => {}
+        }
+    }
+
+    let mut spec_constants = Vec::new();
+    for (result, result_type, default) in &module.spec_constants {
+        let Some(id) = module.decoration(*result, Decoration::SpecId) else {
+            continue;
+        };
+        let kind = match module.ty(*result_type)? {
+            Type::Bool => ScalarKind::Bool,
+            Type::Int { width: 32, signed } => {
+                if *signed {
+                    ScalarKind::Sint
+                } else {
+                    ScalarKind::Uint
+                }
+            }
+            Type::Float { width: 32 } => ScalarKind::Float,
+            other => anyhow::bail!(
+                "specialization constant {} has unsupported type {:?}",
+                id,
+                other
+            ),
+        };
+        spec_constants.push(SpecConstantInfo {
+            id,
+            name: module.names.get(result).cloned(),
+            kind,
+            default: *default,
+        });
+    }
+
+    descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+    spec_constants.sort_by_key(|c| c.id);
+    inputs.sort_by_key(|v: &InterfaceVariable| v.location);
+    outputs.sort_by_key(|v: &InterfaceVariable| v.location);
+
+    Ok(ShaderReflection {
+        stage,
+        entry_point,
+        descriptor_bindings,
+        push_constant_size,
+        inputs,
+        outputs,
+        spec_constants,
+    })
+}
+
+fn parse(words: &[u32]) -> anyhow::Result<Module> {
+    if words.len() < 5 || words[0] != spirv::MAGIC_NUMBER {
+        anyhow::bail!("not a SPIR-V module");
+    }
+
+    let mut module = Module::default();
+    let mut offset = 5;
+
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xffff;
+        if word_count == 0 || offset + word_count > words.len() {
+            anyhow::bail!("malformed SPIR-V instruction at word {offset}");
+        }
+        let operands = &words[offset + 1..offset + word_count];
+        offset += word_count;
+
+        let Some(op) = Op::from_u32(opcode) else {
+            continue;
+        };
+
+        match op {
+            Op::EntryPoint if module.entry_point.is_none() => {
+                let model =
+                    ExecutionModel::from_u32(operands[0]).context("unknown execution model")?;
+                module.entry_point = Some((model, literal_string(&operands[2..])));
+            }
+            Op::Name => {
+                module
+                    .names
+                    .insert(operands[0], literal_string(&operands[1..]));
+            }
+            Op::Decorate => {
+                let Some(decoration) = Decoration::from_u32(operands[1]) else {
+                    continue;
+                };
+                match operands.get(2) {
+                    Some(value) => {
+                        module.decorations.insert((operands[0], decoration), *value);
+                    }
+                    None => module
+                        .flags
+                        .entry(operands[0])
+                        .or_default()
+                        .push(decoration),
+                }
+            }
+            Op::MemberDecorate => {
+                let key = (operands[0], operands[1]);
+                match Decoration::from_u32(operands[2]) {
+                    Some(Decoration::Offset) => {
+                        module.member_offsets.insert(key, operands[3]);
+                    }
+                    Some(Decoration::MatrixStride) => {
+                        module.member_matrix_strides.insert(key, operands[3]);
+                    }
+                    Some(Decoration::BuiltIn) => {
+                        module.member_builtins.insert(operands[0], true);
+                    }
+                    _ => {}
+                }
+            }
+            Op::TypeVoid => {
+                module.types.insert(operands[0], Type::Void);
+            }
+            Op::TypeBool => {
+                module.types.insert(operands[0], Type::Bool);
+            }
+            Op::TypeInt => {
+                module.types.insert(
+                    operands[0],
+                    Type::Int {
+                        width: operands[1],
+                        signed: operands[2] != 0,
+                    },
+                );
+            }
+            Op::TypeFloat => {
+                module
+                    .types
+                    .insert(operands[0], Type::Float { width: operands[1] });
+            }
+            Op::TypeVector => {
+                module.types.insert(
+                    operands[0],
+                    Type::Vector {
+                        component: operands[1],
+                        count: operands[2],
+                    },
+                );
+            }
+            Op::TypeMatrix => {
+                module.types.insert(
+                    operands[0],
+                    Type::Matrix {
+                        column: operands[1],
+                        count: operands[2],
+                    },
+                );
+            }
+            Op::TypeImage => {
+                module.types.insert(
+                    operands[0],
+                    Type::Image {
+                        dim: Dim::from_u32(operands[2]).context("unknown image dimension")?,
+                        sampled: operands[6],
+                    },
+                );
+            }
+            Op::TypeSampler => {
+                module.types.insert(operands[0], Type::Sampler);
+            }
+            Op::TypeSampledImage => {
+                module
+                    .types
+                    .insert(operands[0], Type::SampledImage { image: operands[1] });
+            }
+            Op::TypeArray => {
+                let length = *module
+                    .constants
+                    .get(&operands[2])
+                    .context("array length is not a known constant")?;
+                module.types.insert(
+                    operands[0],
+                    Type::Array {
+                        element: operands[1],
+                        length,
+                    },
+                );
+            }
+            Op::TypeRuntimeArray => {
+                module.types.insert(
+                    operands[0],
+                    Type::RuntimeArray {
+                        element: operands[1],
+                    },
+                );
+            }
+            Op::TypeStruct => {
+                module.types.insert(
+                    operands[0],
+                    Type::Struct {
+                        members: operands[1..].to_vec(),
+                    },
+                );
+            }
+            Op::TypePointer => {
+                module.types.insert(
+                    operands[0],
+                    Type::Pointer {
+                        pointee: operands[2],
+                    },
+                );
+            }
+            Op::TypeAccelerationStructureKHR => {
+                module
+                    .types
+                    .insert(operands[0], Type::AccelerationStructure);
+            }
+            Op::Constant => {
+                module.constants.insert(operands[1], operands[2]);
+            }
+            Op::SpecConstant => {
+                module.constants.insert(operands[1], operands[2]);
+                module
+                    .spec_constants
+                    .push((operands[1], operands[0], operands[2]));
+            }
+            Op::SpecConstantTrue | Op::SpecConstantFalse => {
+                let default = (op == Op::SpecConstantTrue) as u32;
+                module
+                    .spec_constants
+                    .push((operands[1], operands[0], default));
+            }
+            Op::Variable => {
+                module.variables.push(Variable {
+                    id: operands[1],
+                    pointer_type: operands[0],
+                    storage_class: StorageClass::from_u32(operands[2])
+                        .context("unknown storage class")?,
+                });
+            }
+            Op::Function => break,
+            _ => {}
+        }
+    }
+
+    Ok(module)
+}
+
+impl Module {
+    fn decoration(&self, id: u32, decoration: Decoration) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    fn has_flag(&self, id: u32, decoration: Decoration) -> bool {
+        self.flags
+            .get(&id)
+            .is_some_and(|flags| flags.contains(&decoration))
+    }
+
+    fn ty(&self, id: u32) -> anyhow::Result<&Type> {
+        self.types
+            .get(&id)
+            .with_context(|| format!("unknown SPIR-V type %{id}"))
+    }
+
+    fn is_builtin(&self, variable: u32, pointee: u32) -> bool {
+        self.decorations
+            .contains_key(&(variable, Decoration::BuiltIn))
+            || self.member_builtins.contains_key(&pointee)
+    }
+
+    fn descriptor_binding(
+        &self,
+        variable: &Variable,
+        pointee: u32,
+    ) -> anyhow::Result<DescriptorBinding> {
+        let (base, count) = match self.ty(pointee)? {
+            Type::Array { element, length } => (*element, *length),
+            Type::RuntimeArray { element } => (*element, 0),
+            _ => (pointee, 1),
+        };
+
+        let descriptor_type = match (variable.storage_class, self.ty(base)?) {
+            (StorageClass::StorageBuffer, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (StorageClass::Uniform, _) if self.has_flag(base, Decoration::BufferBlock) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (StorageClass::Uniform, _) => vk::DescriptorType::UNIFORM_BUFFER,
+            (_, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (_, Type::SampledImage { image }) => match self.ty(*image)? {
+                Type::Image {
+                    dim: Dim::DimBuffer,
+                    ..
+                } => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                _ => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            },
+            (_, Type::Image { dim, sampled }) => match (dim, sampled) {
+                (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (Dim::DimBuffer, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (Dim::DimBuffer, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (_, Type::AccelerationStructure) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (_, other) => anyhow::bail!("unsupported descriptor type {:?}", other),
+        };
+
+        let set = self
+            .decoration(variable.id, Decoration::DescriptorSet)
+            .unwrap_or(0);
+        let binding = self
+            .decoration(variable.id, Decoration::Binding)
+            .with_context(|| format!("descriptor %{} has no binding", variable.id))?;
+
+        Ok(DescriptorBinding {
+            set,
+            binding,
+            descriptor_type,
+            count,
+            name: self.names.get(&variable.id).cloned(),
+        })
+    }
+
+    /// Size in bytes following explicit layout decorations.
+    fn size_of(&self, id: u32, matrix_stride: Option<u32>) -> anyhow::Result<u32> {
+        Ok(match self.ty(id)? {
+            Type::Void => 0,
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => self.size_of(*component, None)? * count,
+            Type::Matrix { column, count } => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.size_of(*column, None)?,
+                };
+                stride * count
+            }
+            Type::Array { element, length } => {
+                let stride = match self.decoration(id, Decoration::ArrayStride) {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, matrix_stride)?,
+                };
+                stride * length
+            }
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let key = (id, index as u32);
+                    let offset = self.member_offsets.get(&key).copied().unwrap_or(size);
+                    let member_size =
+                        self.size_of(*member, self.member_matrix_strides.get(&key).copied())?;
+                    size = size.max(offset + member_size);
+                }
+                size
+            }
+            Type::RuntimeArray { .. } => 0,
+            other => anyhow::bail!("type {:?} has no defined size", other),
+        })
+    }
+
+    fn interface_variables(
+        &self,
+        id: u32,
+        location: u32,
+        out: &mut Vec<InterfaceVariable>,
+    ) -> anyhow::Result<()> {
+        match self.ty(id)? {
+            Type::Array { element, length } => {
+                for i in 0..*length {
+                    self.interface_variables(*element, location + i, out)?;
+                }
+            }
+            Type::Matrix { column, count } => {
+                for i in 0..*count {
+                    self.interface_variables(*column, location + i, out)?;
+                }
+            }
+            Type::Vector { component, count } => {
+                let (kind, width) = self.scalar(*component)?;
+                out.push(InterfaceVariable {
+                    location,
+                    kind,
+                    width,
+                    components: *count,
+                });
+            }
+            _ => {
+                let (kind, width) = self.scalar(id)?;
+                out.push(InterfaceVariable {
+                    location,
+                    kind,
+                    width,
+                    components: 1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn scalar(&self, id: u32) -> anyhow::Result<(ScalarKind, u32)> {
+        Ok(match self.ty(id)? {
+            Type::Bool => (ScalarKind::Bool, 32),
+            Type::Int {
+                width,
+                signed: true,
+            } => (ScalarKind::Sint, *width),
+            Type::Int {
+                width,
+                signed: false,
+            } => (ScalarKind::Uint, *width),
+            Type::Float { width } => (ScalarKind::Float, *width),
+            other => anyhow::bail!("interface variable of type {:?} is not supported", other),
+        })
+    }
+}
+
+fn literal_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Numeric class a color attachment format is read and written as.
+pub fn format_kind(format: vk::Format) -> Option<ScalarKind> {
+    use vk::Format as F;
+    match format {
+        F::UNDEFINED => None,
+        F::R8_UINT
+        | F::R8G8_UINT
+        | F::R8G8B8A8_UINT
+        | F::R16_UINT
+        | F::R16G16_UINT
+        | F::R16G16B16A16_UINT
+        | F::R32_UINT
+        | F::R32G32_UINT
+        | F::R32G32B32A32_UINT
+        | F::A2B10G10R10_UINT_PACK32 => Some(ScalarKind::Uint),
+        F::R8_SINT
+        | F::R8G8_SINT
+        | F::R8G8B8A8_SINT
+        | F::R16_SINT
+        | F::R16G16_SINT
+        | F::R16G16B16A16_SINT
+        | F::R32_SINT
+        | F::R32G32_SINT
+        | F::R32G32B32A32_SINT => Some(ScalarKind::Sint),
+        _ => Some(ScalarKind::Float),
+    }
+}
@@ -0,0 +1,85 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::render::shader::ShaderId;
+
+/// A specialization constant by its `constant_id`. Values are raw 32-bit words, so floats go
+/// through `f32::to_bits` and booleans are 0 or 1.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct SpecializationConstant {
+    pub id: u32,
+    pub value: u32,
+}
+
+/// A shader plus the specialization constants that select one of its permutations. Constants
+/// are kept sorted so equal variants hash equally regardless of build order. None can be set
+/// yet: naga, which compiles every shader when glslc is missing, can't emit specialization
+/// constants.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ShaderVariant {
+    pub id: ShaderId,
+    constants: Vec<SpecializationConstant>,
+}
+
+impl From<ShaderId> for ShaderVariant {
+    fn from(id: ShaderId) -> Self {
+        Self::new(id)
+    }
+}
+
+impl ShaderVariant {
+    pub fn new(id: ShaderId) -> Self {
+        Self {
+            id,
+            constants: Vec::new(),
+        }
+    }
+
+    pub fn constants(&self) -> &[SpecializationConstant] {
+        &self.constants
+    }
+}
+
+/// Everything a pipeline stage needs to use a validated variant.
+#[derive(Clone)]
+pub struct SpecializedShader {
+    pub module: vk::ShaderModule,
+    pub entry_point: CString,
+    map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializedShader {
+    pub(super) fn new(
+        module: vk::ShaderModule,
+        entry_point: CString,
+        constants: &[SpecializationConstant],
+    ) -> Self {
+        let map_entries = constants
+            .iter()
+            .enumerate()
+            .map(|(i, constant)| vk::SpecializationMapEntry {
+                constant_id: constant.id,
+                offset: (i * size_of::<u32>()) as u32,
+                size: size_of::<u32>(),
+            })
+            .collect();
+        let data = constants
+            .iter()
+            .flat_map(|constant| constant.value.to_ne_bytes())
+            .collect();
+        Self {
+            module,
+            entry_point,
+            map_entries,
+            data,
+        }
+    }
+
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
+}
@@ -57,3 +57,42 @@ pub fn submit_frame(
 
     Ok(())
 }
+
+/// Records `record` into a fresh command buffer, submits it and waits for the queue to go
+/// idle. For one-off work outside the frame loop, such as uploads at startup.
+pub fn submit_once<T>(
+    device: &ash::Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    record: impl FnOnce(vk::CommandBuffer) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let alloc_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+    let cmd = unsafe { device.allocate_command_buffers(&alloc_info)?[0] };
+
+    let result = (|| {
+        unsafe {
+            device.begin_command_buffer(
+                cmd,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )?;
+        }
+        let value = record(cmd)?;
+        let command_buffers = [cmd];
+        let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        unsafe {
+            device.end_command_buffer(cmd)?;
+            device.queue_submit(queue, &[submit_info], vk::Fence::null())?;
+            device.queue_wait_idle(queue)?;
+        }
+        Ok(value)
+    })();
+
+    unsafe {
+        device.free_command_buffers(command_pool, &[cmd]);
+    }
+    result
+}
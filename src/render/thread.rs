@@ -1,4 +1,3 @@
-use core::alloc;
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -6,35 +5,82 @@ use ash::vk;
 #[cfg(feature = "tracing")]
 use tracy_client::frame_mark;
 use tracy_client::{Client, plot};
-use vk_mem::{Alloc, AllocatorCreateInfo};
+use vk_mem::AllocatorCreateInfo;
 
 use crate::{
-    buffer::BufferManager,
+    buffer::{
+        AllocationStrategy, BufferLifetime, BufferManager, BufferSpec, BufferType, BufferUsage,
+        LinearKey, OverflowPolicy,
+    },
     caps::RenderCaps,
     image::ImageManager,
+    leaks::LeakReport,
     messages::{EngineControl, ShutdownPhase},
     render::{
-        Frame, FrameRing,
-        framegraph::{CompositionPass, ForwardPass, FramegraphBuilder, ImageResolveContext},
-        pipeline::PipelineManager,
+        BindlessHeap, Frame, FrameRing,
+        framegraph::{
+            CompositionPass, CullingPass, DepthPyramidPass, ForwardPass, FrameGraphSet,
+            FramegraphBuilder, GraphRate, ImageAlias, ImageResolveContext, ImageState, InsetPass,
+        },
+        pipeline::{GraphicsPipelineDesc, PipelineCache, PipelineManager, RasterizationState},
         present::present_frame,
+        scene::{CubeGrid, MeshArenas},
+        shader::ShaderId,
         submit::submit_frame,
         swapchain::SwapchainContext,
     },
-    vulkan::SwapchainCreateCaps,
+    sampler::{SamplerManager, SamplerSpec},
+    vulkan::{DeviceContext, DeviceFeatures, SwapchainCreateCaps},
 };
 
 use super::render_packet::RenderData;
 
+const FRAMES_IN_FLIGHT: u32 = 3;
+
+/// Set to a frame count to have the app exit once that many frames were presented, for smoke
+/// runs in CI.
+const SMOKE_FRAMES_ENV: &str = "SKELETON_SMOKE_FRAMES";
+
+/// Set to a frame count to refresh the thumbnail inset that often. Unset, the thumbnail is taken
+/// once, from the first complete frame.
+const THUMBNAIL_INTERVAL_ENV: &str = "SKELETON_THUMBNAIL_INTERVAL";
+
+/// Set to a directory of `.spv` files to register each under its file name at startup. A file
+/// named after a built-in shader replaces it, so shaders can be swapped without a rebuild.
+const SHADER_DIR_ENV: &str = "SKELETON_SHADER_DIR";
+
+/// A scaled-down copy of the forward colour, rendered by its own graph at a fixed size and shown
+/// in a corner of the swapchain.
+const THUMBNAIL: ImageAlias = ImageAlias::Named("Thumbnail");
+const THUMBNAIL_EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 320,
+    height: 180,
+};
+
+/// Size of each frame's uniform block. Debug builds treat outgrowing it as a bug so it gets
+/// noticed; release builds grow the block instead of dropping the frame.
+const UNIFORM_DATA_SIZE: usize = 64 * 1024;
+const UNIFORM_OVERFLOW: OverflowPolicy = if cfg!(debug_assertions) {
+    OverflowPolicy::Fail
+} else {
+    OverflowPolicy::Grow
+};
+
+#[cfg(feature = "hot-reload")]
+const SHADER_SOURCE_DIR: &str = "assets";
+
 pub struct FrameExecutionContext<'a> {
     pub device: &'a ash::Device,
+    pub device_context: &'a DeviceContext,
+    pub allocator: &'a vk_mem::Allocator,
     pub frame: &'a mut Frame,
 
     pub image_manager: &'a ImageManager,
+    pub buffer_manager: &'a BufferManager,
     pub pipeline_manager: &'a PipelineManager,
-    pub swapchain_extent: vk::Extent2D,
-    pub viewport: vk::Viewport,
-    pub snizzor: vk::Rect2D,
+    pub bindless: Option<&'a BindlessHeap>,
+    pub uniform_data: LinearKey,
+    pub features: DeviceFeatures,
     pub render_data: &'a RenderData,
 }
 
@@ -69,18 +115,33 @@ pub fn render_thread(
         }
     };
 
-    let frames: Vec<Frame> = vec![
-        Frame::new(&caps.device_context, command_pool, 2, 0).context("failed to create frame")?,
-        Frame::new(&caps.device_context, command_pool, 2, 1).context("failed to create frame")?,
-        Frame::new(&caps.device_context, command_pool, 2, 2).context("failed to create frame")?,
-    ];
+    let frame_count = FRAMES_IN_FLIGHT;
 
-    let frame_count = frames.len() as u32;
+    let device_properties = unsafe {
+        caps.instance
+            .get_physical_device_properties(*caps.physical_device)
+    };
+    let mut sampler_manager = SamplerManager::new(device_properties.limits.max_sampler_anisotropy);
 
-    let mut frame_ring = FrameRing::new(frames);
+    let pipeline_cache =
+        PipelineCache::load(device, &device_properties, PipelineCache::default_dir()?)
+            .context("failed to load pipeline cache")?;
 
     let mut pipeline_manager =
-        PipelineManager::new(device).context("thread failed to create pipeline manager")?;
+        PipelineManager::new(caps.device_context.device.clone(), pipeline_cache)
+            .context("thread failed to create pipeline manager")?;
+
+    if let Some(dir) = std::env::var_os(SHADER_DIR_ENV) {
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("failed to read {SHADER_DIR_ENV} {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "spv") {
+                let id = pipeline_manager.register_shader_path(path)?;
+                log::info!("Registered shader {id} from {SHADER_DIR_ENV}");
+            }
+        }
+    }
 
     let resolve_alias = |_alias| -> vk::Extent2D { vk::Extent2D::default() };
 
@@ -100,43 +161,219 @@ pub fn render_thread(
         .register_external_per_frame(&swapchain_context.images, &swapchain_context.image_views);
 
     let mut aci = AllocatorCreateInfo::new(&caps.instance, device, *caps.physical_device);
-    aci.flags = vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+    if caps.device_context.features.buffer_device_address {
+        aci.flags = vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+    }
 
     let allocator = unsafe { vk_mem::Allocator::new(aci).context("failed to create allocator")? };
 
-    let buffer_info = vk::BufferCreateInfo::default()
-        .size(1024)
-        .usage(
-            vk::BufferUsageFlags::RESOURCE_DESCRIPTOR_BUFFER_EXT
-                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-        )
-        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let bindless = BindlessHeap::new(
+        &caps.instance,
+        *caps.physical_device,
+        &caps.device_context,
+        &allocator,
+    )
+    .context("failed to create bindless heap")?;
 
-    let alloc_info = vk_mem::AllocationCreateInfo {
-        usage: vk_mem::MemoryUsage::Auto,
-        flags: vk_mem::AllocationCreateFlags::MAPPED
-            | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+    if let Some(heap) = &bindless {
+        pipeline_manager.set_bindless_layout(heap.layout().clone());
+        image_manager.set_bindless(heap.clone());
+        buffer_manager.set_bindless(heap.clone());
+        sampler_manager.set_bindless(heap.clone());
+    }
 
-        ..Default::default()
+    let uniform_data = buffer_manager
+        .create_linear(
+            &allocator,
+            &caps.device_context,
+            BufferSpec::default()
+                .allocation_strategy(AllocationStrategy::Linear)
+                .lifetime(BufferLifetime::PerFrame)
+                .buffer_type(BufferType::HostTransient)
+                .usage(BufferUsage::UNIFORM)
+                .size(UNIFORM_DATA_SIZE)
+                .debug_name("UniformData"),
+            UNIFORM_OVERFLOW,
+            device_properties.limits.min_uniform_buffer_offset_alignment,
+            frame_count,
+        )
+        .context("failed to create uniform linear allocator")?;
+
+    // Culling and vertex pulling reach every buffer through the bindless heap.
+    let mesh_arenas = bindless
+        .is_some()
+        .then(|| MeshArenas::new(&mut buffer_manager, &allocator, &caps.device_context))
+        .transpose()
+        .context("failed to create the mesh arenas")?;
+    let scene = mesh_arenas
+        .as_ref()
+        .map(|arenas| {
+            CubeGrid::new(
+                &mut buffer_manager,
+                arenas,
+                &allocator,
+                &caps.device_context,
+                caps.queue,
+                command_pool,
+                frame_count,
+            )
+        })
+        .transpose()
+        .context("failed to create the cube grid")?;
+
+    // Forward colour is shown through the bindless heap when there is one.
+    let composition_sampler = bindless
+        .is_some()
+        .then(|| {
+            sampler_manager.get_or_create(
+                &caps.device_context,
+                SamplerSpec::default()
+                    .filter(vk::Filter::LINEAR, vk::Filter::LINEAR)
+                    .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                    .lod_range(0.0, 0.0),
+            )
+        })
+        .transpose()?;
+    let composition_sampler_index = composition_sampler
+        .map(|sampler| {
+            sampler_manager
+                .sampler(sampler)
+                .bindless
+                .context("composition sampler is not in the bindless heap")
+        })
+        .transpose()?;
+    let composition = match composition_sampler_index {
+        Some(sampler) => CompositionPass::default().sampled(sampler),
+        None => CompositionPass::default(),
     };
 
-    let (buffer, mut allocation) = unsafe { allocator.create_buffer(&buffer_info, &alloc_info)? };
+    let thumbnail_rate = std::env::var(THUMBNAIL_INTERVAL_ENV)
+        .ok()
+        .map(|frames| frames.parse::<u64>())
+        .transpose()
+        .with_context(|| format!("{THUMBNAIL_INTERVAL_ENV} is not a frame count"))?
+        .map_or(GraphRate::OnDemand, GraphRate::Interval);
 
-    unsafe {
-        allocator.destroy_buffer(buffer, &mut allocation);
+    let mut framegraphs = FrameGraphSet::default();
+
+    let swapchain_formats = [swapchain_context.swapchain_format];
+    // The scene renders into ForwardColor and hands it to the main graph for composition.
+    let scene_graph = FramegraphBuilder::new(
+        &mut image_manager,
+        &allocator,
+        caps.device_context.clone(),
+        &swapchain_formats,
+        vk::Format::D32_SFLOAT, // TODO: policy-ize
+        &mut pipeline_manager,
+    )
+    .name("scene");
+    let scene_graph = match &scene {
+        Some(scene) => scene_graph
+            .add_pass(
+                CullingPass::new(scene.culling_buffers())
+                    .readback(scene.visible_count_readback())
+                    .occlusion(scene.depth_pyramid()),
+            )
+            .add_pass(
+                ForwardPass::default().indirect(
+                    scene.indirect_draws(&buffer_manager),
+                    // The cubes' faces wind counter-clockwise seen from outside.
+                    GraphicsPipelineDesc::new(
+                        ShaderId::FORWARD_INDIRECT_VERT,
+                        ShaderId::FORWARD_FRAG,
+                    )
+                    .rasterization(
+                        RasterizationState::default().cull_mode(vk::CullModeFlags::BACK),
+                    ),
+                ),
+            )
+            .add_pass(DepthPyramidPass::new(scene.depth_pyramid())),
+        None => scene_graph.add_pass(ForwardPass::default()),
     }
+    .export_image(ImageAlias::ForwardColor, ImageState::SHADER_READ)
+    .build(&image_ctx)?;
+
+    // Only worth having where the forward colour can be sampled.
+    let thumbnail_graph = match composition_sampler_index {
+        Some(sampler) => Some(
+            FramegraphBuilder::new(
+                &mut image_manager,
+                &allocator,
+                caps.device_context.clone(),
+                &swapchain_formats,
+                vk::Format::D32_SFLOAT, // TODO: policy-ize
+                &mut pipeline_manager,
+            )
+            .name("thumbnail")
+            .extent(THUMBNAIL_EXTENT)
+            .import_image(
+                ImageAlias::ForwardColor,
+                scene_graph.export(ImageAlias::ForwardColor)?,
+            )
+            .add_pass(
+                CompositionPass::default()
+                    .sampled(sampler)
+                    .into_image(THUMBNAIL),
+            )
+            .export_image(THUMBNAIL, ImageState::SHADER_READ)
+            .build(&image_ctx)?,
+        ),
+        None => None,
+    };
 
-    let mut framegraph = FramegraphBuilder::new(
+    let main_graph = FramegraphBuilder::new(
         &mut image_manager,
         &allocator,
         caps.device_context.clone(),
-        &[swapchain_context.swapchain_format],
+        &swapchain_formats,
         vk::Format::D32_SFLOAT, // TODO: policy-ize
         &mut pipeline_manager,
     )
-    .add_pass(ForwardPass::default())
-    .add_pass(CompositionPass::default())
-    .build(&image_ctx, swapchain_keys)?;
+    .import_image(
+        ImageAlias::ForwardColor,
+        scene_graph.export(ImageAlias::ForwardColor)?,
+    )
+    .add_pass(composition)
+    .swapchain(swapchain_keys);
+    let main_graph = match (&thumbnail_graph, composition_sampler_index) {
+        (Some(thumbnail_graph), Some(sampler)) => main_graph
+            .import_image(THUMBNAIL, thumbnail_graph.export(THUMBNAIL)?)
+            .add_pass(InsetPass::new(THUMBNAIL, sampler, THUMBNAIL_EXTENT)),
+        _ => main_graph,
+    }
+    .build(&image_ctx)?;
+
+    framegraphs.add(scene_graph, GraphRate::EveryFrame)?;
+    if let Some(thumbnail_graph) = thumbnail_graph {
+        // Taken as soon as the scene is complete, then at `thumbnail_rate`.
+        let thumbnail = framegraphs.add(thumbnail_graph, thumbnail_rate)?;
+        framegraphs.request(thumbnail)?;
+    }
+    framegraphs.add(main_graph, GraphRate::EveryFrame)?;
+
+    let frames = (0..frame_count as usize)
+        .map(|index| {
+            Frame::new(
+                &caps.device_context,
+                command_pool,
+                framegraphs.pass_count(),
+                index,
+            )
+            .context("failed to create frame")
+        })
+        .collect::<anyhow::Result<Vec<Frame>>>()?;
+
+    let mut frame_ring = FrameRing::new(frames);
+
+    #[cfg(feature = "hot-reload")]
+    let shader_watcher = crate::render::hot_reload::ShaderWatcher::new(SHADER_SOURCE_DIR)
+        .context("failed to start shader watcher")?;
+
+    let smoke_frames = std::env::var(SMOKE_FRAMES_ENV)
+        .ok()
+        .map(|frames| frames.parse::<u64>())
+        .transpose()
+        .with_context(|| format!("{SMOKE_FRAMES_ENV} is not a frame count"))?;
 
     let exec_resources = FrameExecutionResources {
         frame_ring: &mut frame_ring,
@@ -145,6 +382,8 @@ pub fn render_thread(
 
     while control.phase() != ShutdownPhase::StopRender {
         let frame = exec_resources.frame_ring.acquire(device)?;
+        // The frame's fence has signalled, so its transient allocations are free to reuse.
+        buffer_manager.reset_linear(&allocator, frame.index);
 
         let (image_index, _) = exec_resources
             .swapchain_context
@@ -164,31 +403,53 @@ pub fn render_thread(
 
         frame.swapchain_image_index = image_index;
 
-        let render_data = gather_mock_render_data();
+        #[cfg(feature = "hot-reload")]
+        {
+            for reload in shader_watcher.poll() {
+                if let Err(e) = pipeline_manager.reload_shader(
+                    &caps.device_context,
+                    reload.id,
+                    &reload.spirv,
+                    frame.number,
+                ) {
+                    log::error!("Shader reload failed: {e:#}");
+                }
+            }
+        }
+        pipeline_manager.collect_compiled(&caps.device_context);
+        pipeline_manager.collect_retired(device, frame.number, frame_count as u64);
+        buffer_manager.collect_retired(&allocator, frame.number, frame_count as u64);
+        image_manager.collect_retired(device, &allocator, frame.number, frame_count as u64);
 
-        let extent = exec_resources.swapchain_context.swapchain_extent;
-        let viewport = vk::Viewport {
-            height: extent.height as f32,
-            width: extent.width as f32,
-            ..Default::default()
-        };
-        let snizzor = vk::Rect2D {
-            offset: vk::Offset2D::default(),
-            extent,
-        };
+        if let Some(arenas) = &mesh_arenas {
+            arenas.plot_stats(&buffer_manager);
+        }
+        if let Some(scene) = &scene {
+            let visible = scene.visible_count(&buffer_manager, &allocator, frame.index)?;
+            plot!("visible draws", visible as f64);
+        }
+
+        let render_data = gather_mock_render_data(
+            scene.as_ref(),
+            frame.number,
+            exec_resources.swapchain_context.swapchain_extent,
+        );
 
         let fg_ctx = FrameExecutionContext {
             device,
+            device_context: &caps.device_context,
+            allocator: &allocator,
             frame,
             image_manager: &image_manager,
+            buffer_manager: &buffer_manager,
             pipeline_manager: &pipeline_manager,
-            swapchain_extent: extent,
-            viewport,
-            snizzor,
+            bindless: bindless.as_deref(),
+            uniform_data,
+            features: caps.device_context.features,
             render_data: &render_data,
         };
 
-        framegraph.execute(&fg_ctx)?;
+        framegraphs.execute(&fg_ctx)?;
 
         let cmd = create_single_use_command_buffer(device, command_pool)?;
 
@@ -204,6 +465,11 @@ pub fn render_thread(
         present_frame(caps.present_queue, frame, exec_resources.swapchain_context)
             .context("failed to present frame")?;
 
+        if smoke_frames.is_some_and(|frames| frame.number + 1 == frames) {
+            log::info!("Presented {} frames; requesting exit", frame.number + 1);
+            control.request_exit();
+        }
+
         #[cfg(feature = "tracing")]
         frame_mark();
     }
@@ -216,20 +482,56 @@ pub fn render_thread(
     }
     frame_ring.destroy(device);
 
+    framegraphs
+        .destroy(&mut pipeline_manager, &mut image_manager)
+        .context("failed to destroy framegraphs")?;
+    if let Some(scene) = scene {
+        scene.destroy(&mut buffer_manager);
+    }
+    if let Some(arenas) = mesh_arenas {
+        arenas.destroy(&mut buffer_manager);
+    }
+    buffer_manager.destroy_linear(uniform_data);
+    if let Some(sampler) = composition_sampler {
+        sampler_manager.release(sampler);
+    }
+
+    // Everything the framegraphs held is released; whatever is left was never destroyed.
+    let mut leaks = LeakReport::default();
+    leaks.extend(pipeline_manager.leaks());
+    leaks.extend(image_manager.leaks(&allocator));
+    leaks.extend(buffer_manager.leaks());
+    leaks.extend(sampler_manager.leaks());
+
     pipeline_manager
         .destroy(device)
         .context("failed to destroy pipeline manager")?;
 
-    image_manager.cleanup_per_frames(device, &allocator)?;
+    image_manager.destroy(device, &allocator)?;
+    buffer_manager.destroy_all(&allocator)?;
+    sampler_manager.destroy(device);
+    if let Some(heap) = &bindless {
+        leaks.extend(heap.leaks());
+        heap.destroy(&allocator);
+    }
     drop(allocator);
     swapchain_context.destroy();
 
+    leaks.finish()?;
+
     log::debug!("Render thread shutting down");
     Ok(())
 }
 
-fn gather_mock_render_data() -> RenderData {
-    RenderData { _id: 5 }
+fn gather_mock_render_data(
+    scene: Option<&CubeGrid>,
+    frame_number: u64,
+    extent: vk::Extent2D,
+) -> RenderData {
+    RenderData {
+        _id: 5,
+        cull: scene.map(|scene| scene.cull_view(frame_number, extent)),
+    }
 }
 
 pub fn create_single_use_command_buffer(
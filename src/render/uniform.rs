@@ -0,0 +1,70 @@
+use anyhow::Context;
+use ash::vk;
+
+use crate::vulkan::DeviceContext;
+
+const UNIFORM_MAX_SETS: u32 = 256;
+
+/// Descriptor sets for one frame in flight's per-pass uniforms. The uniform data itself comes
+/// from the renderer's linear allocator. Sets are allocated while recording and reset once the
+/// frame's fence has signalled.
+pub struct UniformSets {
+    descriptor_pool: vk::DescriptorPool,
+}
+
+impl UniformSets {
+    pub fn new(device_context: &DeviceContext, index: usize) -> anyhow::Result<Self> {
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::UNIFORM_BUFFER,
+            descriptor_count: UNIFORM_MAX_SETS,
+        }];
+        let descriptor_pool = unsafe {
+            device_context.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(UNIFORM_MAX_SETS)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )?
+        };
+        device_context.name_object(
+            descriptor_pool,
+            format!("UniformDescriptorPool(Frame {:?})", index),
+        )?;
+
+        Ok(Self { descriptor_pool })
+    }
+
+    /// Must only be called after the owning frame's fence has signalled.
+    pub fn reset(&self, device: &ash::Device) -> anyhow::Result<()> {
+        unsafe {
+            device
+                .reset_descriptor_pool(self.descriptor_pool, vk::DescriptorPoolResetFlags::empty())
+                .context("failed to reset uniform descriptor pool")
+        }
+    }
+
+    pub fn allocate_set(
+        &self,
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> anyhow::Result<vk::DescriptorSet> {
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::default()
+                        .descriptor_pool(self.descriptor_pool)
+                        .set_layouts(std::slice::from_ref(&layout)),
+                )
+                .context("uniform descriptor pool exhausted")?
+        };
+        sets.into_iter()
+            .next()
+            .context("no descriptor set allocated")
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+    }
+}
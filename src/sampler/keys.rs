@@ -0,0 +1,3 @@
+use slotmap::new_key_type;
+
+new_key_type! { pub struct SamplerKey; }
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use slotmap::SlotMap;
+
+use crate::{
+    leaks::Leak,
+    render::BindlessHeap,
+    sampler::{keys::SamplerKey, resource::Sampler, spec::SamplerSpec},
+    vulkan::DeviceContext,
+};
+
+/// Creates each distinct sampler once. Samplers are few and cheap to keep, so they live until
+/// `destroy` even once released; releasing only marks them as no longer in use.
+pub struct SamplerManager {
+    samplers: SlotMap<SamplerKey, Sampler>,
+    lookup: HashMap<SamplerSpec, SamplerKey>,
+    max_anisotropy: f32,
+    bindless: Option<Arc<BindlessHeap>>,
+}
+
+impl SamplerManager {
+    /// `max_anisotropy` is the device's `maxSamplerAnisotropy` limit.
+    pub fn new(max_anisotropy: f32) -> Self {
+        Self {
+            samplers: SlotMap::default(),
+            lookup: HashMap::default(),
+            max_anisotropy,
+            bindless: None,
+        }
+    }
+
+    /// Samplers created from here on are registered in `heap`.
+    pub fn set_bindless(&mut self, heap: Arc<BindlessHeap>) {
+        self.bindless = Some(heap);
+    }
+
+    #[inline]
+    pub fn sampler(&self, key: SamplerKey) -> &Sampler {
+        self.samplers.get(key).expect("invalid SamplerKey")
+    }
+
+    /// Every call takes a reference that must be given back with `release`.
+    pub fn get_or_create(
+        &mut self,
+        device_context: &DeviceContext,
+        spec: SamplerSpec,
+    ) -> anyhow::Result<SamplerKey> {
+        // Clamp first so requests above the limit share the sampler at the limit.
+        let spec = self.clamp(spec);
+        if let Some(&key) = self.lookup.get(&spec) {
+            self.samplers[key].ref_count += 1;
+            return Ok(key);
+        }
+
+        if spec.min_lod > spec.max_lod {
+            anyhow::bail!("{spec} has min_lod above max_lod");
+        }
+
+        let vk_sampler = unsafe {
+            device_context
+                .device
+                .create_sampler(&spec.to_vk(), None)
+                .with_context(|| format!("failed to create sampler for {spec}"))?
+        };
+        device_context.name_object(vk_sampler, format!("Sampler({})", self.samplers.len()))?;
+
+        let bindless = match &self.bindless {
+            Some(heap) => Some(
+                heap.register_sampler(vk_sampler)
+                    .context("failed to register sampler in the bindless heap")?,
+            ),
+            None => None,
+        };
+
+        let key = self.samplers.insert(Sampler {
+            vk_sampler,
+            spec,
+            bindless,
+            ref_count: 1,
+        });
+        self.lookup.insert(spec, key);
+        Ok(key)
+    }
+
+    fn clamp(&self, mut spec: SamplerSpec) -> SamplerSpec {
+        if let Some(anisotropy) = spec.max_anisotropy {
+            let clamped = anisotropy.clamp(1.0, self.max_anisotropy);
+            if clamped != anisotropy {
+                log::debug!(
+                    "Sampler anisotropy {} clamped to {} (device limit {})",
+                    anisotropy,
+                    clamped,
+                    self.max_anisotropy
+                );
+            }
+            spec.max_anisotropy = Some(clamped);
+        }
+        spec
+    }
+
+    pub fn release(&mut self, key: SamplerKey) {
+        let sampler = self.samplers.get_mut(key).expect("invalid SamplerKey");
+        sampler.ref_count = sampler.ref_count.saturating_sub(1);
+    }
+
+    /// Samplers still referenced. Released ones stay cached until `destroy` and are not leaks.
+    pub fn leaks(&self) -> Vec<Leak> {
+        self.samplers
+            .values()
+            .filter(|sampler| sampler.ref_count > 0)
+            .map(|sampler| Leak {
+                kind: "sampler",
+                description: format!("{}, {} reference(s)", sampler.spec, sampler.ref_count),
+                bytes: None,
+            })
+            .collect()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.lookup.clear();
+        for (_, sampler) in self.samplers.drain() {
+            if let (Some(heap), Some(index)) = (&self.bindless, sampler.bindless) {
+                heap.release_sampler(index);
+            }
+            unsafe { device.destroy_sampler(sampler.vk_sampler, None) };
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod keys;
+mod manager;
+mod resource;
+mod spec;
+
+pub use manager::SamplerManager;
+pub use spec::SamplerSpec;
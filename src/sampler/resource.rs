@@ -0,0 +1,13 @@
+use ash::vk;
+
+use crate::{render::SamplerIndex, sampler::spec::SamplerSpec};
+
+pub struct Sampler {
+    pub vk_sampler: vk::Sampler,
+    /// The spec as created, with anisotropy already clamped to the device limit.
+    pub spec: SamplerSpec,
+    /// Slot in the bindless heap, if one was set when the sampler was created.
+    pub bindless: Option<SamplerIndex>,
+    /// `get_or_create` calls not yet matched by a `release`.
+    pub(super) ref_count: u32,
+}
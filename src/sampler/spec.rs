@@ -0,0 +1,149 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use ash::vk;
+
+/// Everything a `vk::Sampler` is created from. Two equal specs share one sampler.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerSpec {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    /// Clamped to the device's `maxSamplerAnisotropy` when the sampler is created.
+    pub max_anisotropy: Option<f32>,
+    /// Turns the sampler into a comparison (shadow) sampler.
+    pub compare_op: Option<vk::CompareOp>,
+    pub mip_lod_bias: f32,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    /// Only read with `CLAMP_TO_BORDER` address modes.
+    pub border_color: vk::BorderColor,
+}
+
+impl Default for SamplerSpec {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+            compare_op: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        }
+    }
+}
+
+impl SamplerSpec {
+    pub fn filter(mut self, mag: vk::Filter, min: vk::Filter) -> Self {
+        self.mag_filter = mag;
+        self.min_filter = min;
+        self
+    }
+
+    /// Same mode on all three axes.
+    pub fn address_mode(self, mode: vk::SamplerAddressMode) -> Self {
+        self.address_modes(mode, mode, mode)
+    }
+
+    pub fn address_modes(
+        mut self,
+        u: vk::SamplerAddressMode,
+        v: vk::SamplerAddressMode,
+        w: vk::SamplerAddressMode,
+    ) -> Self {
+        self.address_mode_u = u;
+        self.address_mode_v = v;
+        self.address_mode_w = w;
+        self
+    }
+
+    pub fn lod_range(mut self, min: f32, max: f32) -> Self {
+        self.min_lod = min;
+        self.max_lod = max;
+        self
+    }
+
+    pub fn to_vk(self) -> vk::SamplerCreateInfo<'static> {
+        vk::SamplerCreateInfo::default()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode_u)
+            .address_mode_v(self.address_mode_v)
+            .address_mode_w(self.address_mode_w)
+            .anisotropy_enable(self.max_anisotropy.is_some())
+            .max_anisotropy(self.max_anisotropy.unwrap_or(1.0))
+            .compare_enable(self.compare_op.is_some())
+            .compare_op(self.compare_op.unwrap_or(vk::CompareOp::NEVER))
+            .mip_lod_bias(self.mip_lod_bias)
+            .min_lod(self.min_lod)
+            .max_lod(self.max_lod)
+            .border_color(self.border_color)
+    }
+
+    /// Floats compared by bit pattern, so specs can key a hash map.
+    fn key(&self) -> impl Eq + Hash {
+        (
+            (self.mag_filter, self.min_filter, self.mipmap_mode),
+            (
+                self.address_mode_u,
+                self.address_mode_v,
+                self.address_mode_w,
+            ),
+            self.max_anisotropy.map(f32::to_bits),
+            self.compare_op,
+            (
+                self.mip_lod_bias.to_bits(),
+                self.min_lod.to_bits(),
+                self.max_lod.to_bits(),
+            ),
+            self.border_color,
+        )
+    }
+}
+
+impl PartialEq for SamplerSpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerSpec {}
+
+impl Hash for SamplerSpec {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl fmt::Display for SamplerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SamplerSpec(filter={:?}/{:?}, mipmap={:?}, address={:?}/{:?}/{:?}, anisotropy={:?}, compare={:?}, lod={}..{} bias {}, border={:?})",
+            self.mag_filter,
+            self.min_filter,
+            self.mipmap_mode,
+            self.address_mode_u,
+            self.address_mode_v,
+            self.address_mode_w,
+            self.max_anisotropy,
+            self.compare_op,
+            self.min_lod,
+            self.max_lod,
+            self.mip_lod_bias,
+            self.border_color
+        )
+    }
+}
@@ -52,7 +52,7 @@ impl VulkanContext {
             pick_physical_device(&instance, &surface_instance, surface_khr)
                 .context("failed to select a physical device")?;
 
-        let (device, graphics_queue, present_queue) =
+        let (device, features, graphics_queue, present_queue) =
             create_logical_device(&instance, physical_device, queue_families_indices)
                 .context("failed to create a logical device and/or queues")?;
 
@@ -71,6 +71,7 @@ impl VulkanContext {
                 device,
                 debug_instance: debug_instance.map(Arc::new),
                 debug_utils: Some(debug_utils),
+                features,
             },
         })
     }
@@ -3,7 +3,7 @@ use std::{ffi::CStr, sync::Arc};
 use anyhow::Context;
 use ash::vk;
 
-use super::physical::QueueFamiliesIndices;
+use super::{features::DeviceFeatures, physical::QueueFamiliesIndices};
 
 fn get_required_device_extensions() -> [&'static CStr; 1] {
     [ash::khr::swapchain::NAME]
@@ -13,7 +13,12 @@ pub fn create_logical_device(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
     queue_families_indices: QueueFamiliesIndices,
-) -> anyhow::Result<(Arc<ash::Device>, ash::vk::Queue, ash::vk::Queue)> {
+) -> anyhow::Result<(
+    Arc<ash::Device>,
+    DeviceFeatures,
+    ash::vk::Queue,
+    ash::vk::Queue,
+)> {
     let graphics_family_index = queue_families_indices.graphics_index;
     let present_family_index = queue_families_indices.present_index;
     let queue_priorities = [1.0f32];
@@ -32,22 +37,32 @@ pub fn create_logical_device(
             .collect::<Vec<_>>()
     };
 
-    let device_extensions = get_required_device_extensions();
-    let device_extensions_ptrs = device_extensions
-        .iter()
+    let features = DeviceFeatures::query(instance, physical_device);
+    log::debug!("Device features: {:?}", features);
+
+    let device_extensions_ptrs = get_required_device_extensions()
+        .into_iter()
+        .chain(features.extensions())
         .map(|ext| ext.as_ptr())
         .collect::<Vec<_>>();
 
-    let device_features = vk::PhysicalDeviceFeatures::default().sampler_anisotropy(true);
+    let device_features = features.vulkan10().sampler_anisotropy(true);
     let mut features13 = vk::PhysicalDeviceVulkan13Features::default()
         .synchronization2(true)
         .dynamic_rendering(true);
+    let mut features12 = features.vulkan12();
+    let mut descriptor_buffer =
+        vk::PhysicalDeviceDescriptorBufferFeaturesEXT::default().descriptor_buffer(true);
 
-    let device_create_info = vk::DeviceCreateInfo::default()
+    let mut device_create_info = vk::DeviceCreateInfo::default()
         .queue_create_infos(&queue_create_infos)
         .enabled_extension_names(&device_extensions_ptrs)
         .enabled_features(&device_features)
-        .push_next(&mut features13);
+        .push_next(&mut features13)
+        .push_next(&mut features12);
+    if features.descriptor_buffer {
+        device_create_info = device_create_info.push_next(&mut descriptor_buffer);
+    }
 
     let device = Arc::new(unsafe {
         instance
@@ -59,5 +74,5 @@ pub fn create_logical_device(
 
     log::trace!("Created logical device");
 
-    Ok((device, graphics_queue, present_queue))
+    Ok((device, features, graphics_queue, present_queue))
 }
@@ -2,11 +2,14 @@ use std::{ffi::CString, sync::Arc};
 
 use ash::vk::{self};
 
+use crate::vulkan::DeviceFeatures;
+
 #[derive(Clone)]
 pub struct DeviceContext {
     pub device: Arc<ash::Device>,
     pub debug_instance: Option<Arc<ash::ext::debug_utils::Instance>>,
     pub debug_utils: Option<Arc<ash::ext::debug_utils::Device>>,
+    pub features: DeviceFeatures,
 }
 
 impl DeviceContext {
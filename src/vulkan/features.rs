@@ -0,0 +1,108 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+/// Optional device capabilities. Each flag is only set when the device supports it and it
+/// was enabled on the logical device, so code can branch on it directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceFeatures {
+    pub buffer_device_address: bool,
+    /// `VK_EXT_descriptor_buffer`.
+    pub descriptor_buffer: bool,
+    /// Unsized descriptor arrays in shaders.
+    pub runtime_descriptor_array: bool,
+    /// `nonuniformEXT` indexing of sampled image and storage buffer arrays.
+    pub non_uniform_indexing: bool,
+    /// Partially bound, update-after-bind sampler, sampled image and storage buffer arrays,
+    /// which may be written while unused slots are in use by the GPU.
+    pub descriptor_indexing: bool,
+    /// More than one draw per `vkCmdDrawIndexedIndirect`.
+    pub multi_draw_indirect: bool,
+    /// `vkCmdDrawIndexedIndirectCount`, with the draw count read from a buffer.
+    pub draw_indirect_count: bool,
+}
+
+/// Set to any value to leave `VK_EXT_descriptor_buffer` disabled even where it is supported,
+/// so the descriptor indexing path can be exercised on any device.
+const DISABLE_DESCRIPTOR_BUFFER_ENV: &str = "SKELETON_DISABLE_DESCRIPTOR_BUFFER";
+
+impl DeviceFeatures {
+    /// Queries what `physical_device` supports among the optional features.
+    pub fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let descriptor_buffer_disabled = std::env::var_os(DISABLE_DESCRIPTOR_BUFFER_ENV).is_some();
+        if descriptor_buffer_disabled {
+            log::info!("{DISABLE_DESCRIPTOR_BUFFER_ENV} is set; not enabling descriptor buffers");
+        }
+        let has_descriptor_buffer_ext = !descriptor_buffer_disabled
+            && has_extension(instance, physical_device, ash::ext::descriptor_buffer::NAME);
+
+        let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut descriptor_buffer = vk::PhysicalDeviceDescriptorBufferFeaturesEXT::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut features12);
+        if has_descriptor_buffer_ext {
+            features2 = features2.push_next(&mut descriptor_buffer);
+        }
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+        let multi_draw_indirect = features2.features.multi_draw_indirect == vk::TRUE;
+        let buffer_device_address = features12.buffer_device_address == vk::TRUE;
+        Self {
+            buffer_device_address,
+            // Descriptor buffers are addressed by device address, so they need both.
+            descriptor_buffer: buffer_device_address
+                && descriptor_buffer.descriptor_buffer == vk::TRUE,
+            runtime_descriptor_array: features12.runtime_descriptor_array == vk::TRUE,
+            non_uniform_indexing: features12.shader_sampled_image_array_non_uniform_indexing
+                == vk::TRUE
+                && features12.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE,
+            descriptor_indexing: features12.descriptor_binding_partially_bound == vk::TRUE
+                && features12.descriptor_binding_update_unused_while_pending == vk::TRUE
+                && features12.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+                && features12.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE,
+            multi_draw_indirect,
+            draw_indirect_count: features12.draw_indirect_count == vk::TRUE,
+        }
+    }
+
+    /// Extensions to enable on top of the required ones.
+    pub fn extensions(&self) -> Vec<&'static CStr> {
+        let mut extensions = Vec::new();
+        if self.descriptor_buffer {
+            extensions.push(ash::ext::descriptor_buffer::NAME);
+        }
+        extensions
+    }
+
+    pub fn vulkan10(&self) -> vk::PhysicalDeviceFeatures {
+        vk::PhysicalDeviceFeatures::default().multi_draw_indirect(self.multi_draw_indirect)
+    }
+
+    pub fn vulkan12(&self) -> vk::PhysicalDeviceVulkan12Features<'static> {
+        vk::PhysicalDeviceVulkan12Features::default()
+            .buffer_device_address(self.buffer_device_address)
+            .runtime_descriptor_array(self.runtime_descriptor_array)
+            .shader_sampled_image_array_non_uniform_indexing(self.non_uniform_indexing)
+            .shader_storage_buffer_array_non_uniform_indexing(self.non_uniform_indexing)
+            .descriptor_binding_partially_bound(self.descriptor_indexing)
+            .descriptor_binding_update_unused_while_pending(self.descriptor_indexing)
+            .descriptor_binding_sampled_image_update_after_bind(self.descriptor_indexing)
+            .descriptor_binding_storage_buffer_update_after_bind(self.descriptor_indexing)
+            .draw_indirect_count(self.draw_indirect_count)
+    }
+}
+
+fn has_extension(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> bool {
+    let Ok(extensions) =
+        (unsafe { instance.enumerate_device_extension_properties(physical_device) })
+    else {
+        return false;
+    };
+    extensions.iter().any(|ext| {
+        ext.extension_name_as_c_str()
+            .is_ok_and(|ext_name| ext_name == name)
+    })
+}
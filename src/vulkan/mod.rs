@@ -2,6 +2,7 @@ mod context;
 mod debug;
 mod device;
 mod device_context;
+mod features;
 mod instance;
 mod physical;
 mod surface;
@@ -13,3 +14,5 @@ pub use context::VulkanContext;
 pub use surface::{SurfaceSupportDetails, SwapchainProperties};
 
 pub use device_context::DeviceContext;
+
+pub use features::DeviceFeatures;